@@ -0,0 +1,39 @@
+//! Serves a writable overlay of two local directories over NFS.
+//!
+//! ```sh
+//! cargo run --example local_overlay -- /data/lower /data/upper
+//! mount -t nfs -o nolocks,vers=3,tcp,port=12000,mountport=12000,soft 127.0.0.1:/ ../mnt/
+//! ```
+
+use std::sync::Arc;
+
+use log::info;
+use nfsserve::tcp::{NFSTcp, NFSTcpListener};
+use opendal::{services::Fs, Operator};
+use opendal_mount::{storage::OverlayStorage, OpendalFs};
+
+fn local(root: &str) -> anyhow::Result<Operator> {
+    Ok(Operator::new(Fs::default().root(root))?.finish())
+}
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    pretty_env_logger::init();
+
+    let mut args = std::env::args().skip(1);
+    let lower = args.next().unwrap_or_else(|| "lower".to_owned());
+    let upper = args.next().unwrap_or_else(|| "upper".to_owned());
+
+    let storage = OverlayStorage::new(Arc::new(local(&lower)?), Arc::new(local(&upper)?));
+    let fs = OpendalFs::with_storage(Arc::new(storage));
+
+    info!(
+        "Serving overlay of {} over {} on 127.0.0.1:12000",
+        upper, lower
+    );
+
+    let listener = NFSTcpListener::bind("127.0.0.1:12000", fs).await?;
+    listener.handle_forever().await?;
+
+    Ok(())
+}
@@ -1,3 +1,8 @@
+use std::{
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
 use anyhow::Ok;
 
 use async_graphql::{
@@ -11,10 +16,12 @@ use axum::{
     Router,
 };
 use clap::Parser;
-use log::info;
+use log::{error, info};
 use nfsserve::tcp::NFSTcp;
 use nfsserve::tcp::NFSTcpListener;
+use notify::{RecommendedWatcher, RecursiveMode, Watcher};
 use opendal_mount::{
+    config::Config,
     schema::{Mutation, Query},
     MultiplexedFs,
 };
@@ -26,45 +33,140 @@ use tokio::{
         self,
         unix::{signal, SignalKind},
     },
+    sync::mpsc,
 };
 
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
 struct Args {
-    /// ip address to bind to
-    #[arg(long, default_value = "127.0.0.1")]
-    host: String,
-
-    #[arg(long, default_value = "1200")]
-    port: u16,
-
-    #[arg(long, default_value = "127.0.0.1:8080")]
-    graphql_addr: String,
+    /// TOML file declaring the server and its mounts, overridden by the
+    /// other arguments
+    #[arg(long)]
+    config: Option<PathBuf>,
+
+    /// ip address to bind to [default: 127.0.0.1]
+    #[arg(long)]
+    host: Option<String>,
+
+    /// [default: 1200]
+    #[arg(long)]
+    port: Option<u16>,
+
+    /// [default: 127.0.0.1:8080]
+    #[arg(long)]
+    graphql_addr: Option<String>,
+
+    /// mount the server once at this path, operators being mounted as its
+    /// subdirectories
+    #[arg(long)]
+    root_mount: Option<String>,
+
+    /// file saving the mounts, restored at startup
+    #[arg(long)]
+    state_file: Option<PathBuf>,
 }
 
 async fn graphql_playground() -> impl IntoResponse {
     Html(playground_source(GraphQLPlaygroundConfig::new("/")))
 }
 
+/// Notifies `changed` when the config file at `path` changes, its directory
+/// being watched for the file to be followed when replaced by editors.
+fn watch_config(
+    path: &Path,
+    changed: mpsc::UnboundedSender<()>,
+) -> notify::Result<RecommendedWatcher> {
+    let name = path.file_name().map(|name| name.to_owned());
+
+    let mut watcher = notify::recommended_watcher(move |event: notify::Result<notify::Event>| {
+        let relevant = event.map_or(true, |event| {
+            event
+                .paths
+                .iter()
+                .any(|path| path.file_name() == name.as_deref())
+        });
+
+        if relevant {
+            let _ = changed.send(());
+        }
+    })?;
+
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    watcher.watch(dir, RecursiveMode::NonRecursive)?;
+
+    notify::Result::Ok(watcher)
+}
+
+/// Applies the mounts of the config file to `fs`.
+async fn reload(fs: &MultiplexedFs) {
+    if let Err(e) = fs.reload_config(false).await {
+        error!("Not reloading the config file: {}", e);
+    }
+}
+
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
     console_subscriber::init();
     let args = Args::parse();
 
-    let fs = MultiplexedFs::new(&args.host, args.port);
+    let config = match &args.config {
+        Some(path) => Config::load(path)?,
+        None => Config::default(),
+    };
+
+    let host = args
+        .host
+        .or(config.server.host.clone())
+        .unwrap_or_else(|| "127.0.0.1".to_owned());
+    let port = args.port.or(config.server.port).unwrap_or(1200);
+    let graphql_addr = args
+        .graphql_addr
+        .or(config.server.graphql_addr.clone())
+        .unwrap_or_else(|| "127.0.0.1:8080".to_owned());
+
+    MultiplexedFs::clean_scratch();
+
+    let mut fs = MultiplexedFs::new(&host, port);
+    if let Some(root_mount) = args.root_mount.or(config.server.root_mount.clone()) {
+        fs = fs.with_root_mount(&root_mount);
+    }
+    if let Some(state_file) =
+        args.state_file
+            .or(config.server.state_file.clone().map(PathBuf::from))
+    {
+        fs = fs.with_state_file(&state_file);
+    }
+    if let Some(path) = &args.config {
+        fs = fs.with_config_file(path);
+    }
     let fs_nfs = fs.clone();
     let fs_umount = fs.clone();
+    let fs_reload = fs.clone();
 
     info!("Starting FS");
+    let listener = NFSTcpListener::bind(&format!("{}:{}", host, port), fs_nfs).await?;
     tokio::spawn(async move {
-        info!("Serving FS on {}:{}", args.host, args.port);
-
-        let listener =
-            NFSTcpListener::bind(&format!("{}:{}", args.host, args.port), fs_nfs).await?;
+        info!("Serving FS on {}:{}", host, port);
 
         listener.handle_forever().await
     });
 
+    fs.mount_root().await?;
+
+    if let Err(e) = fs.restore().await {
+        error!("Failed to restore mounts: {}", e);
+    }
+
+    for entry in config.entries() {
+        let mount_point = entry.mount_point.clone();
+        if let Err(e) = fs.mount_declared(entry).await {
+            error!("Failed to mount {}: {}", mount_point, e);
+        }
+    }
+
     info!("Starting GraphQL");
     tokio::spawn(async move {
         let schema = Schema::build(Query, Mutation, EmptySubscription)
@@ -76,10 +178,17 @@ async fn main() -> anyhow::Result<()> {
             get(graphql_playground).post_service(GraphQL::new(schema)),
         );
 
-        axum::serve(TcpListener::bind(&args.graphql_addr).await.unwrap(), app).await
+        axum::serve(TcpListener::bind(&graphql_addr).await.unwrap(), app).await
     });
 
     let mut sig_term = signal(SignalKind::terminate())?;
+    let mut sig_hup = signal(SignalKind::hangup())?;
+
+    let (changed, mut config_changes) = mpsc::unbounded_channel();
+    let _watcher = match &args.config {
+        Some(path) => Some(watch_config(path, changed)?),
+        None => None,
+    };
 
     info!("Running, press Ctrl-C to stop");
 
@@ -93,6 +202,19 @@ async fn main() -> anyhow::Result<()> {
                 info!("Received SIGTERM, stopping");
                 break;
             }
+            _ = sig_hup.recv() => {
+                match &args.config {
+                    Some(_) => reload(&fs_reload).await,
+                    None => info!("Received SIGHUP without a config file, ignoring"),
+                }
+            }
+            Some(_) = config_changes.recv() => {
+                // Editors write a file in several steps.
+                tokio::time::sleep(Duration::from_millis(200)).await;
+                while config_changes.try_recv().is_ok() {}
+
+                reload(&fs_reload).await;
+            }
         }
     }
 
@@ -0,0 +1,287 @@
+use std::{collections::HashMap, path::Path};
+
+use serde::Deserialize;
+use toml::{Table, Value};
+
+use crate::{
+    errors::{OpendalMountError, OpendalMountResult},
+    schema::{MountOptions, OperatorInput},
+    MountEntry, MountSource,
+};
+
+/// Server configuration, as read from a TOML file.
+///
+/// ```toml
+/// [server]
+/// port = 1200
+///
+/// [defaults]
+/// read_only = true
+///
+/// [[mounts]]
+/// mount_point = "/mnt/data"
+/// service = "s3"
+/// parameters = { bucket = "data", secret_access_key_file = "/run/secrets/s3" }
+/// options = { sub_path = "${USER}" }
+/// ```
+///
+/// `${VAR}` in any string is replaced by the environment variable `VAR`. A
+/// `<key>_file` operator parameter is replaced by a `<key>` parameter
+/// holding the content of the file, so that secrets stay out of the
+/// configuration. `defaults` holds the options of every mount, which its
+/// own options override.
+#[derive(Deserialize, Debug, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub server: ServerConfig,
+    pub defaults: MountOptions,
+    pub mounts: Vec<MountConfig>,
+}
+
+#[derive(Deserialize, Debug, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct ServerConfig {
+    /// IP address the NFS server binds to
+    pub host: Option<String>,
+    pub port: Option<u16>,
+    pub graphql_addr: Option<String>,
+    /// Where to mount the server once, operators being its subdirectories
+    pub root_mount: Option<String>,
+    /// File saving the mounts created through the API
+    pub state_file: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct MountConfig {
+    pub mount_point: String,
+    #[serde(alias = "scheme")]
+    pub service: String,
+    #[serde(default)]
+    pub parameters: HashMap<String, String>,
+    #[serde(default)]
+    pub options: MountOptions,
+}
+
+impl Config {
+    pub fn load(path: &Path) -> OpendalMountResult<Self> {
+        let text = std::fs::read_to_string(path)?;
+
+        Self::parse(&text).map_err(|e| match e {
+            OpendalMountError::InvalidConfig(e) => {
+                OpendalMountError::InvalidConfig(format!("{}: {}", path.display(), e))
+            }
+            e => e,
+        })
+    }
+
+    pub fn parse(text: &str) -> OpendalMountResult<Self> {
+        let mut table: Table = text.parse().map_err(invalid)?;
+
+        interpolate_table(&mut table)?;
+        read_secrets(&mut table)?;
+
+        // Options are merged as tables, for a mount to only override the
+        // defaults it sets.
+        let defaults = table
+            .get("defaults")
+            .cloned()
+            .unwrap_or_else(|| Value::Table(Table::new()));
+        if let Some(Value::Array(mounts)) = table.get_mut("mounts") {
+            for mount in mounts.iter_mut().filter_map(Value::as_table_mut) {
+                let options = mount
+                    .entry("options")
+                    .or_insert_with(|| Value::Table(Table::new()));
+
+                *options = merge(defaults.clone(), options.clone());
+            }
+        }
+
+        table.try_into().map_err(invalid)
+    }
+
+    /// Mounts to create at startup.
+    pub fn entries(&self) -> Vec<MountEntry> {
+        self.mounts
+            .iter()
+            .map(|mount| MountEntry {
+                mount_point: mount.mount_point.clone(),
+                source: MountSource::Operator(OperatorInput {
+                    service: mount.service.clone(),
+                    parameters: mount.parameters.clone(),
+                }),
+                options: mount.options.clone(),
+                index: None,
+            })
+            .collect()
+    }
+}
+
+fn invalid(e: impl ToString) -> OpendalMountError {
+    OpendalMountError::InvalidConfig(e.to_string())
+}
+
+/// `over` with the keys it does not set taken from `base`.
+fn merge(base: Value, over: Value) -> Value {
+    match (base, over) {
+        (Value::Table(mut base), Value::Table(over)) => {
+            for (key, value) in over {
+                let merged = match base.remove(&key) {
+                    Some(base) => merge(base, value),
+                    None => value,
+                };
+                base.insert(key, merged);
+            }
+
+            Value::Table(base)
+        }
+        (_, over) => over,
+    }
+}
+
+fn interpolate_table(table: &mut Table) -> OpendalMountResult<()> {
+    for value in table.values_mut() {
+        interpolate_value(value)?;
+    }
+
+    Ok(())
+}
+
+fn interpolate_value(value: &mut Value) -> OpendalMountResult<()> {
+    match value {
+        Value::String(s) => *s = interpolate(s)?,
+        Value::Array(values) => {
+            for value in values {
+                interpolate_value(value)?;
+            }
+        }
+        Value::Table(table) => interpolate_table(table)?,
+        _ => {}
+    }
+
+    Ok(())
+}
+
+/// `s` with every `${VAR}` replaced by the value of `VAR`, `$$` being a
+/// literal `$`.
+fn interpolate(s: &str) -> OpendalMountResult<String> {
+    let mut out = String::with_capacity(s.len());
+    let mut rest = s;
+
+    while let Some(start) = rest.find('$') {
+        out.push_str(&rest[..start]);
+        rest = &rest[start + 1..];
+
+        if let Some(after) = rest.strip_prefix('$') {
+            out.push('$');
+            rest = after;
+        } else if let Some(after) = rest.strip_prefix('{') {
+            let end = after
+                .find('}')
+                .ok_or_else(|| invalid(format!("unterminated variable in {:?}", s)))?;
+            let name = &after[..end];
+
+            let value = std::env::var(name)
+                .map_err(|_| invalid(format!("environment variable {} is not set", name)))?;
+            out.push_str(&value);
+            rest = &after[end + 1..];
+        } else {
+            out.push('$');
+        }
+    }
+    out.push_str(rest);
+
+    Ok(out)
+}
+
+/// Replaces the `<key>_file` entries of every `parameters` table by the
+/// content of the file.
+fn read_secrets(table: &mut Table) -> OpendalMountResult<()> {
+    for (key, value) in table.iter_mut() {
+        match value {
+            Value::Table(parameters) if key == "parameters" => {
+                let files: Vec<String> = parameters
+                    .keys()
+                    .filter(|key| key.ends_with("_file"))
+                    .cloned()
+                    .collect();
+
+                for file_key in files {
+                    let Some(Value::String(path)) = parameters.remove(&file_key) else {
+                        return Err(invalid(format!("{} is not a path", file_key)));
+                    };
+
+                    let secret = std::fs::read_to_string(&path).map_err(|e| {
+                        invalid(format!("unable to read {} from {}: {}", file_key, path, e))
+                    })?;
+
+                    let key = file_key.strip_suffix("_file").unwrap_or(&file_key);
+                    parameters.insert(key.to_owned(), Value::String(secret.trim_end().to_owned()));
+                }
+            }
+            Value::Table(table) => read_secrets(table)?,
+            Value::Array(values) => {
+                for table in values.iter_mut().filter_map(Value::as_table_mut) {
+                    read_secrets(table)?;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    Ok(())
+}
+
+/// Changes bringing the mounts declared in a config file to the mounts
+/// declared in a new version of it.
+#[derive(Debug, Default)]
+pub struct ReloadPlan {
+    pub add: Vec<MountEntry>,
+    pub remove: Vec<String>,
+    /// Mounts whose operators or options changed, unmounted then mounted
+    pub remount: Vec<MountEntry>,
+    /// Mount points left alone
+    pub unchanged: Vec<String>,
+}
+
+impl ReloadPlan {
+    pub fn diff(live: &[MountEntry], declared: &[MountEntry]) -> Self {
+        let mut plan = Self::default();
+
+        for entry in declared {
+            match live.iter().find(|e| e.mount_point == entry.mount_point) {
+                None => plan.add.push(entry.clone()),
+                Some(live) if live != entry => plan.remount.push(entry.clone()),
+                Some(_) => plan.unchanged.push(entry.mount_point.clone()),
+            }
+        }
+
+        for entry in live {
+            if !declared.iter().any(|e| e.mount_point == entry.mount_point) {
+                plan.remove.push(entry.mount_point.clone());
+            }
+        }
+
+        plan
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.add.is_empty() && self.remove.is_empty() && self.remount.is_empty()
+    }
+}
+
+impl std::fmt::Display for ReloadPlan {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for entry in &self.add {
+            writeln!(f, "+ {}", entry.mount_point)?;
+        }
+        for mount_point in &self.remove {
+            writeln!(f, "- {}", mount_point)?;
+        }
+        for entry in &self.remount {
+            writeln!(f, "~ {}", entry.mount_point)?;
+        }
+
+        write!(f, "{} unchanged", self.unchanged.len())
+    }
+}
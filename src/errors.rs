@@ -14,9 +14,51 @@ pub enum OpendalMountError {
     #[error("FS already mounted at {0}")]
     AlreadyMounted(String),
 
+    #[error("invalid mount name {0}")]
+    InvalidMountName(String),
+
+    #[error("no mount slot left")]
+    TooManyMounts(),
+
     #[error("operator creation failure {0}")]
     OperatorCreateError(String),
 
+    #[error("invalid sub path {0}")]
+    InvalidSubPath(String),
+
+    #[error("invalid encryption key: {0}")]
+    InvalidKey(String),
+
+    #[error("invalid pattern {0}")]
+    InvalidPattern(String),
+
+    #[error("no FS mounted at {0}")]
+    NotMounted(String),
+
+    #[error("FS mounted at {0} has pending writes")]
+    Busy(String),
+
+    #[error("FS mounted at {0} is not mirrored")]
+    NotMirrored(String),
+
+    #[error("FS mounted at {0} is not tiered")]
+    NotTiered(String),
+
+    #[error("FS mounted at {0} has no trash")]
+    NoTrash(String),
+
+    #[error("invalid config {0}")]
+    InvalidConfig(String),
+
+    #[error("no config file to reload")]
+    NoConfigFile(),
+
+    #[error("invalid state file {0}")]
+    InvalidState(String),
+
+    #[error(transparent)]
+    StorageError(#[from] opendal::Error),
+
     #[error(transparent)]
     IoError(#[from] std::io::Error),
 }
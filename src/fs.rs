@@ -1,98 +1,614 @@
 use std::{
-    collections::hash_map::DefaultHasher,
-    hash::{Hash, Hasher},
+    collections::HashMap,
     path::Path,
     sync::Arc,
+    time::{Duration, Instant},
 };
 
 use async_trait::async_trait;
-use bimap::BiMap;
+use globset::GlobSet;
 use log::{debug, warn};
 use nfsserve::{
-    nfs::{fattr3, fileid3, filename3, ftype3, nfspath3, nfsstat3, nfstime3, sattr3, specdata3},
+    nfs::{
+        fattr3, fileid3, filename3, ftype3, nfs_fh3, nfspath3, nfsstat3, nfstime3, sattr3,
+        set_size3, specdata3,
+    },
     vfs::{DirEntry, NFSFileSystem, ReadDirResult, VFSCapabilities},
 };
-use opendal::Operator;
+use opendal::{services::Memory, ErrorKind, Metadata, Operator};
 use tokio::sync::RwLock;
 
+use crate::{
+    inodes::{self, InodeTable},
+    quota::Quota,
+    sidecar::{self, Sidecar},
+    storage::{self, ScratchStorage, Storage, WriteMetadata},
+    versions::{self, VersionNode, VERSIONS_DIR, VERSIONS_NAME},
+};
+
+/// Number of cached directories listed to find an evicted inode, before
+/// giving up with `NFS3ERR_STALE`. The handle of a file evicted from the
+/// inode table is thus stale once its directory is not among the most
+/// recently used ones, until the directory is used again.
+const MAX_CACHED_DIRS_SEARCHED: usize = 64;
+
+/// Size past which writes to a sidecar fail with `NFS3ERR_FBIG`.
+const MAX_SIDECAR_LEN: u64 = 64 * 1024;
+
+/// Number of sidecars being written at once, the least recently written
+/// being dropped past it.
+const MAX_PENDING_SIDECARS: usize = 1024;
+
+/// Time after which a sidecar that was not completed is dropped.
+const PENDING_SIDECAR_TTL: Duration = Duration::from_secs(300);
+
+/// Size of the ignored files kept in memory, past which writing them fails.
+const MAX_IGNORED_BYTES: u64 = 64 * 1024 * 1024;
+
+/// Zeros appended at once to extend a file.
+const ZEROS_LEN: u64 = 1024 * 1024;
+
 pub struct OpendalFs {
-    operator: Operator,
-    inodes: Arc<RwLock<BiMap<u64, String>>>,
+    storage: Arc<dyn Storage>,
+    inodes: Arc<InodeTable>,
+    quota: Arc<Quota>,
+    versions: bool,
+    sidecars: bool,
+    read_only: bool,
+    /// Sidecars being written, until they hold a complete document.
+    pending_sidecars: Arc<RwLock<HashMap<String, PendingSidecar>>>,
+}
+
+struct PendingSidecar {
+    data: Vec<u8>,
+    written: Instant,
 }
 
 impl OpendalFs {
     pub fn new(operator: Operator) -> Self {
-        let mut inodes = BiMap::new();
-        inodes.insert(1, "/".to_string());
+        Self::with_storage(Arc::new(operator))
+    }
 
+    pub fn with_storage(storage: Arc<dyn Storage>) -> Self {
         OpendalFs {
-            operator,
-            inodes: Arc::new(RwLock::new(inodes)),
+            storage,
+            inodes: Arc::new(InodeTable::default()),
+            quota: Arc::new(Quota::unlimited()),
+            versions: false,
+            sidecars: false,
+            read_only: false,
+            pending_sidecars: Arc::new(RwLock::new(HashMap::new())),
         }
     }
 
-    async fn inode_to_path(&self, inode: u64) -> Option<String> {
-        self.inodes.read().await.get_by_left(&inode).cloned()
+    /// Keeps at most `capacity` paths in the inode table, the least
+    /// recently used ones being found again in the storage when needed.
+    pub fn with_inode_capacity(mut self, capacity: usize) -> Self {
+        self.inodes = Arc::new(InodeTable::new(capacity));
+        self
     }
 
-    async fn path_to_inode(&self, path: &str, insert: bool) -> Result<u64, nfsstat3> {
-        let ino = self.inodes.read().await.get_by_right(path).copied();
+    pub fn with_quota(mut self, quota: Quota) -> Self {
+        self.quota = Arc::new(quota);
+        self
+    }
 
-        match ino {
-            Some(ino) => Ok(ino),
-            None if insert => {
-                let mut hasher = DefaultHasher::new();
-                path.hash(&mut hasher);
-                let ino = hasher.finish();
+    pub fn quota(&self) -> &Quota {
+        &self.quota
+    }
+
+    /// Starts the quota from the files already in the storage, walking the
+    /// whole tree when a limit is set.
+    pub async fn measure_usage(&self) -> opendal::Result<()> {
+        if !self.quota.is_limited() {
+            return Ok(());
+        }
+
+        let (bytes, objects) = self.usage("/").await?;
+        self.quota.set_usage(bytes, objects);
 
-                let mut inodes = self.inodes.write().await;
-                (*inodes).insert(ino, path.to_owned());
+        Ok(())
+    }
+
+    /// Size and number of the files under the directory at `dir`.
+    async fn usage(&self, dir: &str) -> opendal::Result<(u64, u64)> {
+        let mut bytes = 0;
+        let mut objects = 0;
 
-                Ok(ino)
+        for entry in storage::walk(self.storage.as_ref(), dir).await? {
+            if entry.metadata().is_dir() || entry.path().ends_with('/') {
+                continue;
             }
-            _ => Err(nfsstat3::NFS3ERR_NOENT),
+
+            bytes += self.storage.stat(entry.path()).await?.content_length();
+            objects += 1;
         }
+
+        Ok((bytes, objects))
     }
 
-    async fn path_to_attr(&self, ino: u64, path: &str) -> Result<fattr3, nfsstat3> {
-        let meta = self.operator.stat(&path).await.map_err(|e| {
+    /// Whether sidecars are still being written, which would be lost by
+    /// [`OpendalFs::purge`].
+    pub async fn has_pending_writes(&self) -> bool {
+        self.pending_sidecars
+            .read()
+            .await
+            .values()
+            .any(|sidecar| sidecar.written.elapsed() <= PENDING_SIDECAR_TTL)
+    }
+
+    /// Drops the state of the mount, once unmounted: the sidecars still
+    /// being written and the inode table.
+    pub async fn purge(&self) {
+        let pending = std::mem::take(&mut *self.pending_sidecars.write().await);
+        for path in pending.keys() {
+            warn!("discarding incomplete sidecar {:?}", path);
+        }
+
+        self.inodes.clear();
+    }
+
+    pub fn storage(&self) -> &Arc<dyn Storage> {
+        &self.storage
+    }
+
+    /// Exposes the versions of every file under a read only `.versions`
+    /// directory at the root of the mount.
+    pub fn with_versions(mut self, versions: bool) -> Self {
+        self.versions = versions;
+        self
+    }
+
+    /// Keeps the files whose name matches `patterns` in `scratch` until they
+    /// are renamed to a name that does not match.
+    pub fn with_local_only(mut self, patterns: GlobSet, scratch: Arc<dyn Storage>) -> Self {
+        self.storage = Arc::new(ScratchStorage::new(self.storage, scratch, patterns));
+        self
+    }
+
+    /// Keeps the files whose name matches `patterns` in memory, up to
+    /// 64 MiB, hiding the matching objects of the storage.
+    pub fn with_ignored(mut self, patterns: GlobSet) -> opendal::Result<Self> {
+        let memory = Operator::new(Memory::default())?.finish();
+
+        self.storage = Arc::new(
+            ScratchStorage::new(self.storage, Arc::new(memory), patterns)
+                .with_max_bytes(MAX_IGNORED_BYTES),
+        );
+        Ok(self)
+    }
+
+    /// Rejects every change to the mount with `NFS3ERR_ROFS`.
+    pub fn with_read_only(mut self, read_only: bool) -> Self {
+        self.read_only = read_only;
+        self
+    }
+
+    fn check_writable(&self) -> Result<(), nfsstat3> {
+        if self.read_only {
+            Err(nfsstat3::NFS3ERR_ROFS)
+        } else {
+            Ok(())
+        }
+    }
+
+    fn is_version_path(&self, path: &str) -> bool {
+        self.versions && versions::strip(path).is_some()
+    }
+
+    /// Shows the metadata of every file `foo` in a hidden `.foo.meta.json`
+    /// file, which can be written to update the metadata.
+    pub fn with_sidecars(mut self, sidecars: bool) -> Self {
+        self.sidecars = sidecars;
+        self
+    }
+
+    /// Path and metadata of the file described by the sidecar at `path`.
+    async fn sidecar_target(&self, path: &str) -> Option<(String, Metadata)> {
+        if !self.sidecars {
+            return None;
+        }
+
+        let target = sidecar::target_path(path)?;
+        let meta = self.storage.stat(&target).await.ok()?;
+
+        meta.is_file().then_some((target, meta))
+    }
+
+    async fn write_sidecar(
+        &self,
+        id: fileid3,
+        path: &str,
+        target: &str,
+        offset: u64,
+        data: &[u8],
+    ) -> Result<fattr3, nfsstat3> {
+        let end = offset
+            .checked_add(data.len() as u64)
+            .filter(|end| *end <= MAX_SIDECAR_LEN)
+            .ok_or(nfsstat3::NFS3ERR_FBIG)? as usize;
+
+        let mut pending = self.pending_sidecars.write().await;
+
+        pending.retain(|path, sidecar| {
+            let expired = sidecar.written.elapsed() > PENDING_SIDECAR_TTL;
+            if expired {
+                warn!("dropping incomplete sidecar {:?}", path);
+            }
+
+            !expired
+        });
+
+        if !pending.contains_key(path) && pending.len() >= MAX_PENDING_SIDECARS {
+            let oldest = pending
+                .iter()
+                .min_by_key(|(_, sidecar)| sidecar.written)
+                .map(|(path, _)| path.clone());
+
+            if let Some(oldest) = oldest {
+                warn!("dropping incomplete sidecar {:?}", oldest);
+                pending.remove(&oldest);
+            }
+        }
+
+        let sidecar = pending
+            .entry(path.to_owned())
+            .or_insert_with(|| PendingSidecar {
+                data: Vec::new(),
+                written: Instant::now(),
+            });
+        sidecar.written = Instant::now();
+
+        let buffer = &mut sidecar.data;
+        if buffer.len() < end {
+            buffer.resize(end, 0);
+        }
+        buffer[offset as usize..end].copy_from_slice(data);
+
+        match Sidecar::parse(buffer) {
+            Ok(metadata) => {
+                pending.remove(path);
+                drop(pending);
+
+                self.update_metadata(target, metadata).await?;
+            }
+            Err(e) if e.is_eof() => {}
+            Err(e) => {
+                warn!("invalid sidecar {:?}: {}", path, e);
+                pending.remove(path);
+
+                return Err(nfsstat3::NFS3ERR_INVAL);
+            }
+        }
+
+        self.path_to_attr(id, path).await
+    }
+
+    /// Rewrites the file at `path` with new metadata, failing if a field
+    /// that changed cannot be stored by the backend or if the file is too
+    /// large to be rewritten.
+    async fn update_metadata(&self, path: &str, metadata: WriteMetadata) -> Result<(), nfsstat3> {
+        let meta = self.storage.stat(path).await.map_err(|e| {
             warn!("unable to get metadata for {:?}: {}", path, e);
             nfsstat3::NFS3ERR_NOENT
         })?;
 
-        let kind = if meta.is_dir() {
-            ftype3::NF3DIR
-        } else {
-            ftype3::NF3REG
-        };
+        let current = WriteMetadata::from(&meta);
+        let capability = self.storage.info().full_capability();
+
+        let supported = (metadata.content_type == current.content_type
+            || capability.write_with_content_type)
+            && (metadata.content_disposition == current.content_disposition
+                || capability.write_with_content_disposition)
+            && (metadata.cache_control == current.cache_control
+                || capability.write_with_cache_control)
+            && (metadata.user_metadata == current.user_metadata
+                || capability.write_with_user_metadata);
+
+        if !supported {
+            warn!("backend is unable to store metadata of {:?}", path);
+            return Err(nfsstat3::NFS3ERR_NOTSUPP);
+        }
+
+        if meta.content_length() > storage::MAX_BUFFERED_LEN {
+            warn!("{:?} is too large for its metadata to be updated", path);
+            return Err(nfsstat3::NFS3ERR_FBIG);
+        }
+
+        let data = self
+            .storage
+            .read(path, 0..meta.content_length())
+            .await
+            .map_err(|e| {
+                warn!("unable to read {:?}: {}", path, e);
+                nfsstat3::NFS3ERR_IO
+            })?;
+
+        self.storage
+            .write_with_metadata(path, data, metadata)
+            .await
+            .map_err(|e| {
+                warn!("unable to update metadata of {:?}: {}", path, e);
+                nfsstat3::NFS3ERR_IO
+            })
+    }
+
+    /// Cuts or extends with zeros the file at `path` to `size` bytes, the
+    /// kept part being rewritten when cut.
+    async fn truncate(&self, path: &str, size: u64) -> Result<(), nfsstat3> {
+        let meta = self.storage.stat(path).await.map_err(|e| {
+            warn!("unable to get metadata for {:?}: {}", path, e);
+            nfsstat3::NFS3ERR_NOENT
+        })?;
+
+        if meta.is_dir() {
+            return Err(nfsstat3::NFS3ERR_ISDIR);
+        }
+
+        let current = meta.content_length();
+        if size == current {
+            return Ok(());
+        }
+
+        if size < current && size > storage::MAX_BUFFERED_LEN {
+            warn!("{:?} is too large to be cut", path);
+            return Err(nfsstat3::NFS3ERR_FBIG);
+        }
+
+        let grown = size.saturating_sub(current);
+        self.quota.reserve_bytes(grown)?;
 
-        let mtime = if let Some(mtime) = meta.last_modified() {
-            nfstime3 {
-                seconds: mtime.timestamp() as u32,
-                nseconds: 0,
+        let truncated = async {
+            if size < current {
+                let data = match size {
+                    0 => Vec::new(),
+                    _ => self.storage.read(path, 0..size).await?,
+                };
+
+                return self.storage.write(path, data).await;
             }
-        } else {
-            nfstime3::default()
+
+            let mut zeros = grown;
+            while zeros > 0 {
+                let len = zeros.min(ZEROS_LEN);
+                self.storage.append(path, vec![0; len as usize]).await?;
+                zeros -= len;
+            }
+
+            Ok(())
+        };
+
+        if let Err(e) = truncated.await {
+            warn!("unable to truncate {:?}: {}", path, e);
+            self.quota.release_bytes(grown);
+
+            return Err(nfsstat3::NFS3ERR_IO);
+        }
+        self.quota.release_bytes(current.saturating_sub(size));
+
+        Ok(())
+    }
+
+    async fn inode_to_path(&self, inode: u64) -> Result<String, nfsstat3> {
+        match self.inodes.path(inode) {
+            Some(path) => Ok(path),
+            None => self.rederive(inode).await.ok_or(nfsstat3::NFS3ERR_STALE),
+        }
+    }
+
+    async fn path_to_inode(&self, path: &str, insert: bool) -> Result<u64, nfsstat3> {
+        match self.inodes.get(path) {
+            Some(ino) => Ok(ino),
+            None if insert => Ok(self.inodes.insert(path)),
+            None => Err(nfsstat3::NFS3ERR_NOENT),
+        }
+    }
+
+    /// Inode of the directory holding `path`, the root of the mount being
+    /// its own parent so that `..` never leads out of it.
+    async fn parent_inode(&self, path: &str) -> Result<u64, nfsstat3> {
+        let parent = match path.trim_end_matches('/').rsplit_once('/') {
+            Some((parent, _)) if !parent.is_empty() => parent,
+            _ => return Ok(inodes::ROOT_INODE),
         };
 
-        let mode = if meta.is_dir() { 0o777 } else { 0o755 };
-
-        Ok(fattr3 {
-            ftype: kind,
-            mode,
-            nlink: 0,
-            uid: 507,
-            gid: 507,
-            size: meta.content_length(),
-            used: meta.content_length(),
-            rdev: specdata3::default(),
-            fsid: 0,
-            fileid: ino,
-            atime: mtime,
-            mtime,
-            ctime: mtime,
+        match self.path_to_inode(parent, false).await {
+            Err(nfsstat3::NFS3ERR_NOENT) => {
+                let parent = self.find(parent).await?;
+
+                self.path_to_inode(&parent, true).await
+            }
+            ino => ino,
+        }
+    }
+
+    /// Finds the path of an inode evicted from the inode table in the
+    /// [`MAX_CACHED_DIRS_SEARCHED`] most recently used cached directories and
+    /// the root, rather than walking the whole tree for every unknown handle.
+    /// Handles only carry the inode, whose path is not found elsewhere.
+    async fn rederive(&self, inode: u64) -> Option<String> {
+        debug!("looking for evicted inode {:?}", inode);
+
+        let mut dirs = self.inodes.dirs();
+        dirs.truncate(MAX_CACHED_DIRS_SEARCHED);
+        dirs.push("/".to_owned());
+
+        for dir in dirs {
+            let Ok(children) = self.children(&dir).await else {
+                continue;
+            };
+
+            for (path, _) in children {
+                if self.inodes.inode(&path) == inode {
+                    self.inodes.insert(&path);
+                    return Some(path);
+                }
+            }
+        }
+
+        debug!("inode {:?} not found", inode);
+
+        None
+    }
+
+    /// Path of the file or directory at `path` in the storage, directories
+    /// ending with `/`.
+    async fn find(&self, path: &str) -> Result<String, nfsstat3> {
+        let dir = format!("{}/", path.trim_end_matches('/'));
+
+        match self.storage.stat(path).await {
+            Ok(meta) if meta.is_dir() => Ok(dir),
+            Ok(_) => Ok(path.to_owned()),
+            Err(_) => match self.storage.stat(&dir).await {
+                Ok(meta) if meta.is_dir() => Ok(dir),
+                _ => Err(nfsstat3::NFS3ERR_NOENT),
+            },
+        }
+    }
+
+    async fn path_to_attr(&self, ino: u64, path: &str) -> Result<fattr3, nfsstat3> {
+        if self.is_version_path(path) {
+            let (node, meta) = VersionNode::resolve(self.storage.as_ref(), path)
+                .await
+                .ok_or(nfsstat3::NFS3ERR_NOENT)?;
+
+            return Ok(match node {
+                VersionNode::Version { .. } => meta_to_attr(ino, &meta, ftype3::NF3REG, 0o444),
+                _ => meta_to_attr(ino, &meta, ftype3::NF3DIR, 0o555),
+            });
+        }
+
+        if let Some((_, meta)) = self.sidecar_target(path).await {
+            let size = match self.pending_sidecars.read().await.get(path) {
+                Some(pending) if !pending.data.is_empty() => pending.data.len(),
+                _ => Sidecar::render(&meta).len(),
+            };
+
+            let mut attr = meta_to_attr(ino, &meta, ftype3::NF3REG, 0o644);
+            attr.size = size as u64;
+            attr.used = size as u64;
+
+            return Ok(attr);
+        }
+
+        let meta = self.storage.stat(path).await.map_err(|e| {
+            warn!("unable to get metadata for {:?}: {}", path, e);
+            nfsstat3::NFS3ERR_NOENT
+        })?;
+
+        Ok(if meta.is_dir() {
+            meta_to_attr(ino, &meta, ftype3::NF3DIR, 0o777)
+        } else {
+            meta_to_attr(ino, &meta, ftype3::NF3REG, 0o755)
         })
     }
+
+    /// Children of a directory as `(path, name)` pairs.
+    async fn children(&self, path: &str) -> Result<Vec<(String, String)>, nfsstat3> {
+        if self.is_version_path(path) {
+            let node = VersionNode::resolve(self.storage.as_ref(), path).await;
+
+            return match node {
+                Some((VersionNode::Tree(inner), _)) => {
+                    let ds = self
+                        .storage
+                        .list(&inner)
+                        .await
+                        .map_err(|_| nfsstat3::NFS3ERR_NOENT)?;
+
+                    Ok(ds
+                        .iter()
+                        .map(|de| {
+                            let name = de.name().trim_end_matches('/').to_owned();
+                            (versions::versions_path(de.path()), name)
+                        })
+                        .collect())
+                }
+                Some((VersionNode::Versions(inner), _)) => {
+                    let ds = self.storage.list_versions(&inner).await.map_err(|e| {
+                        warn!("unable to list versions of {:?}: {}", inner, e);
+                        nfsstat3::NFS3ERR_IO
+                    })?;
+
+                    Ok(ds
+                        .iter()
+                        .filter_map(|de| de.metadata().version())
+                        .map(|v| {
+                            (
+                                format!("{}/{}", path.trim_end_matches('/'), v),
+                                v.to_owned(),
+                            )
+                        })
+                        .collect())
+                }
+                Some((VersionNode::Version { .. }, _)) => Err(nfsstat3::NFS3ERR_NOTDIR),
+                None => Err(nfsstat3::NFS3ERR_NOENT),
+            };
+        }
+
+        let ds = self
+            .storage
+            .list(path)
+            .await
+            .map_err(|_| nfsstat3::NFS3ERR_NOENT)?;
+
+        let mut children: Vec<(String, String)> = ds
+            .iter()
+            .map(|de| {
+                let name = de.name().trim_end_matches('/').to_owned();
+                (format!("/{}", de.path().trim_start_matches('/')), name)
+            })
+            .collect();
+
+        if self.sidecars {
+            let sidecars: Vec<(String, String)> = children
+                .iter()
+                .filter(|(path, _)| !path.ends_with('/'))
+                .map(|(path, name)| (sidecar::sidecar_path(path), sidecar::sidecar_path(name)))
+                .collect();
+
+            children.extend(sidecars);
+        }
+
+        if self.versions && path == "/" {
+            children.push((VERSIONS_DIR.to_owned(), VERSIONS_NAME.to_owned()));
+        }
+
+        Ok(children)
+    }
+}
+
+fn meta_to_attr(ino: u64, meta: &Metadata, kind: ftype3, mode: u32) -> fattr3 {
+    let mtime = if let Some(mtime) = meta.last_modified() {
+        nfstime3 {
+            seconds: mtime.timestamp() as u32,
+            nseconds: 0,
+        }
+    } else {
+        nfstime3::default()
+    };
+
+    let size = if matches!(kind, ftype3::NF3DIR) {
+        0
+    } else {
+        meta.content_length()
+    };
+
+    fattr3 {
+        ftype: kind,
+        mode,
+        nlink: 0,
+        uid: 507,
+        gid: 507,
+        size,
+        used: size,
+        rdev: specdata3::default(),
+        fsid: 0,
+        fileid: ino,
+        atime: mtime,
+        mtime,
+        ctime: mtime,
+    }
 }
 
 #[async_trait]
@@ -103,10 +619,25 @@ impl NFSFileSystem for OpendalFs {
         1
     }
 
+    fn id_to_fh(&self, id: fileid3) -> nfs_fh3 {
+        inodes::file_handle(id, self.inodes.generation(id))
+    }
+
+    fn fh_to_id(&self, fh: &nfs_fh3) -> Result<fileid3, nfsstat3> {
+        let (id, generation) = inodes::parse_file_handle(fh)?;
+
+        if generation != self.inodes.generation(id) {
+            debug!("stale handle for {:?} at generation {}", id, generation);
+            return Err(nfsstat3::NFS3ERR_STALE);
+        }
+
+        Ok(id)
+    }
+
     fn capabilities(&self) -> VFSCapabilities {
         debug!("capabilities");
 
-        if self.operator.info().full_capability().write {
+        if !self.read_only && self.storage.info().full_capability().write {
             VFSCapabilities::ReadWrite
         } else {
             VFSCapabilities::ReadOnly
@@ -116,34 +647,58 @@ impl NFSFileSystem for OpendalFs {
     async fn write(&self, id: fileid3, offset: u64, data: &[u8]) -> Result<fattr3, nfsstat3> {
         debug!("write {:?} {:?} {:?}", id, offset, data);
 
-        let path = self.inode_to_path(id).await;
+        self.check_writable()?;
 
-        if let Some(path) = path {
-            if offset == 0 {
-                self.operator
-                    .write(&path, data.to_vec())
-                    .await
-                    .map_err(|_| {
-                        warn!("unable to write to {:?}", path);
-                        nfsstat3::NFS3ERR_IO
-                    })
-            } else {
-                self.operator
-                    .write_with(&path, data.to_vec())
-                    .append(true)
-                    .await
-                    .map_err(|_| {
-                        warn!("unable to append to {:?}", path);
-                        nfsstat3::NFS3ERR_IO
-                    })
-            }?;
+        let path = self.inode_to_path(id).await?;
 
-            let attr = self.path_to_attr(id, &path).await?;
+        if self.is_version_path(&path) {
+            return Err(nfsstat3::NFS3ERR_ROFS);
+        }
 
-            Ok(attr)
+        if let Some((target, _)) = self.sidecar_target(&path).await {
+            return self.write_sidecar(id, &path, &target, offset, data).await;
+        }
+
+        // Quota is charged by how much the file grows, the storage
+        // replacing the file on a write at offset 0.
+        let size = self
+            .storage
+            .stat(&path)
+            .await
+            .map_or(0, |meta| meta.content_length());
+        let new_size = if offset == 0 {
+            data.len() as u64
         } else {
-            Err(nfsstat3::NFS3ERR_NOENT)
+            size + data.len() as u64
+        };
+        let grown = new_size.saturating_sub(size);
+
+        self.quota.reserve_bytes(grown)?;
+
+        let written = if offset == 0 {
+            self.storage.write(&path, data.to_vec()).await.map_err(|_| {
+                warn!("unable to write to {:?}", path);
+                nfsstat3::NFS3ERR_IO
+            })
+        } else {
+            self.storage
+                .append(&path, data.to_vec())
+                .await
+                .map_err(|_| {
+                    warn!("unable to append to {:?}", path);
+                    nfsstat3::NFS3ERR_IO
+                })
+        };
+
+        if let Err(e) = written {
+            self.quota.release_bytes(grown);
+            return Err(e);
         }
+        self.quota.release_bytes(size.saturating_sub(new_size));
+
+        let attr = self.path_to_attr(id, &path).await?;
+
+        Ok(attr)
     }
 
     async fn create(
@@ -154,16 +709,41 @@ impl NFSFileSystem for OpendalFs {
     ) -> Result<(fileid3, fattr3), nfsstat3> {
         debug!("create {:?} {:?}", dirid, filename);
 
+        self.check_writable()?;
+
         let filename = std::str::from_utf8(&filename.0);
-        let path = self.inode_to_path(dirid).await;
+        let path = self.inode_to_path(dirid).await?;
 
-        if let (Ok(filename), Some(path)) = (filename, path) {
-            let path = Path::new(&path).join(filename);
-            let ino = self
-                .path_to_inode(&path.display().to_string(), true)
-                .await?;
+        if let Ok(filename) = filename {
+            if self.is_version_path(&path) {
+                return Err(nfsstat3::NFS3ERR_ROFS);
+            }
+
+            let path = Path::new(&path).join(filename).display().to_string();
+            let ino = self.path_to_inode(&path, true).await?;
 
-            self.write(ino, 0, &[]).await.map(|attr| (ino, attr))
+            if self.sidecar_target(&path).await.is_some() {
+                self.pending_sidecars.write().await.remove(&path);
+
+                return self.path_to_attr(ino, &path).await.map(|attr| (ino, attr));
+            }
+
+            // Creating an existing file truncates it, it is not one more
+            // object.
+            let exists = self.storage.stat(&path).await.is_ok();
+            if !exists {
+                self.quota.reserve_object()?;
+            }
+
+            match self.write(ino, 0, &[]).await {
+                Ok(attr) => Ok((ino, attr)),
+                Err(e) => {
+                    if !exists {
+                        self.quota.release_object();
+                    }
+                    Err(e)
+                }
+            }
         } else {
             warn!("unable to create file {:?} {:?}", dirid, filename);
             Err(nfsstat3::NFS3ERR_NOENT)
@@ -177,16 +757,30 @@ impl NFSFileSystem for OpendalFs {
     ) -> Result<fileid3, nfsstat3> {
         debug!("create_exclusive {:?} {:?}", dirid, filename);
 
+        self.check_writable()?;
+
         let filename = std::str::from_utf8(&filename.0);
-        let path = self.inode_to_path(dirid).await;
+        let path = self.inode_to_path(dirid).await?;
 
-        if let (Ok(filename), Some(path)) = (filename, path) {
-            let path = Path::new(&path).join(filename);
-            let ino = self
-                .path_to_inode(&path.display().to_string(), true)
-                .await?;
+        if let Ok(filename) = filename {
+            if self.is_version_path(&path) {
+                return Err(nfsstat3::NFS3ERR_ROFS);
+            }
 
-            self.write(ino, 0, &[0]).await?;
+            let path = Path::new(&path).join(filename).display().to_string();
+            let ino = self.path_to_inode(&path, true).await?;
+
+            let exists = self.storage.stat(&path).await.is_ok();
+            if !exists {
+                self.quota.reserve_object()?;
+            }
+
+            if let Err(e) = self.write(ino, 0, &[0]).await {
+                if !exists {
+                    self.quota.release_object();
+                }
+                return Err(e);
+            }
 
             Ok(ino)
         } else {
@@ -199,12 +793,36 @@ impl NFSFileSystem for OpendalFs {
         debug!("lookup {:?} {:?}", dirid, filename);
 
         let filename = std::str::from_utf8(&filename.0);
-        let path = self.inode_to_path(dirid).await;
+        let path = self.inode_to_path(dirid).await?;
+
+        if let Ok(filename) = filename {
+            match filename {
+                "." => return Ok(dirid),
+                ".." => return self.parent_inode(&path).await,
+                _ => {}
+            }
 
-        if let (Ok(filename), Some(path)) = (filename, path) {
-            let path = Path::new(&path).join(filename);
+            let path = Path::new(&path).join(filename).display().to_string();
 
-            self.path_to_inode(&path.display().to_string(), false).await
+            match self.path_to_inode(&path, false).await {
+                Err(nfsstat3::NFS3ERR_NOENT) if self.is_version_path(&path) => {
+                    VersionNode::resolve(self.storage.as_ref(), &path)
+                        .await
+                        .ok_or(nfsstat3::NFS3ERR_NOENT)?;
+
+                    self.path_to_inode(&path, true).await
+                }
+                Err(nfsstat3::NFS3ERR_NOENT) if self.sidecar_target(&path).await.is_some() => {
+                    self.path_to_inode(&path, true).await
+                }
+                // Not listed yet, or evicted from the inode table.
+                Err(nfsstat3::NFS3ERR_NOENT) => {
+                    let path = self.find(&path).await?;
+
+                    self.path_to_inode(&path, true).await
+                }
+                ino => ino,
+            }
         } else {
             Err(nfsstat3::NFS3ERR_NOENT)
         }
@@ -213,20 +831,27 @@ impl NFSFileSystem for OpendalFs {
     async fn getattr(&self, id: fileid3) -> Result<fattr3, nfsstat3> {
         debug!("getattr {:?}", id);
 
-        let path = self
-            .inode_to_path(id)
-            .await
-            .ok_or(nfsstat3::NFS3ERR_NOENT)?;
+        let path = self.inode_to_path(id).await?;
 
         self.path_to_attr(id, &path).await
     }
     async fn setattr(&self, id: fileid3, setattr: sattr3) -> Result<fattr3, nfsstat3> {
         debug!("setattr {:?} {:?}", id, setattr);
 
-        let path = self
-            .inode_to_path(id)
-            .await
-            .ok_or(nfsstat3::NFS3ERR_NOENT)?;
+        self.check_writable()?;
+
+        let path = self.inode_to_path(id).await?;
+
+        if let set_size3::size(size) = setattr.size {
+            if self.is_version_path(&path) {
+                return Err(nfsstat3::NFS3ERR_ROFS);
+            }
+
+            // Sidecars are rewritten whole, their size follows their writes.
+            if self.sidecar_target(&path).await.is_none() {
+                self.truncate(&path, size).await?;
+            }
+        }
 
         let attrs = self.path_to_attr(id, &path).await?;
 
@@ -241,16 +866,30 @@ impl NFSFileSystem for OpendalFs {
     ) -> Result<(Vec<u8>, bool), nfsstat3> {
         debug!("read {:?} {:?} {:?}", id, offset, count);
 
-        let path = self
-            .inode_to_path(id)
-            .await
-            .ok_or(nfsstat3::NFS3ERR_NOENT)?;
+        let path = self.inode_to_path(id).await?;
 
-        let data = self
-            .operator
-            .read_with(&path)
-            .range(offset..offset + count as u64)
-            .await;
+        let range = offset..offset + count as u64;
+
+        let data = if self.is_version_path(&path) {
+            match VersionNode::resolve(self.storage.as_ref(), &path).await {
+                Some((VersionNode::Version { path, version }, _)) => {
+                    match self.storage.at_version(&version) {
+                        Ok(storage) => storage.read(&path, range).await,
+                        Err(e) => Err(e),
+                    }
+                }
+                Some(_) => return Err(nfsstat3::NFS3ERR_ISDIR),
+                None => return Err(nfsstat3::NFS3ERR_NOENT),
+            }
+        } else if let Some((_, meta)) = self.sidecar_target(&path).await {
+            let sidecar = Sidecar::render(&meta);
+            let start = (range.start as usize).min(sidecar.len());
+            let end = (range.end as usize).min(sidecar.len());
+
+            Ok(sidecar[start..end].to_vec())
+        } else {
+            self.storage.read(&path, range).await
+        };
 
         match data {
             Ok(data) => {
@@ -272,30 +911,23 @@ impl NFSFileSystem for OpendalFs {
     ) -> Result<ReadDirResult, nfsstat3> {
         debug!("readdir {:?} {:?} {:?}", dirid, start_after, max_entries);
 
-        let path = self
-            .inode_to_path(dirid)
-            .await
-            .ok_or(nfsstat3::NFS3ERR_NOENT)?;
+        let path = self.inode_to_path(dirid).await?;
 
-        let ds = self
-            .operator
-            .list(&path)
-            .await
-            .map_err(|_| nfsstat3::NFS3ERR_NOENT)?;
+        let children = self.children(&path).await?;
 
         let mut entries = Vec::new();
 
         let mut capture: bool = start_after == 0;
 
-        for de in ds {
-            let id = self.path_to_inode(de.path(), true).await?;
+        for (path, name) in children {
+            let id = self.path_to_inode(&path, true).await?;
 
             if capture {
                 if let Ok(attr) = self.getattr(id).await {
                     entries.push(DirEntry {
                         attr,
                         fileid: id,
-                        name: de.name().trim_end_matches('/').as_bytes().into(),
+                        name: name.as_bytes().into(),
                     });
 
                     if entries.len() >= max_entries {
@@ -313,10 +945,69 @@ impl NFSFileSystem for OpendalFs {
     /// Removes a file.
     /// If not supported dur to readonly file system
     /// this should return Err(nfsstat3::NFS3ERR_ROFS)
-    #[allow(unused)]
     async fn remove(&self, dirid: fileid3, filename: &filename3) -> Result<(), nfsstat3> {
         debug!("remove {:?} {:?}", dirid, filename);
-        return Err(nfsstat3::NFS3ERR_NOTSUPP);
+
+        self.check_writable()?;
+
+        let filename = std::str::from_utf8(&filename.0);
+        let path = self.inode_to_path(dirid).await?;
+
+        if let Ok(filename) = filename {
+            if self.is_version_path(&path) {
+                return Err(nfsstat3::NFS3ERR_ROFS);
+            }
+
+            let path = Path::new(&path).join(filename).display().to_string();
+
+            if self.sidecar_target(&path).await.is_some() {
+                return Err(nfsstat3::NFS3ERR_NOTSUPP);
+            }
+
+            let meta = self.storage.stat(&path).await.map_err(|e| {
+                warn!("unable to get metadata for {:?}: {}", path, e);
+                nfsstat3::NFS3ERR_NOENT
+            })?;
+
+            let target = if meta.is_dir() {
+                format!("{}/", path)
+            } else {
+                path.clone()
+            };
+
+            let (bytes, objects) = if meta.is_dir() {
+                let children = self.storage.list(&target).await.map_err(|e| {
+                    warn!("unable to list {:?}: {}", path, e);
+                    nfsstat3::NFS3ERR_IO
+                })?;
+
+                if !children.is_empty() {
+                    return Err(nfsstat3::NFS3ERR_NOTEMPTY);
+                }
+
+                (0, 0)
+            } else {
+                (meta.content_length(), 1)
+            };
+
+            self.storage.delete(&target).await.map_err(|e| {
+                warn!("unable to remove {:?}: {}", path, e);
+                nfsstat3::NFS3ERR_IO
+            })?;
+
+            if meta.is_dir() {
+                self.inodes.remove_tree(&path);
+            } else {
+                self.inodes.remove(&path);
+            }
+
+            self.quota.release_bytes(bytes);
+            self.quota.release_objects(objects);
+
+            Ok(())
+        } else {
+            Err(nfsstat3::NFS3ERR_NOENT)
+        }
     }
 
     /// Removes a file.
@@ -334,7 +1025,48 @@ impl NFSFileSystem for OpendalFs {
             "rename {:?} {:?} {:?} {:?}",
             from_dirid, from_filename, to_dirid, to_filename
         );
-        return Err(nfsstat3::NFS3ERR_NOTSUPP);
+
+        self.check_writable()?;
+
+        let from_filename = std::str::from_utf8(&from_filename.0);
+        let to_filename = std::str::from_utf8(&to_filename.0);
+        let from_dir = self.inode_to_path(from_dirid).await?;
+        let to_dir = self.inode_to_path(to_dirid).await?;
+
+        if let (Ok(from_filename), Ok(to_filename)) = (from_filename, to_filename) {
+            if self.is_version_path(&from_dir) || self.is_version_path(&to_dir) {
+                return Err(nfsstat3::NFS3ERR_ROFS);
+            }
+
+            let from = Path::new(&from_dir)
+                .join(from_filename)
+                .display()
+                .to_string();
+            let to = Path::new(&to_dir).join(to_filename).display().to_string();
+
+            if self.sidecar_target(&from).await.is_some()
+                || self.sidecar_target(&to).await.is_some()
+            {
+                return Err(nfsstat3::NFS3ERR_NOTSUPP);
+            }
+
+            self.storage.rename(&from, &to).await.map_err(|e| {
+                warn!("unable to rename {:?} to {:?}: {}", from, to, e);
+
+                match e.kind() {
+                    ErrorKind::NotFound => nfsstat3::NFS3ERR_NOENT,
+                    ErrorKind::Unsupported => nfsstat3::NFS3ERR_NOTSUPP,
+                    _ => nfsstat3::NFS3ERR_IO,
+                }
+            })?;
+
+            // Handles of the renamed file keep pointing to it.
+            self.inodes.rename(&from, &to);
+
+            Ok(())
+        } else {
+            Err(nfsstat3::NFS3ERR_NOENT)
+        }
     }
 
     #[allow(unused)]
@@ -345,15 +1077,21 @@ impl NFSFileSystem for OpendalFs {
     ) -> Result<(fileid3, fattr3), nfsstat3> {
         debug!("mkdir {:?} {:?}", dirid, dirname);
 
+        self.check_writable()?;
+
         let dirname = std::str::from_utf8(&dirname.0);
-        let path = self.inode_to_path(dirid).await;
+        let path = self.inode_to_path(dirid).await?;
+
+        if let Ok(dirname) = dirname {
+            if self.is_version_path(&path) {
+                return Err(nfsstat3::NFS3ERR_ROFS);
+            }
 
-        if let (Ok(dirname), Some(path)) = (dirname, path) {
             let path = Path::new(&path).join(dirname);
             let path = path.to_str().ok_or(nfsstat3::NFS3ERR_NOENT)?;
             let ino = self.path_to_inode(&path, true).await?;
 
-            self.operator.create_dir(path).await.map_err(|e| {
+            self.storage.create_dir(path).await.map_err(|e| {
                 warn!("unable to create dir {:?} {:?}: {:?}", dirid, dirname, e);
                 nfsstat3::NFS3ERR_NOENT
             })?;
@@ -0,0 +1,406 @@
+use std::{collections::HashMap, num::NonZeroUsize, sync::Mutex};
+
+use lru::LruCache;
+use nfsserve::nfs::{nfs_fh3, nfsstat3};
+
+/// Inode of the root directory.
+pub const ROOT_INODE: u64 = 1;
+
+/// Default number of paths kept in an [`InodeTable`].
+pub const DEFAULT_CAPACITY: usize = 100_000;
+
+/// Position of the bits of a file id identifying its mount when several
+/// mounts share a server, the inode within the mount using the lower ones.
+pub const MOUNT_SHIFT: u32 = 48;
+
+const INODE_MASK: u64 = (1 << MOUNT_SHIFT) - 1;
+
+/// File id of `ino` in the mount with the given index.
+pub fn mount_fileid(mount: u16, ino: u64) -> u64 {
+    ((mount as u64) << MOUNT_SHIFT) | (ino & INODE_MASK)
+}
+
+/// Mount index and inode within the mount of a [`mount_fileid`].
+pub fn split_fileid(id: u64) -> (u16, u64) {
+    ((id >> MOUNT_SHIFT) as u16, id & INODE_MASK)
+}
+
+/// Inode of `path`, derived from the path so that it can be found again
+/// once evicted from an [`InodeTable`]. Inodes fit in the lower
+/// [`MOUNT_SHIFT`] bits.
+///
+/// The path is hashed with FNV-1a, whose output is fixed, for handles to
+/// stay valid across builds of the server.
+pub fn inode_of(path: &str) -> u64 {
+    let key = key(path);
+    if key.is_empty() {
+        return ROOT_INODE;
+    }
+
+    match fnv1a(format!("/{}", key).as_bytes()) & INODE_MASK {
+        // Reserved for the root and for `start_after` in readdir.
+        0 | ROOT_INODE => 2,
+        ino => ino,
+    }
+}
+
+/// 64-bit FNV-1a hash of `data`.
+fn fnv1a(data: &[u8]) -> u64 {
+    const OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+    const PRIME: u64 = 0x0100_0000_01b3;
+
+    data.iter().fold(OFFSET_BASIS, |hash, byte| {
+        (hash ^ *byte as u64).wrapping_mul(PRIME)
+    })
+}
+
+/// File handle of `ino` at `generation`.
+///
+/// Handles carry no instance component, so that they stay valid across
+/// restarts of the server as inodes are derived from paths. Generations
+/// are not persisted though: after a restart, a handle of a file removed
+/// before it is valid again if a file is created at the same path.
+pub fn file_handle(ino: u64, generation: u64) -> nfs_fh3 {
+    let mut data = Vec::with_capacity(16);
+    data.extend_from_slice(&ino.to_le_bytes());
+    data.extend_from_slice(&generation.to_le_bytes());
+
+    nfs_fh3 { data }
+}
+
+/// Inode and generation of a file handle built by [`file_handle`].
+pub fn parse_file_handle(fh: &nfs_fh3) -> Result<(u64, u64), nfsstat3> {
+    if fh.data.len() != 16 {
+        return Err(nfsstat3::NFS3ERR_BADHANDLE);
+    }
+
+    let ino = u64::from_le_bytes(fh.data[0..8].try_into().unwrap());
+    let generation = u64::from_le_bytes(fh.data[8..16].try_into().unwrap());
+
+    Ok((ino, generation))
+}
+
+/// Path without its leading and trailing `/`, so that `/dir`, `dir/` and
+/// `/dir/` are the same entry.
+fn key(path: &str) -> &str {
+    path.trim_matches('/')
+}
+
+struct Tables {
+    paths: LruCache<u64, String>,
+    inodes: HashMap<String, u64>,
+    /// Inodes of the paths not at [`inode_of`] their path, as renamed paths
+    /// keeping the inode of their former path and paths whose inode was
+    /// taken by another path. Kept apart from `paths` so that their inodes
+    /// are found again once evicted.
+    aliases: LruCache<String, u64>,
+    /// Paths of the inodes in `aliases`.
+    aliased: HashMap<u64, String>,
+    /// Generations of the inodes whose path has been removed or replaced
+    /// most recently, the others being at generation `floor`.
+    generations: LruCache<u64, u64>,
+    /// Highest generation evicted from `generations`, so that a handle of a
+    /// removed file cannot become valid again once its generation is
+    /// forgotten. Handles of the other inodes built at a lower generation go
+    /// stale as well, and are looked up again by clients.
+    floor: u64,
+}
+
+impl Tables {
+    fn generation(&self, ino: u64) -> u64 {
+        self.generations.peek(&ino).copied().unwrap_or(self.floor)
+    }
+
+    fn bump(&mut self, ino: u64) {
+        let generation = self.generation(ino) + 1;
+
+        if let Some((evicted, evicted_generation)) = self.generations.push(ino, generation) {
+            if evicted != ino {
+                self.floor = self.floor.max(evicted_generation);
+            }
+        }
+    }
+
+    fn inode(&self, path: &str) -> u64 {
+        match self.aliases.peek(key(path)) {
+            Some(ino) => *ino,
+            None => inode_of(path),
+        }
+    }
+
+    /// Whether `ino` is given to a path, other than `path`.
+    fn is_taken(&self, ino: u64, path: &str) -> bool {
+        let taken_by = self.paths.peek(&ino).or_else(|| self.aliased.get(&ino));
+
+        ino == 0 || ino == ROOT_INODE || taken_by.is_some_and(|taken| key(taken) != key(path))
+    }
+
+    /// Records that `path` has the inode `ino`.
+    fn alias(&mut self, path: &str, ino: u64) {
+        self.unalias(path);
+
+        if ino == inode_of(path) {
+            return;
+        }
+
+        if let Some((evicted, evicted_ino)) = self.aliases.push(key(path).to_owned(), ino) {
+            // The inode of the evicted path can no longer be found, its
+            // handles go stale rather than reaching another path.
+            self.aliased.remove(&evicted_ino);
+            if self.inodes.get(&evicted) == Some(&evicted_ino) {
+                self.inodes.remove(&evicted);
+                self.paths.pop(&evicted_ino);
+            }
+            self.bump(evicted_ino);
+        }
+        self.aliased.insert(ino, path.to_owned());
+    }
+
+    fn unalias(&mut self, path: &str) {
+        if let Some(ino) = self.aliases.pop(key(path)) {
+            self.aliased.remove(&ino);
+        }
+    }
+
+    /// Caches `path` at `ino`, evicting the least recently used path if
+    /// full.
+    fn put(&mut self, path: &str, ino: u64) {
+        if let Some((evicted, evicted_path)) = self.paths.push(ino, path.to_owned()) {
+            if evicted != ino && self.inodes.get(key(&evicted_path)) == Some(&evicted) {
+                self.inodes.remove(key(&evicted_path));
+            }
+        }
+        self.inodes.insert(key(path).to_owned(), ino);
+    }
+
+    /// Forgets `path`, making the handles of its inode stale.
+    fn forget(&mut self, path: &str) {
+        let ino = self
+            .inodes
+            .remove(key(path))
+            .unwrap_or_else(|| self.inode(path));
+
+        self.paths.pop(&ino);
+        self.unalias(path);
+        self.bump(ino);
+    }
+
+    /// Paths under the directory at `path`, cached or aliased.
+    fn children(&self, path: &str) -> Vec<String> {
+        let prefix = format!("{}/", key(path));
+
+        let mut children: Vec<String> = self
+            .inodes
+            .keys()
+            .chain(self.aliases.iter().map(|(child, _)| child))
+            .filter(|child| child.starts_with(&prefix))
+            .cloned()
+            .collect();
+        children.sort();
+        children.dedup();
+
+        children
+    }
+}
+
+/// Bounded mapping between inodes and paths.
+///
+/// The least recently used paths are evicted once the table is full, the
+/// root is never evicted. Paths keep the trailing `/` they were inserted
+/// with, directories being inserted as listed by the storage.
+///
+/// Paths get the inode derived from them by [`inode_of`], so that it can be
+/// found again once evicted, except when renamed or when that inode is
+/// taken by another path. Such paths are kept in a second table, bounded
+/// the same way, and the handles of their inode go stale once evicted
+/// from it.
+///
+/// Removing or replacing a path bumps the generation of its inode, which
+/// makes the file handles built before stale. Generations are bounded the
+/// same way, the inodes whose generation is evicted being at the highest
+/// generation evicted.
+pub struct InodeTable {
+    tables: Mutex<Tables>,
+}
+
+impl InodeTable {
+    pub fn new(capacity: usize) -> Self {
+        let capacity = NonZeroUsize::new(capacity).unwrap_or(NonZeroUsize::MIN);
+
+        Self {
+            tables: Mutex::new(Tables {
+                paths: LruCache::new(capacity),
+                inodes: HashMap::new(),
+                aliases: LruCache::new(capacity),
+                aliased: HashMap::new(),
+                generations: LruCache::new(capacity),
+                floor: 0,
+            }),
+        }
+    }
+
+    /// Path of `ino`, if still in the table.
+    pub fn path(&self, ino: u64) -> Option<String> {
+        if ino == ROOT_INODE {
+            return Some("/".to_owned());
+        }
+
+        let mut tables = self.tables.lock().unwrap();
+
+        match tables.paths.get(&ino) {
+            Some(path) => Some(path.clone()),
+            None => tables.aliased.get(&ino).cloned(),
+        }
+    }
+
+    /// Inode of `path`, if in the table.
+    pub fn get(&self, path: &str) -> Option<u64> {
+        if key(path).is_empty() {
+            return Some(ROOT_INODE);
+        }
+
+        let mut tables = self.tables.lock().unwrap();
+        let ino = *tables.inodes.get(key(path))?;
+        tables.paths.promote(&ino);
+
+        Some(ino)
+    }
+
+    /// Inode `path` has or would get once inserted.
+    pub fn inode(&self, path: &str) -> u64 {
+        self.tables.lock().unwrap().inode(path)
+    }
+
+    /// Inode of `path`, inserting it if needed.
+    pub fn insert(&self, path: &str) -> u64 {
+        if key(path).is_empty() {
+            return ROOT_INODE;
+        }
+
+        let mut tables = self.tables.lock().unwrap();
+
+        if let Some(ino) = tables.inodes.get(key(path)).copied() {
+            // Directories are also looked up without their trailing `/`.
+            if tables.paths.get(&ino).is_some_and(|p| p.ends_with('/')) {
+                return ino;
+            }
+            tables.put(path, ino);
+
+            return ino;
+        }
+
+        let mut ino = tables.inode(path);
+
+        // Another path has the same inode, the next free one is taken.
+        if tables.is_taken(ino, path) {
+            while tables.is_taken(ino, path) {
+                ino = (ino + 1) & INODE_MASK;
+            }
+            tables.alias(path, ino);
+        }
+
+        tables.put(path, ino);
+
+        ino
+    }
+
+    /// Removes `path`, making its file handles stale.
+    pub fn remove(&self, path: &str) {
+        self.tables.lock().unwrap().forget(path);
+    }
+
+    /// Removes the directory at `path` along with every path under it,
+    /// making their file handles stale.
+    pub fn remove_tree(&self, path: &str) {
+        let mut tables = self.tables.lock().unwrap();
+
+        for child in tables.children(path) {
+            tables.forget(&child);
+        }
+        tables.forget(path);
+    }
+
+    /// Moves the inode of `from` to `to`, along with the inodes of the paths
+    /// under it, so that handles of a renamed file stay valid.
+    pub fn rename(&self, from: &str, to: &str) {
+        if key(from) == key(to) {
+            return;
+        }
+
+        let mut tables = self.tables.lock().unwrap();
+
+        let moves: Vec<(String, String)> = tables
+            .children(from)
+            .into_iter()
+            .map(|child| {
+                let moved = format!("{}{}", key(to), &child[key(from).len()..]);
+                (child, moved)
+            })
+            .chain([(key(from).to_owned(), key(to).to_owned())])
+            .collect();
+
+        for (from, to) in moves {
+            // Replaced by the renamed path.
+            if tables.inodes.contains_key(&to) || tables.aliases.contains(&to) {
+                tables.forget(&to);
+            }
+
+            let ino = tables
+                .inodes
+                .remove(&from)
+                .unwrap_or_else(|| tables.inode(&from));
+            let slash = match tables.paths.pop(&ino) {
+                Some(path) if path.ends_with('/') => "/",
+                _ => "",
+            };
+            let to = format!("{}{}", to, slash);
+
+            tables.unalias(&from);
+            tables.alias(&to, ino);
+            tables.put(&to, ino);
+        }
+    }
+
+    /// Removes every path, as when the mount is gone.
+    pub fn clear(&self) {
+        let mut tables = self.tables.lock().unwrap();
+
+        tables.paths.clear();
+        tables.inodes.clear();
+        tables.aliases.clear();
+        tables.aliased.clear();
+        tables.generations.clear();
+        tables.floor = 0;
+    }
+
+    /// Current generation of `ino`.
+    pub fn generation(&self, ino: u64) -> u64 {
+        self.tables.lock().unwrap().generation(ino)
+    }
+
+    /// Cached directories, most recently used first.
+    pub fn dirs(&self) -> Vec<String> {
+        let tables = self.tables.lock().unwrap();
+
+        tables
+            .paths
+            .iter()
+            .filter(|(_, path)| path.ends_with('/'))
+            .map(|(_, path)| path.clone())
+            .collect()
+    }
+
+    pub fn len(&self) -> usize {
+        self.tables.lock().unwrap().paths.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl Default for InodeTable {
+    fn default() -> Self {
+        Self::new(DEFAULT_CAPACITY)
+    }
+}
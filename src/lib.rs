@@ -1,10 +1,21 @@
+pub mod config;
 pub mod errors;
 mod fs;
+mod inodes;
 mod mount;
 mod multiplex;
 mod nfs;
+mod quota;
+mod registry;
 pub mod schema;
+mod sidecar;
+mod snapshot;
+pub mod storage;
+mod versions;
 
 pub use fs::OpendalFs;
+pub use quota::Quota;
+pub use registry::{MountEntry, MountSource};
+pub use snapshot::snapshot;
 
 pub use multiplex::MultiplexedFs;
@@ -1,8 +1,13 @@
+use crate::mount::Mounter;
+use tokio::process::Command;
+
+pub struct LinuxMounter;
+
 fn build_mount_command(
-    ip: String,
+    ip: &str,
     hostport: u16,
     prefix: &str,
-    mount_path: String,
+    mount_path: &str,
     writable: bool,
     sudo: bool,
 ) -> Command {
@@ -23,6 +28,29 @@ fn build_mount_command(
             "user,noacl,nolock,vers=3,tcp,rsize=131072,actimeo=120,port={hostport},mountport={hostport}"
         ));
     }
-    ret.arg(format!("{}:/", &ip)).arg(mount_path);
+    ret.arg(format!("{}:/{}", ip, prefix)).arg(mount_path);
     ret
 }
+
+impl Mounter for LinuxMounter {
+    fn check() -> bool {
+        std::path::Path::new("/sbin/mount.nfs").exists()
+    }
+
+    fn mount_command(
+        ip: &str,
+        hostport: u16,
+        prefix: &str,
+        mount_path: &str,
+        writable: bool,
+    ) -> Command {
+        build_mount_command(ip, hostport, prefix, mount_path, writable, false)
+    }
+
+    fn umount_command(mount_path: &str) -> Command {
+        let mut cmd = Command::new("umount");
+        cmd.arg(mount_path);
+
+        cmd
+    }
+}
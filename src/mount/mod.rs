@@ -10,6 +10,9 @@ mod windows;
 #[cfg(target_os = "linux")]
 mod linux;
 
+#[cfg(target_os = "linux")]
+pub use linux::LinuxMounter as FsMounter;
+
 use log::{debug, error};
 use tokio::process::Command;
 
@@ -28,7 +28,7 @@ fn build_mount_command(
             "anon,nolock,mtype=soft,fileaccess={},casesensitive,lang=ansi,rsize=128,wsize=128,timeout=60,retry=2",
             if writable { "6" } else { "4" }
         ),
-        &format!("\\\\{ip}\\\\"),
+        &format!("\\\\{ip}\\{prefix}"),
         &format!("{}:", &mount_drive),
     ]);
 
@@ -1,224 +1,1406 @@
 use std::{
-    collections::{hash_map::DefaultHasher, HashMap},
-    hash::{Hash, Hasher},
+    collections::{BTreeMap, HashMap},
+    fs::File,
     path::{Component, Path, PathBuf},
-    sync::Arc,
+    str::FromStr,
+    sync::{
+        atomic::{AtomicBool, AtomicU16, AtomicU64, Ordering},
+        Arc,
+    },
+    time::{Duration, SystemTime, UNIX_EPOCH},
 };
 
 use async_trait::async_trait;
-use bimap::BiMap;
+use globset::{Glob, GlobSet, GlobSetBuilder};
 use log::{debug, error, info, warn};
 use nfsserve::{
-    nfs::{fattr3, fileid3, filename3, ftype3, nfspath3, nfsstat3, nfstime3, sattr3, specdata3},
-    vfs::{NFSFileSystem, ReadDirResult, VFSCapabilities},
+    nfs::{
+        fattr3, fileid3, filename3, ftype3, nfs_fh3, nfspath3, nfsstat3, nfstime3, sattr3,
+        specdata3,
+    },
+    vfs::{DirEntry, NFSFileSystem, ReadDirResult, VFSCapabilities},
 };
 
-use opendal::Operator;
+use opendal::{services::Fs, Operator, Scheme};
 use tokio::sync::RwLock;
 use uuid::Uuid;
 
 use crate::{
-    errors::OpendalMountResult,
+    config::{Config, ReloadPlan},
+    errors::{OpendalMountError, OpendalMountResult},
+    inodes::{self, ROOT_INODE},
     mount::{FsMounter, Mounter},
-    schema::MountedFs,
+    quota::Quota,
+    registry::{MountEntry, MountSource, Registry},
+    schema::{MountOptions, MountedFs, RestoreFailure, Snapshot, Tier, TrashItem},
+    snapshot,
+    storage::{
+        ArchiveStorage, CompressedStorage, DedupStorage, EncryptedStorage, MirrorStorage,
+        OverlayStorage, Storage, SubPathStorage, TierPolicy, TieredStorage, TrashStorage,
+    },
+    OpendalFs,
 };
 
 struct MountedOperator {
     mount_point: String,
+    /// Index of the mount in the high bits of its file ids.
+    index: u16,
+    /// Path of the export of the mount on the NFS server.
+    prefix: String,
     op: Operator,
+    /// Root of the mount within the operator.
+    root: String,
+    options: MountOptions,
+    fs: Arc<OpendalFs>,
+    /// Storage of the mount under its sub path, holding the files as the
+    /// layers above it encode them.
+    stored: Arc<dyn Storage>,
+    mirror: Option<Arc<MirrorStorage>>,
+    compressed: Option<Arc<CompressedStorage>>,
+    dedup: Option<Arc<DedupStorage>>,
+    tiered: Option<Arc<TieredStorage>>,
+    /// Layer confining the paths of the mount, above the tiers.
+    sub_path: Option<Arc<SubPathStorage>>,
+    trash: Option<Arc<TrashStorage>>,
+    /// Local directory of the files kept off the operator.
+    scratch: Option<PathBuf>,
+    origin: Origin,
 }
 
+/// How a mount was created, which decides whether it is saved. Entries
+/// hold the options as requested, before the mount adjusts them.
+enum Origin {
+    /// From operators built by the caller
+    Direct,
+    /// Through [`MultiplexedFs::mount`], saved in the state file
+    Saved(MountEntry),
+    /// From the config file, created again from it on startup
+    Declared(MountEntry),
+}
+
+/// Matcher of file names for the `patterns` globs.
+fn glob_set(patterns: &[String]) -> OpendalMountResult<GlobSet> {
+    let mut builder = GlobSetBuilder::new();
+
+    for pattern in patterns {
+        let glob = Glob::new(pattern)
+            .map_err(|_| OpendalMountError::InvalidPattern(pattern.to_owned()))?;
+        builder.add(glob);
+    }
+
+    builder
+        .build()
+        .map_err(|e| OpendalMountError::InvalidPattern(e.to_string()))
+}
+
+/// Checks that `name` can be the directory of a mount in the root.
+fn mount_name(name: &str) -> OpendalMountResult<String> {
+    if name.is_empty() || name == "." || name == ".." || name.contains('/') {
+        return Err(OpendalMountError::InvalidMountName(name.to_owned()));
+    }
+
+    Ok(name.to_owned())
+}
+
+/// File locked by a server in its scratch directory while it runs.
+const SCRATCH_LOCK: &str = ".lock";
+
+/// Local directory holding the scratch directories of the servers.
+fn scratch_root() -> PathBuf {
+    std::env::temp_dir().join("opendal-mount")
+}
+
+/// Local directory of a server holding, for each mount, the files that
+/// never reach its operator.
+///
+/// It is locked while the server runs, for [`MultiplexedFs::clean_scratch`]
+/// to only remove the directories of servers gone, and removed along with
+/// the server.
+struct ScratchDir {
+    path: PathBuf,
+    lock: std::sync::Mutex<Option<File>>,
+}
+
+impl ScratchDir {
+    fn new() -> Self {
+        Self {
+            path: scratch_root().join(Uuid::new_v4().to_string()),
+            lock: std::sync::Mutex::new(None),
+        }
+    }
+
+    /// Creates a scratch directory for a mount, named apart from its prefix
+    /// for a mount being replaced not to share it with the new one.
+    fn mount_dir(&self) -> std::io::Result<PathBuf> {
+        let mut lock = self.lock.lock().unwrap();
+
+        if lock.is_none() {
+            std::fs::create_dir_all(&self.path)?;
+
+            let file = File::create(self.path.join(SCRATCH_LOCK))?;
+            file.try_lock()?;
+            *lock = Some(file);
+        }
+
+        let dir = self.path.join(Uuid::new_v4().to_string());
+        std::fs::create_dir_all(&dir)?;
+
+        Ok(dir)
+    }
+}
+
+impl Drop for ScratchDir {
+    fn drop(&mut self) {
+        let locked = self.lock.get_mut().is_ok_and(|lock| lock.is_some());
+
+        if locked {
+            if let Err(e) = std::fs::remove_dir_all(&self.path) {
+                warn!("unable to remove {}: {}", self.path.display(), e);
+            }
+        }
+    }
+}
+
+/// Whether there is a file under the directory at `dir`.
+fn has_files(dir: &Path) -> bool {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return false;
+    };
+
+    entries.flatten().any(|entry| match entry.file_type() {
+        Ok(file_type) if file_type.is_dir() => has_files(&entry.path()),
+        Ok(_) => true,
+        Err(_) => false,
+    })
+}
+
+fn scratch_operator(dir: &Path) -> OpendalMountResult<Operator> {
+    let op = Operator::new(Fs::default().root(&dir.display().to_string()))?.finish();
+
+    Ok(op)
+}
+
+pub(crate) fn build_operator(
+    service: String,
+    parameters: HashMap<String, String>,
+) -> OpendalMountResult<Operator> {
+    let scheme =
+        Scheme::from_str(&service).map_err(|_| OpendalMountError::UnsupportedScheme(service))?;
+
+    let op = Operator::via_map(scheme, parameters).map_err(|e| {
+        error!("operator creation failure: {}", e);
+        OpendalMountError::OperatorCreateError(format!("{}", e))
+    })?;
+
+    Ok(op)
+}
+
+/// What NFS calls need to reach a mount from one of its file ids.
+struct Route {
+    prefix: String,
+    fs: Arc<OpendalFs>,
+}
+
+/// Operators mounted behind a single NFS server.
+///
+/// Each operator is exported at `/<prefix>`, `prefix` being generated when
+/// it is mounted, so that an OS mount only sees its own operator.
+///
+/// With a root mount, the server is instead mounted once and operators are
+/// only added as subdirectories of its root, named after their mount point.
 #[derive(Clone)]
 pub struct MultiplexedFs {
     ip: String,
     port: u16,
     ops: Arc<RwLock<HashMap<String, MountedOperator>>>,
-    inodes: Arc<RwLock<BiMap<u64, String>>>,
+    /// Mounts by index, read by the synchronous file handle conversions.
+    routes: Arc<std::sync::RwLock<BTreeMap<u16, Route>>>,
+    /// Where to start looking for a free index. Indices are not reused right
+    /// away, for the handles of an unmounted operator to go stale rather
+    /// than reach the next one.
+    next_index: Arc<AtomicU16>,
+    /// Whether every mount is read only, updated as operators are mounted
+    /// and unmounted for `capabilities` not to wait for `ops`.
+    read_only: Arc<AtomicBool>,
+    /// Modification time of the root listing the mounts, in nanoseconds
+    /// since the epoch, for clients to see mounts come and go.
+    root_mtime: Arc<AtomicU64>,
+    /// Where the whole server is mounted, if mounted once.
+    root_mount: Option<String>,
+    registry: Option<Arc<Registry>>,
+    /// Config file declaring mounts, applied by [`MultiplexedFs::reload_config`].
+    config_file: Option<PathBuf>,
+    /// Saved mounts that could not be restored, kept in the registry until
+    /// unmounted or mounted again.
+    failed: Arc<RwLock<Vec<(MountEntry, String)>>>,
+    /// Mounts of the config file that could not be mounted, until mounted
+    /// or no longer declared.
+    failed_declared: Arc<RwLock<Vec<(MountEntry, String)>>>,
+    scratch: Arc<ScratchDir>,
 }
 
 impl MultiplexedFs {
     pub fn new(ip: &str, port: u16) -> Self {
-        let mut inodes = BiMap::new();
-        inodes.insert(1, "/".to_string());
-
         Self {
             ip: ip.to_owned(),
             port,
             ops: Arc::new(RwLock::new(HashMap::new())),
-            inodes: Arc::new(RwLock::new(inodes)),
+            routes: Arc::new(std::sync::RwLock::new(BTreeMap::new())),
+            next_index: Arc::new(AtomicU16::new(1)),
+            read_only: Arc::new(AtomicBool::new(false)),
+            root_mtime: Arc::new(AtomicU64::new(now_nanos())),
+            root_mount: None,
+            registry: None,
+            config_file: None,
+            failed: Arc::new(RwLock::new(Vec::new())),
+            failed_declared: Arc::new(RwLock::new(Vec::new())),
+            scratch: Arc::new(ScratchDir::new()),
+        }
+    }
+
+    /// Removes the scratch files left by servers no longer running, which
+    /// never reached their operators.
+    pub fn clean_scratch() {
+        let Ok(dirs) = std::fs::read_dir(scratch_root()) else {
+            return;
+        };
+
+        for dir in dirs.flatten() {
+            let path = dir.path();
+
+            // Running servers hold the lock of their directory.
+            let stale = match File::open(path.join(SCRATCH_LOCK)) {
+                Ok(lock) => lock.try_lock().is_ok(),
+                Err(e) => e.kind() == std::io::ErrorKind::NotFound,
+            };
+            if !stale {
+                continue;
+            }
+
+            match std::fs::remove_dir_all(&path) {
+                Ok(()) => info!("removed stale scratch files {}", path.display()),
+                Err(e) => warn!("unable to remove {}: {}", path.display(), e),
+            }
+        }
+    }
+
+    /// Saves the mounts created with [`MultiplexedFs::mount`] to `path`, to
+    /// be restored with [`MultiplexedFs::restore`].
+    pub fn with_state_file(mut self, path: &Path) -> Self {
+        self.registry = Some(Arc::new(Registry::new(path)));
+        self
+    }
+
+    /// Reloads the mounts declared in the config file at `path` with
+    /// [`MultiplexedFs::reload_config`].
+    pub fn with_config_file(mut self, path: &Path) -> Self {
+        self.config_file = Some(path.to_owned());
+        self
+    }
+
+    /// Mounts the server once at `mount_point`, operators being mounted as
+    /// subdirectories of it rather than with an OS mount each.
+    pub fn with_root_mount(mut self, mount_point: &str) -> Self {
+        self.root_mount = Some(mount_point.to_owned());
+        self
+    }
+
+    /// OS mount of the root, to be done once the NFS server is listening.
+    pub async fn mount_root(&self) -> OpendalMountResult<()> {
+        if let Some(root_mount) = &self.root_mount {
+            info!("Mounting root at {}", root_mount);
+
+            FsMounter::mount(&self.ip, self.port, "", root_mount, true).await?;
+        }
+
+        Ok(())
+    }
+
+    /// Mounts the operators of `entry`, saving it in the state file if any.
+    pub async fn mount(&self, entry: MountEntry) -> OpendalMountResult<()> {
+        let mount_point = entry.mount_point.clone();
+
+        self.mount_entry(entry, false).await?;
+
+        self.failed
+            .write()
+            .await
+            .retain(|(entry, _)| entry.mount_point != mount_point);
+
+        self.save_state().await
+    }
+
+    /// Mounts the operators of `entry`, declared in the config file and thus
+    /// not saved. A failure is reported by
+    /// [`MultiplexedFs::restore_failures`] until the mount succeeds or is no
+    /// longer declared.
+    pub async fn mount_declared(&self, entry: MountEntry) -> OpendalMountResult<()> {
+        let result = self.mount_entry(entry.clone(), true).await;
+
+        self.record_declared(entry, result.as_ref().err()).await;
+
+        result
+    }
+
+    /// Records whether the declared mount of `entry` failed with `error`.
+    async fn record_declared(&self, entry: MountEntry, error: Option<&OpendalMountError>) {
+        let mut failed = self.failed_declared.write().await;
+        failed.retain(|(failed, _)| failed.mount_point != entry.mount_point);
+
+        if let Some(e) = error {
+            failed.push((entry, e.to_string()));
+        }
+    }
+
+    async fn mount_entry(&self, entry: MountEntry, declared: bool) -> OpendalMountResult<()> {
+        if self.ops.read().await.contains_key(&entry.mount_point) {
+            return Err(OpendalMountError::AlreadyMounted(entry.mount_point));
+        }
+
+        let mounted = self.build_entry(entry, declared).await?;
+
+        self.install(mounted).await
+    }
+
+    /// Builds the mount of `entry`, without serving it.
+    async fn build_entry(
+        &self,
+        entry: MountEntry,
+        declared: bool,
+    ) -> OpendalMountResult<MountedOperator> {
+        let (op, storage): (Operator, Arc<dyn Storage>) = match &entry.source {
+            MountSource::Operator(input) => {
+                let op = build_operator(input.service.clone(), input.parameters.clone())?;
+
+                (op.clone(), Arc::new(op))
+            }
+            MountSource::Overlay { lower, upper } => {
+                let lower = build_operator(lower.service.clone(), lower.parameters.clone())?;
+                let upper = build_operator(upper.service.clone(), upper.parameters.clone())?;
+
+                let storage = OverlayStorage::new(Arc::new(lower), Arc::new(upper.clone()));
+
+                (upper, Arc::new(storage))
+            }
+        };
+
+        let mount_point = entry.mount_point.clone();
+        let options = entry.options.clone();
+        let origin = if declared {
+            Origin::Declared(entry)
+        } else {
+            Origin::Saved(entry)
+        };
+
+        self.build(&mount_point, op, storage, options, origin).await
+    }
+
+    /// Mounts declared in the config file, as declared.
+    pub async fn declared(&self) -> Vec<MountEntry> {
+        let mut entries: Vec<MountEntry> = self
+            .ops
+            .read()
+            .await
+            .values()
+            .filter_map(|op| match &op.origin {
+                Origin::Declared(entry) => Some(entry.clone()),
+                _ => None,
+            })
+            .collect();
+        entries.sort_by(|a, b| a.mount_point.cmp(&b.mount_point));
+
+        entries
+    }
+
+    /// Brings the mounts declared in the config file to `declared`, leaving
+    /// the others alone. With `dry_run`, the plan is only logged.
+    pub async fn reload(&self, declared: &[MountEntry], dry_run: bool) -> ReloadPlan {
+        let plan = ReloadPlan::diff(&self.declared().await, declared);
+
+        if !dry_run {
+            self.failed_declared.write().await.retain(|(failed, _)| {
+                declared
+                    .iter()
+                    .any(|entry| entry.mount_point == failed.mount_point)
+            });
         }
+
+        if plan.is_empty() {
+            info!("Reload: nothing to do, {}", plan);
+            return plan;
+        }
+
+        info!(
+            "Reload plan{}:\n{}",
+            if dry_run { " (dry run)" } else { "" },
+            plan
+        );
+
+        if dry_run {
+            return plan;
+        }
+
+        for mount_point in &plan.remove {
+            if let Err(e) = self.release(mount_point, false).await {
+                error!("Failed to unmount {}: {}", mount_point, e);
+            }
+        }
+
+        for entry in &plan.remount {
+            self.remount(entry).await;
+        }
+
+        for entry in &plan.add {
+            if let Err(e) = self.mount_declared(entry.clone()).await {
+                error!("Failed to mount {}: {}", entry.mount_point, e);
+            }
+        }
+
+        plan
     }
 
-    async fn inode_to_path(&self, inode: u64) -> Option<String> {
-        self.inodes.read().await.get_by_left(&inode).cloned()
+    /// Replaces the declared mount at the mount point of `entry` with the
+    /// mount of `entry`. The new mount is built before the current one is
+    /// released, which is kept when it cannot be, and mounted again when
+    /// the new one fails to be served.
+    async fn remount(&self, entry: &MountEntry) {
+        let mount_point = &entry.mount_point;
+
+        let mounted = match self.build_entry(entry.clone(), true).await {
+            Ok(mounted) => mounted,
+            Err(e) => {
+                error!("Failed to mount {}, keeping it as is: {}", mount_point, e);
+
+                self.record_declared(entry.clone(), Some(&e)).await;
+                return;
+            }
+        };
+
+        let current = self
+            .with_mounted(mount_point, |op| match &op.origin {
+                Origin::Declared(entry) => Some(entry.clone()),
+                _ => None,
+            })
+            .await
+            .ok()
+            .flatten();
+
+        if let Err(e) = self.release(mount_point, false).await {
+            error!("Failed to unmount {}: {}", mount_point, e);
+
+            Self::close(mounted).await;
+            return;
+        }
+
+        match self.install(mounted).await {
+            Ok(()) => self.record_declared(entry.clone(), None).await,
+            Err(e) => {
+                error!("Failed to mount {}: {}", mount_point, e);
+
+                self.record_declared(entry.clone(), Some(&e)).await;
+
+                if let Some(current) = current {
+                    info!("Mounting {} back as it was", mount_point);
+
+                    if let Err(e) = self.mount_entry(current, true).await {
+                        error!("Failed to mount {} back: {}", mount_point, e);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Reads the config file again and brings the mounts it declares to it.
+    /// With `dry_run`, the plan is only returned.
+    pub async fn reload_config(&self, dry_run: bool) -> OpendalMountResult<ReloadPlan> {
+        let path = self
+            .config_file
+            .as_ref()
+            .ok_or(OpendalMountError::NoConfigFile())?;
+
+        info!("Reloading {}", path.display());
+        let config = Config::load(path)?;
+
+        Ok(self.reload(&config.entries(), dry_run).await)
     }
 
-    async fn path_to_inode(&self, path: &str, insert: bool) -> Result<u64, nfsstat3> {
-        let ino = self.inodes.read().await.get_by_right(path).copied();
+    /// Mounts again the mounts of the state file, those that fail being
+    /// reported by [`MultiplexedFs::restore_failures`].
+    pub async fn restore(&self) -> OpendalMountResult<()> {
+        let Some(registry) = &self.registry else {
+            return Ok(());
+        };
 
-        match ino {
-            Some(ino) => Ok(ino),
-            None if insert => {
-                let mut hasher = DefaultHasher::new();
-                path.hash(&mut hasher);
-                let ino = hasher.finish();
+        for entry in registry.load().await? {
+            info!("Restoring mount at {}", entry.mount_point);
 
-                let mut inodes = self.inodes.write().await;
-                (*inodes).insert(ino, path.to_owned());
+            if let Err(e) = self.mount_entry(entry.clone(), false).await {
+                error!("Failed to restore mount at {}: {}", entry.mount_point, e);
 
-                Ok(ino)
+                self.failed.write().await.push((entry, e.to_string()));
             }
-            _ => Err(nfsstat3::NFS3ERR_NOENT),
         }
+
+        self.save_state().await
+    }
+
+    /// Saved mounts that could not be restored and declared mounts that
+    /// could not be mounted.
+    pub async fn restore_failures(&self) -> Vec<RestoreFailure> {
+        let failed = self.failed.read().await;
+        let failed_declared = self.failed_declared.read().await;
+
+        failed
+            .iter()
+            .chain(failed_declared.iter())
+            .map(|(entry, error)| RestoreFailure {
+                mount_point: entry.mount_point.clone(),
+                error: error.clone(),
+            })
+            .collect()
+    }
+
+    /// Writes the saved mounts to the state file.
+    async fn save_state(&self) -> OpendalMountResult<()> {
+        let Some(registry) = &self.registry else {
+            return Ok(());
+        };
+
+        // Listed once the previous saves are done, for the state file to end
+        // up with the latest mounts.
+        registry
+            .save(async {
+                let mut entries: Vec<MountEntry> = self
+                    .ops
+                    .read()
+                    .await
+                    .values()
+                    .filter_map(|op| match &op.origin {
+                        Origin::Saved(entry) => Some(MountEntry {
+                            index: Some(op.index),
+                            ..entry.clone()
+                        }),
+                        _ => None,
+                    })
+                    .collect();
+
+                entries.extend(
+                    self.failed
+                        .read()
+                        .await
+                        .iter()
+                        .map(|(entry, _)| entry.clone()),
+                );
+                entries.sort_by(|a, b| a.mount_point.cmp(&b.mount_point));
+
+                entries
+            })
+            .await
+    }
+
+    pub async fn mount_operator(
+        &self,
+        mount_point: &str,
+        op: Operator,
+        options: MountOptions,
+    ) -> OpendalMountResult<()> {
+        self.mount_storage(
+            mount_point,
+            op.clone(),
+            Arc::new(op),
+            options,
+            Origin::Direct,
+        )
+        .await
+    }
+
+    /// Mounts the union of a read only `lower` operator and a writable
+    /// `upper` one.
+    pub async fn mount_overlay(
+        &self,
+        mount_point: &str,
+        lower: Operator,
+        upper: Operator,
+        options: MountOptions,
+    ) -> OpendalMountResult<()> {
+        let storage = OverlayStorage::new(Arc::new(lower), Arc::new(upper.clone()));
+
+        self.mount_storage(
+            mount_point,
+            upper,
+            Arc::new(storage),
+            options,
+            Origin::Direct,
+        )
+        .await
+    }
+
+    /// Mounts, read only, the snapshot at `prefix` of `op`, its files being
+    /// decoded with the `options` of the snapshotted mount. The snapshot
+    /// holding the files under the sub path of that mount, `options` may
+    /// not have a sub path.
+    pub async fn mount_snapshot(
+        &self,
+        mount_point: &str,
+        op: Operator,
+        prefix: &str,
+        options: MountOptions,
+    ) -> OpendalMountResult<()> {
+        if let Some(sub_path) = options.sub_path {
+            return Err(OpendalMountError::InvalidSubPath(sub_path));
+        }
+
+        let options = MountOptions {
+            read_only: true,
+            sub_path: Some(prefix.to_owned()),
+            ..options
+        };
+
+        self.mount_operator(mount_point, op, options).await
     }
 
-    async fn path_to_attr(&self, ino: u64, path: &str) -> Result<fattr3, nfsstat3> {
-        let meta = self.operator.stat(&path).await.map_err(|e| {
-            warn!("unable to get metadata for {:?}: {}", path, e);
-            nfsstat3::NFS3ERR_NOENT
-        })?;
+    /// Mounts `storage`, `op` being the operator reported by the API.
+    async fn mount_storage(
+        &self,
+        mount_point: &str,
+        op: Operator,
+        storage: Arc<dyn Storage>,
+        options: MountOptions,
+        origin: Origin,
+    ) -> OpendalMountResult<()> {
+        if self.ops.read().await.contains_key(mount_point) {
+            return Err(OpendalMountError::AlreadyMounted(mount_point.to_owned()));
+        }
+
+        let mounted = self
+            .build(mount_point, op, storage, options, origin)
+            .await?;
+
+        self.install(mounted).await
+    }
+
+    /// Builds the mount of `storage` at `mount_point`, without serving it.
+    /// Its index is given by [`MultiplexedFs::install`].
+    async fn build(
+        &self,
+        mount_point: &str,
+        op: Operator,
+        mut storage: Arc<dyn Storage>,
+        mut options: MountOptions,
+        origin: Origin,
+    ) -> OpendalMountResult<MountedOperator> {
+        let prefix = match &self.root_mount {
+            Some(_) => mount_name(mount_point)?,
+            None => Uuid::new_v4().to_string(),
+        };
+
+        let mut root = op.info().root().to_owned();
+
+        let tiered = match &options.tiering {
+            Some(tiering) => {
+                let cold = build_operator(
+                    tiering.cold.service.clone(),
+                    tiering.cold.parameters.clone(),
+                )?;
+                let policy = TierPolicy {
+                    max_age: tiering.demote_after_secs.map(Duration::from_secs),
+                    max_hot_bytes: tiering.max_hot_bytes,
+                    promote: tiering.promote,
+                };
+                let tiered = Arc::new(TieredStorage::new(storage, Arc::new(cold), policy));
+                tiered.spawn_sweeper();
+
+                storage = tiered.clone();
+                Some(tiered)
+            }
+            None => None,
+        };
+
+        let mirror = match &options.mirror {
+            Some(mirror) => {
+                let secondary = build_operator(
+                    mirror.secondary.service.clone(),
+                    mirror.secondary.parameters.clone(),
+                )?;
+                let mirror = Arc::new(MirrorStorage::new(
+                    storage,
+                    Arc::new(secondary),
+                    mirror.policy,
+                ));
+
+                storage = mirror.clone();
+                Some(mirror)
+            }
+            None => None,
+        };
+
+        if let Some(archive) = options.archive.clone() {
+            storage = Arc::new(ArchiveStorage::open(storage, &archive).await?);
+
+            root = format!(
+                "{}/{}",
+                root.trim_end_matches('/'),
+                archive.trim_matches('/')
+            );
+            options.read_only = true;
+        }
+
+        let sub_path = match &options.sub_path {
+            Some(sub_path) => {
+                let sub_path = SubPathStorage::new(storage, sub_path)
+                    .map_err(|_| OpendalMountError::InvalidSubPath(sub_path.to_owned()))?;
+
+                root = format!("{}/{}", root.trim_end_matches('/'), sub_path.prefix());
+
+                let sub_path = Arc::new(sub_path);
+                storage = sub_path.clone();
+                Some(sub_path)
+            }
+            None => None,
+        };
+
+        let stored = storage.clone();
+
+        if let Some(key_file) = &options.encryption_key_file {
+            storage = Arc::new(EncryptedStorage::from_key_file(
+                storage,
+                Path::new(key_file),
+            )?);
+        }
+
+        let compressed = match options.compression {
+            Some(compression) => {
+                let compressed = Arc::new(CompressedStorage::new(storage, compression));
+                compressed.spawn_sealer();
+
+                storage = compressed.clone();
+                Some(compressed)
+            }
+            None => None,
+        };
 
-        let kind = if meta.is_dir() {
-            ftype3::NF3DIR
+        let dedup = if options.dedup {
+            let dedup = Arc::new(DedupStorage::new(storage));
+            dedup.spawn_collector();
+            dedup.spawn_flusher();
+
+            storage = dedup.clone();
+            Some(dedup)
         } else {
-            ftype3::NF3REG
+            None
         };
 
-        let mtime = if let Some(mtime) = meta.last_modified() {
-            nfstime3 {
-                seconds: mtime.timestamp() as u32,
-                nseconds: 0,
+        let trash = match &options.trash {
+            Some(trash) => {
+                let trash = Arc::new(TrashStorage::new(
+                    storage,
+                    Duration::from_secs(trash.retention_secs),
+                ));
+                trash.spawn_sweeper();
+
+                storage = trash.clone();
+                Some(trash)
             }
+            None => None,
+        };
+
+        let mut fs = OpendalFs::with_storage(storage)
+            .with_inode_capacity(
+                options
+                    .max_inodes
+                    .map_or(inodes::DEFAULT_CAPACITY, |max| max as usize),
+            )
+            .with_quota(Quota::new(options.max_bytes, options.max_objects))
+            .with_versions(options.versions)
+            .with_sidecars(options.sidecars)
+            .with_read_only(options.read_only);
+
+        let scratch = if options.local_only.is_empty() {
+            None
         } else {
-            nfstime3::default()
+            let dir = self.scratch.mount_dir()?;
+            fs = fs.with_local_only(
+                glob_set(&options.local_only)?,
+                Arc::new(scratch_operator(&dir)?),
+            );
+
+            Some(dir)
         };
 
-        let mode = if meta.is_dir() { 0o777 } else { 0o755 };
-
-        Ok(fattr3 {
-            ftype: kind,
-            mode,
-            nlink: 0,
-            uid: 507,
-            gid: 507,
-            size: meta.content_length(),
-            used: meta.content_length(),
-            rdev: specdata3::default(),
-            fsid: 0,
-            fileid: ino,
-            atime: mtime,
-            mtime,
-            ctime: mtime,
+        if !options.ignore.is_empty() {
+            fs = fs.with_ignored(glob_set(&options.ignore)?)?;
+        }
+
+        fs.measure_usage().await?;
+
+        Ok(MountedOperator {
+            mount_point: mount_point.to_owned(),
+            index: 0,
+            prefix,
+            op,
+            root,
+            options,
+            fs: Arc::new(fs),
+            stored,
+            mirror,
+            compressed,
+            dedup,
+            tiered,
+            sub_path,
+            trash,
+            scratch,
+            origin,
         })
     }
 
-    pub async fn mount_operator(&self, mount_point: &str, op: Operator) -> OpendalMountResult<()> {
-        let mut ops = self.ops.write().await;
+    /// Serves `mounted`, mounting it on the OS unless the server is mounted
+    /// once.
+    async fn install(&self, mut mounted: MountedOperator) -> OpendalMountResult<()> {
+        let mount_point = mounted.mount_point.clone();
+        let prefix = mounted.prefix.clone();
+        let writable = !mounted.options.read_only;
 
-        let prefix = Uuid::new_v4().to_string();
+        info!("Mounting {} at {}", mounted.op.info().name(), mount_point);
+        let rejected = 'register: {
+            let mut ops = self.ops.write().await;
 
-        info!("Mounting{} at {}", op.info().name(), mount_point);
-        ops.insert(
-            mount_point.to_owned(),
-            MountedOperator {
-                mount_point: mount_point.to_owned(),
-                op,
-            },
-        );
+            if ops.contains_key(&mount_point) {
+                let e = OpendalMountError::AlreadyMounted(mount_point.clone());
+                break 'register Some((mounted, e));
+            }
 
-        FsMounter::mount(&self.ip, self.port, &prefix, mount_point, true).await?;
+            let mut routes = self.routes.write().unwrap();
+
+            // A restored mount gets its index back, for the handles given out
+            // before the restart to reach it again.
+            let saved = match &mounted.origin {
+                Origin::Saved(entry) => entry
+                    .index
+                    .filter(|index| *index != 0 && !routes.contains_key(index)),
+                _ => None,
+            };
+            let index = match saved {
+                Some(index) => index,
+                None => {
+                    let first = self.next_index.load(Ordering::Relaxed) as u32;
+                    let Some(index) = (0..u16::MAX as u32)
+                        .map(|i| ((first - 1 + i) % u16::MAX as u32 + 1) as u16)
+                        .find(|index| !routes.contains_key(index))
+                    else {
+                        break 'register Some((mounted, OpendalMountError::TooManyMounts()));
+                    };
+                    self.next_index
+                        .store(index % u16::MAX + 1, Ordering::Relaxed);
+
+                    index
+                }
+            };
+            routes.insert(
+                index,
+                Route {
+                    prefix: prefix.clone(),
+                    fs: mounted.fs.clone(),
+                },
+            );
+
+            mounted.index = index;
+            ops.insert(mount_point.clone(), mounted);
+
+            self.update_read_only(&ops);
+            self.touch_root();
+
+            None
+        };
+
+        if let Some((mounted, e)) = rejected {
+            Self::close(mounted).await;
+
+            return Err(e);
+        }
+
+        if self.root_mount.is_some() {
+            return Ok(());
+        }
+
+        // The lock is released as the OS mount goes through the NFS server.
+        if let Err(e) = FsMounter::mount(&self.ip, self.port, &prefix, &mount_point, writable).await
+        {
+            error!("Rolling back mount at {}: {}", mount_point, e);
+
+            if let Some(op) = self.unregister(&mount_point).await {
+                Self::close(op).await;
+            }
+
+            return Err(e.into());
+        }
 
         Ok(())
     }
 
+    /// Unmounts the operator mounted at `mount_point`, flushing its pending
+    /// writes and forgetting its inodes. The mount is removed from the state
+    /// file, as is a saved mount that failed to be restored there.
+    ///
+    /// Fails with [`OpendalMountError::Busy`] while sidecars are still being
+    /// written or local-only files were never renamed, as they would be
+    /// lost.
     pub async fn umount(&self, mount_point: &str) -> OpendalMountResult<()> {
-        FsMounter::umount(mount_point).await?;
+        let forgotten = {
+            let mut failed = self.failed.write().await;
+            let len = failed.len();
+            failed.retain(|(entry, _)| entry.mount_point != mount_point);
+
+            failed.len() != len
+        };
+
+        if !forgotten {
+            self.release(mount_point, false).await?;
+        }
+
+        self.save_state().await
+    }
+
+    /// Unmounts the operator mounted at `mount_point`, keeping it in the
+    /// state file. Unless `force`, a mount with writes that cannot be flushed
+    /// is left mounted.
+    async fn release(&self, mount_point: &str, force: bool) -> OpendalMountResult<()> {
+        let (fs, scratch) = self
+            .with_mounted(mount_point, |op| (op.fs.clone(), op.scratch.clone()))
+            .await?;
+
+        if !force && (fs.has_pending_writes().await || scratch.is_some_and(|dir| has_files(&dir))) {
+            return Err(OpendalMountError::Busy(mount_point.to_owned()));
+        }
+
+        if self.root_mount.is_none() {
+            FsMounter::umount(mount_point).await?;
+        }
+
+        if let Some(op) = self.unregister(mount_point).await {
+            Self::close(op).await;
+        }
 
         Ok(())
     }
 
+    /// Removes the mount at `mount_point`, its file ids resolving to
+    /// nothing from then on.
+    async fn unregister(&self, mount_point: &str) -> Option<MountedOperator> {
+        let mut ops = self.ops.write().await;
+
+        let op = ops.remove(mount_point)?;
+        self.routes.write().unwrap().remove(&op.index);
+        self.update_read_only(&ops);
+        self.touch_root();
+
+        Some(op)
+    }
+
+    /// Keeps the `read_only` flag in line with `ops`, with its lock held.
+    fn update_read_only(&self, ops: &HashMap<String, MountedOperator>) {
+        let read_only = !ops.is_empty() && ops.values().all(|op| op.options.read_only);
+
+        self.read_only.store(read_only, Ordering::Relaxed);
+    }
+
+    /// Bumps the modification time of the root, always forward so that a
+    /// change is seen even within the same clock tick.
+    fn touch_root(&self) {
+        let now = now_nanos();
+
+        let _ = self
+            .root_mtime
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |mtime| {
+                Some(now.max(mtime + 1))
+            });
+    }
+
+    /// Releases what a mount holds once it is no longer served.
+    async fn close(op: MountedOperator) {
+        if let Some(dedup) = &op.dedup {
+            dedup.flush_all().await;
+        }
+
+        if let Some(compressed) = &op.compressed {
+            compressed.seal_all().await;
+        }
+
+        if let Some(mirror) = &op.mirror {
+            mirror.flush().await;
+        }
+
+        op.fs.purge().await;
+
+        if let Some(scratch) = &op.scratch {
+            if has_files(scratch) {
+                warn!("discarding local-only files of {}", op.mount_point);
+            }
+
+            match tokio::fs::remove_dir_all(scratch).await {
+                Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+                    warn!(
+                        "unable to remove scratch files of {}: {}",
+                        op.mount_point, e
+                    )
+                }
+                _ => {}
+            }
+        }
+    }
+
     pub async fn umount_all(&self) -> OpendalMountResult<()> {
         debug!("Unmounting all operators at {}:{}", self.ip, self.port);
 
-        let ops = self.ops.read().await;
+        let mount_points: Vec<String> = self.ops.read().await.keys().cloned().collect();
 
-        for (mount_point, _) in ops.iter() {
-            match self.umount(mount_point).await {
+        for mount_point in mount_points.iter() {
+            match self.release(mount_point, true).await {
                 Ok(_) => info!("Unmounted {}", mount_point),
                 Err(e) => error!("Failed to unmount {}: {}", mount_point, e),
             }
         }
 
+        if let Some(root_mount) = &self.root_mount {
+            FsMounter::umount(root_mount).await?;
+        }
+
         Ok(())
     }
 
-    pub async fn mounted_operators(&self) -> Vec<MountedFs> {
+    /// Applies `f` to the operator mounted at `mount_point`.
+    async fn with_mounted<T>(
+        &self,
+        mount_point: &str,
+        f: impl FnOnce(&MountedOperator) -> T,
+    ) -> OpendalMountResult<T> {
         let ops = self.ops.read().await;
 
-        ops.iter()
-            .map(|(key, op)| {
-                let info = op.op.info();
+        let op = ops
+            .get(mount_point)
+            .ok_or_else(|| OpendalMountError::NotMounted(mount_point.to_owned()))?;
 
-                MountedFs {
-                    id: key.to_owned(),
-                    mount_point: op.mount_point.to_owned(),
-                    scheme: info.scheme().to_string(),
-                    root: info.root().to_owned(),
-                    name: info.name().to_owned(),
-                }
+        Ok(f(op))
+    }
+
+    /// Copies the tree mounted at `mount_point` to `target`, resuming the
+    /// snapshot at the `resume` prefix if any.
+    ///
+    /// Files are copied as stored, encrypted, compressed or chunked, so the
+    /// snapshot is mounted with the options of the mount to be read.
+    pub async fn snapshot(
+        &self,
+        mount_point: &str,
+        target: Operator,
+        resume: Option<String>,
+    ) -> OpendalMountResult<Snapshot> {
+        let (storage, dedup, compressed) = self
+            .with_mounted(mount_point, |op| {
+                (op.stored.clone(), op.dedup.clone(), op.compressed.clone())
             })
-            .collect()
+            .await?;
+
+        // Appends buffered above the stored files, chunked ones first as
+        // they are written through the compression.
+        if let Some(dedup) = dedup {
+            dedup.flush_all().await;
+        }
+        if let Some(compressed) = compressed {
+            compressed.seal_all().await;
+        }
+
+        let prefix = resume.unwrap_or_else(snapshot::new_prefix);
+
+        snapshot::snapshot(storage, target, &prefix).await
+    }
+
+    /// Copies the paths that diverged on the secondary operator of the
+    /// mirror mounted at `mount_point` again.
+    pub async fn repair_mirror(&self, mount_point: &str) -> OpendalMountResult<usize> {
+        let mirror = self
+            .with_mounted(mount_point, |op| op.mirror.clone())
+            .await?
+            .ok_or_else(|| OpendalMountError::NotMirrored(mount_point.to_owned()))?;
+
+        Ok(mirror.repair().await)
+    }
+
+    /// Tier holding `path` in the tiered FS mounted at `mount_point`.
+    pub async fn tier(&self, mount_point: &str, path: &str) -> OpendalMountResult<Tier> {
+        let (tiered, sub_path) = self
+            .with_mounted(mount_point, |op| (op.tiered.clone(), op.sub_path.clone()))
+            .await?;
+        let tiered = tiered.ok_or_else(|| OpendalMountError::NotTiered(mount_point.to_owned()))?;
+
+        // Paths are relative to the mount, the tiers being below its sub
+        // path.
+        let path = match sub_path {
+            Some(sub_path) => sub_path.resolve(path)?,
+            None => path.to_owned(),
+        };
+
+        Ok(tiered.tier(&path).await?)
+    }
+
+    /// Objects in the trash of the FS mounted at `mount_point`.
+    pub async fn trash(&self, mount_point: &str) -> OpendalMountResult<Vec<TrashItem>> {
+        let trash = self
+            .with_mounted(mount_point, |op| op.trash.clone())
+            .await?
+            .ok_or_else(|| OpendalMountError::NoTrash(mount_point.to_owned()))?;
+
+        Ok(trash.items().await?)
+    }
+
+    /// Moves the object at `trash_path` in the trash of the FS mounted at
+    /// `mount_point` back to where it was removed from.
+    pub async fn restore_trash(
+        &self,
+        mount_point: &str,
+        trash_path: &str,
+    ) -> OpendalMountResult<String> {
+        let trash = self
+            .with_mounted(mount_point, |op| op.trash.clone())
+            .await?
+            .ok_or_else(|| OpendalMountError::NoTrash(mount_point.to_owned()))?;
+
+        Ok(trash.restore(trash_path).await?)
+    }
+
+    pub async fn mounted_operators(&self) -> Vec<MountedFs> {
+        let ops = self.ops.read().await;
+
+        let mut mounted = Vec::with_capacity(ops.len());
+
+        for (key, op) in ops.iter() {
+            let info = op.op.info();
+
+            let divergences = match &op.mirror {
+                Some(mirror) => mirror.divergences().await,
+                None => vec![],
+            };
+
+            mounted.push(MountedFs {
+                id: key.to_owned(),
+                mount_point: match &self.root_mount {
+                    Some(root_mount) => Path::new(root_mount)
+                        .join(&op.mount_point)
+                        .display()
+                        .to_string(),
+                    None => op.mount_point.to_owned(),
+                },
+                scheme: info.scheme().to_string(),
+                root: op.root.to_owned(),
+                name: info.name().to_owned(),
+                read_only: op.options.read_only,
+                usage: op.fs.quota().usage(),
+                divergences,
+            });
+        }
+
+        mounted
+    }
+}
+
+fn now_nanos() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_nanos() as u64)
+}
+
+/// Attributes of the directory listing the mounts, modified at `mtime`
+/// nanoseconds since the epoch.
+fn root_attr(mtime: u64) -> fattr3 {
+    let mtime = nfstime3 {
+        seconds: (mtime / 1_000_000_000) as u32,
+        nseconds: (mtime % 1_000_000_000) as u32,
+    };
+
+    fattr3 {
+        ftype: ftype3::NF3DIR,
+        mode: 0o555,
+        nlink: 0,
+        uid: 507,
+        gid: 507,
+        size: 0,
+        used: 0,
+        rdev: specdata3::default(),
+        fsid: 0,
+        fileid: ROOT_INODE,
+        atime: mtime,
+        mtime,
+        ctime: mtime,
     }
 }
 
+impl MultiplexedFs {
+    /// Mount owning `id`, with the inode of `id` within the mount.
+    fn route(&self, id: fileid3) -> Result<(u16, Arc<OpendalFs>, fileid3), nfsstat3> {
+        let (index, ino) = inodes::split_fileid(id);
+
+        let routes = self.routes.read().unwrap();
+        let route = routes.get(&index).ok_or(nfsstat3::NFS3ERR_STALE)?;
+
+        Ok((index, route.fs.clone(), ino))
+    }
+
+    /// Mount owning `id`, to be modified. The root listing the mounts is
+    /// read only.
+    fn route_writable(&self, id: fileid3) -> Result<(u16, Arc<OpendalFs>, fileid3), nfsstat3> {
+        if id == ROOT_INODE {
+            return Err(nfsstat3::NFS3ERR_ACCES);
+        }
+
+        self.route(id)
+    }
+
+    /// Entries of the directory listing the mounts, named after their
+    /// prefix.
+    async fn root_entries(&self) -> Vec<DirEntry> {
+        let routes: Vec<(u16, String, Arc<OpendalFs>)> = self
+            .routes
+            .read()
+            .unwrap()
+            .iter()
+            .map(|(index, route)| (*index, route.prefix.clone(), route.fs.clone()))
+            .collect();
+
+        let mut entries = Vec::with_capacity(routes.len());
+
+        for (index, prefix, fs) in routes {
+            let fileid = inodes::mount_fileid(index, fs.root_dir());
+
+            match fs.getattr(fs.root_dir()).await {
+                Ok(attr) => entries.push(DirEntry {
+                    fileid,
+                    name: prefix.as_bytes().into(),
+                    attr: with_fileid(attr, fileid),
+                }),
+                Err(e) => warn!("unable to get attributes of mount {}: {:?}", prefix, e),
+            }
+        }
+
+        entries
+    }
+}
+
+fn with_fileid(mut attr: fattr3, fileid: fileid3) -> fattr3 {
+    attr.fileid = fileid;
+    attr
+}
+
 #[async_trait]
 impl NFSFileSystem for MultiplexedFs {
+    fn id_to_fh(&self, id: fileid3) -> nfs_fh3 {
+        if id == ROOT_INODE {
+            return inodes::file_handle(id, 0);
+        }
+
+        match self.route(id) {
+            Ok((_, fs, ino)) => {
+                // The generation is the one of the inode within its mount.
+                let fh = fs.id_to_fh(ino);
+                let generation = inodes::parse_file_handle(&fh).map_or(0, |(_, g)| g);
+
+                inodes::file_handle(id, generation)
+            }
+            Err(_) => inodes::file_handle(id, 0),
+        }
+    }
+
+    fn fh_to_id(&self, fh: &nfs_fh3) -> Result<fileid3, nfsstat3> {
+        let (id, generation) = inodes::parse_file_handle(fh)?;
+
+        // The root listing every mount is only exported in root-mount mode.
+        if id == ROOT_INODE {
+            return match self.root_mount {
+                Some(_) => Ok(id),
+                None => Err(nfsstat3::NFS3ERR_STALE),
+            };
+        }
+
+        let (index, fs, ino) = self.route(id)?;
+        let ino = fs.fh_to_id(&inodes::file_handle(ino, generation))?;
+
+        Ok(inodes::mount_fileid(index, ino))
+    }
+
     fn capabilities(&self) -> VFSCapabilities {
-        VFSCapabilities::ReadWrite
+        // Writes are checked by each mount, the whole server is only read
+        // only when every mount is.
+        if self.read_only.load(Ordering::Relaxed) {
+            VFSCapabilities::ReadOnly
+        } else {
+            VFSCapabilities::ReadWrite
+        }
     }
 
     fn root_dir(&self) -> fileid3 {
         debug!("Root dir requested");
 
-        1
+        ROOT_INODE
+    }
+
+    /// Resolves the dirpath of a MOUNT request, which has to start with the
+    /// prefix of a mount. The root listing the mounts is only exported when
+    /// the server is mounted once.
+    async fn path_to_id(&self, path: &[u8]) -> Result<fileid3, nfsstat3> {
+        debug!("Export {} requested", String::from_utf8_lossy(path));
+
+        let mut components = path
+            .split(|c| *c == b'/')
+            .filter(|c| !c.is_empty())
+            .peekable();
+
+        if components.peek().is_none() && self.root_mount.is_none() {
+            return Err(nfsstat3::NFS3ERR_NOENT);
+        }
+
+        let mut id = ROOT_INODE;
+
+        for component in components {
+            id = self.lookup(id, &component.to_vec().into()).await?;
+        }
+
+        Ok(id)
     }
 
     async fn lookup(&self, parent: fileid3, name: &filename3) -> Result<fileid3, nfsstat3> {
-        debug!("Lookup {} in {}", parent, String::from_utf8_lossy(name));
+        debug!("Lookup {} in {}", String::from_utf8_lossy(name), parent);
 
-        Err(nfsstat3::NFS3ERR_NOENT)
+        if parent == ROOT_INODE {
+            let routes = self.routes.read().unwrap();
+
+            return routes
+                .iter()
+                .find(|(_, route)| route.prefix.as_bytes() == name.0.as_slice())
+                .map(|(index, route)| inodes::mount_fileid(*index, route.fs.root_dir()))
+                .ok_or(nfsstat3::NFS3ERR_NOENT);
+        }
+
+        let (index, fs, dirid) = self.route(parent)?;
+        let ino = fs.lookup(dirid, name).await?;
+
+        Ok(inodes::mount_fileid(index, ino))
     }
 
     async fn getattr(&self, id: fileid3) -> Result<fattr3, nfsstat3> {
         debug!("Getattr {}", id);
 
-        if id == 1 {
-            let mtime = nfstime3::default();
-
-            return Ok(fattr3 {
-                ftype: ftype3::NF3DIR,
-                mode: 0o777,
-                nlink: 0,
-                uid: 507,
-                gid: 507,
-                size: 0,
-                used: 0,
-                rdev: specdata3::default(),
-                fsid: 0,
-                fileid: 1,
-                atime: mtime,
-                mtime,
-                ctime: mtime,
-            });
+        if id == ROOT_INODE {
+            return Ok(root_attr(self.root_mtime.load(Ordering::Relaxed)));
         }
 
-        Err(nfsstat3::NFS3ERR_NOENT)
+        let (_, fs, ino) = self.route(id)?;
+
+        Ok(with_fileid(fs.getattr(ino).await?, id))
     }
 
     async fn setattr(&self, id: fileid3, setattr: sattr3) -> Result<fattr3, nfsstat3> {
         debug!("Setattr {} with {:?}", id, setattr);
 
-        Err(nfsstat3::NFS3ERR_NOENT)
+        let (_, fs, ino) = self.route_writable(id)?;
+
+        Ok(with_fileid(fs.setattr(ino, setattr).await?, id))
     }
 
     async fn read(
@@ -229,13 +1411,21 @@ impl NFSFileSystem for MultiplexedFs {
     ) -> Result<(Vec<u8>, bool), nfsstat3> {
         debug!("Read {} from {} with {} bytes", id, offset, count);
 
-        Err(nfsstat3::NFS3ERR_NOENT)
+        if id == ROOT_INODE {
+            return Err(nfsstat3::NFS3ERR_ISDIR);
+        }
+
+        let (_, fs, ino) = self.route(id)?;
+
+        fs.read(ino, offset, count).await
     }
 
     async fn write(&self, id: fileid3, offset: u64, data: &[u8]) -> Result<fattr3, nfsstat3> {
         debug!("Write {} from {} with {} bytes", id, offset, data.len());
 
-        Err(nfsstat3::NFS3ERR_NOENT)
+        let (_, fs, ino) = self.route_writable(id)?;
+
+        Ok(with_fileid(fs.write(ino, offset, data).await?, id))
     }
 
     async fn create(
@@ -251,7 +1441,11 @@ impl NFSFileSystem for MultiplexedFs {
             attr
         );
 
-        Err(nfsstat3::NFS3ERR_NOENT)
+        let (index, fs, dirid) = self.route_writable(dirid)?;
+        let (ino, attr) = fs.create(dirid, filename, attr).await?;
+        let id = inodes::mount_fileid(index, ino);
+
+        Ok((id, with_fileid(attr, id)))
     }
 
     async fn create_exclusive(
@@ -265,7 +1459,10 @@ impl NFSFileSystem for MultiplexedFs {
             dirid
         );
 
-        Err(nfsstat3::NFS3ERR_NOENT)
+        let (index, fs, dirid) = self.route_writable(dirid)?;
+        let ino = fs.create_exclusive(dirid, filename).await?;
+
+        Ok(inodes::mount_fileid(index, ino))
     }
 
     async fn mkdir(
@@ -275,13 +1472,19 @@ impl NFSFileSystem for MultiplexedFs {
     ) -> Result<(fileid3, fattr3), nfsstat3> {
         debug!("Mkdir {} in {}", String::from_utf8_lossy(dirname), dirid);
 
-        Err(nfsstat3::NFS3ERR_NOENT)
+        let (index, fs, dirid) = self.route_writable(dirid)?;
+        let (ino, attr) = fs.mkdir(dirid, dirname).await?;
+        let id = inodes::mount_fileid(index, ino);
+
+        Ok((id, with_fileid(attr, id)))
     }
 
     async fn remove(&self, dirid: fileid3, filename: &filename3) -> Result<(), nfsstat3> {
         debug!("Remove {} in {}", String::from_utf8_lossy(filename), dirid);
 
-        Err(nfsstat3::NFS3ERR_NOENT)
+        let (_, fs, dirid) = self.route_writable(dirid)?;
+
+        fs.remove(dirid, filename).await
     }
 
     async fn rename(
@@ -299,7 +1502,15 @@ impl NFSFileSystem for MultiplexedFs {
             to_dirid
         );
 
-        Err(nfsstat3::NFS3ERR_NOENT)
+        let (from_index, fs, from_dirid) = self.route_writable(from_dirid)?;
+        let (to_index, _, to_dirid) = self.route_writable(to_dirid)?;
+
+        if from_index != to_index {
+            return Err(nfsstat3::NFS3ERR_XDEV);
+        }
+
+        fs.rename(from_dirid, from_filename, to_dirid, to_filename)
+            .await
     }
 
     async fn readdir(
@@ -310,7 +1521,35 @@ impl NFSFileSystem for MultiplexedFs {
     ) -> Result<ReadDirResult, nfsstat3> {
         debug!("Readdir {} with {} entries", dirid, max_entries);
 
-        Err(nfsstat3::NFS3ERR_NOENT)
+        if dirid == ROOT_INODE {
+            let mut entries: Vec<DirEntry> = self
+                .root_entries()
+                .await
+                .into_iter()
+                .skip_while(|entry| start_after != 0 && entry.fileid <= start_after)
+                .collect();
+
+            let end = entries.len() <= max_entries;
+            entries.truncate(max_entries);
+
+            return Ok(ReadDirResult { entries, end });
+        }
+
+        let (index, fs, dirid) = self.route(dirid)?;
+
+        let start_after = if start_after == 0 {
+            0
+        } else {
+            inodes::split_fileid(start_after).1
+        };
+
+        let mut result = fs.readdir(dirid, start_after, max_entries).await?;
+        for entry in result.entries.iter_mut() {
+            entry.fileid = inodes::mount_fileid(index, entry.fileid);
+            entry.attr.fileid = entry.fileid;
+        }
+
+        Ok(result)
     }
 
     async fn symlink(
@@ -328,12 +1567,18 @@ impl NFSFileSystem for MultiplexedFs {
             attr
         );
 
-        Err(nfsstat3::NFS3ERR_NOENT)
+        let (index, fs, dirid) = self.route_writable(dirid)?;
+        let (ino, attr) = fs.symlink(dirid, linkname, symlink, attr).await?;
+        let id = inodes::mount_fileid(index, ino);
+
+        Ok((id, with_fileid(attr, id)))
     }
 
     async fn readlink(&self, id: fileid3) -> Result<nfspath3, nfsstat3> {
         debug!("Readlink {}", id);
 
-        Err(nfsstat3::NFS3ERR_NOENT)
+        let (_, fs, ino) = self.route(id)?;
+
+        fs.readlink(ino).await
     }
 }
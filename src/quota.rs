@@ -0,0 +1,107 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use log::warn;
+use nfsserve::nfs::nfsstat3;
+
+use crate::schema::QuotaUsage;
+
+/// Write limits of a mounted operator.
+///
+/// Usage is the size and number of the files of the mount. It starts from
+/// what the storage holds when mounted, then follows the changes made
+/// through the mount: overwrites and truncations count by how much they
+/// change the size of a file, removals release what the file used. Changes
+/// made to the storage behind the mount are not seen until it is mounted
+/// again.
+#[derive(Debug, Default)]
+pub struct Quota {
+    max_bytes: Option<u64>,
+    max_objects: Option<u64>,
+    bytes: AtomicU64,
+    objects: AtomicU64,
+}
+
+impl Quota {
+    pub fn new(max_bytes: Option<u64>, max_objects: Option<u64>) -> Self {
+        Self {
+            max_bytes,
+            max_objects,
+            ..Default::default()
+        }
+    }
+
+    pub fn unlimited() -> Self {
+        Self::default()
+    }
+
+    /// Whether any limit is set, usage being otherwise not worth measuring.
+    pub fn is_limited(&self) -> bool {
+        self.max_bytes.is_some() || self.max_objects.is_some()
+    }
+
+    /// Starts counting from `bytes` and `objects` already in use.
+    pub fn set_usage(&self, bytes: u64, objects: u64) {
+        self.bytes.store(bytes, Ordering::SeqCst);
+        self.objects.store(objects, Ordering::SeqCst);
+    }
+
+    /// Accounts for `len` more bytes, failing with `NFS3ERR_DQUOT` if it
+    /// would exceed the limit.
+    pub fn reserve_bytes(&self, len: u64) -> Result<(), nfsstat3> {
+        Self::reserve(&self.bytes, len, self.max_bytes).map_err(|used| {
+            warn!(
+                "byte quota exceeded: {} + {} > {:?}",
+                used, len, self.max_bytes
+            );
+            nfsstat3::NFS3ERR_DQUOT
+        })
+    }
+
+    pub fn release_bytes(&self, len: u64) {
+        Self::release(&self.bytes, len);
+    }
+
+    /// Accounts for one more object, failing with `NFS3ERR_DQUOT` if it
+    /// would exceed the limit.
+    pub fn reserve_object(&self) -> Result<(), nfsstat3> {
+        Self::reserve(&self.objects, 1, self.max_objects).map_err(|used| {
+            warn!("object quota exceeded: {} >= {:?}", used, self.max_objects);
+            nfsstat3::NFS3ERR_DQUOT
+        })
+    }
+
+    pub fn release_object(&self) {
+        self.release_objects(1);
+    }
+
+    pub fn release_objects(&self, count: u64) {
+        Self::release(&self.objects, count);
+    }
+
+    pub fn usage(&self) -> QuotaUsage {
+        QuotaUsage {
+            bytes_used: self.bytes.load(Ordering::Relaxed),
+            objects: self.objects.load(Ordering::Relaxed),
+            max_bytes: self.max_bytes,
+            max_objects: self.max_objects,
+        }
+    }
+
+    fn reserve(counter: &AtomicU64, amount: u64, limit: Option<u64>) -> Result<(), u64> {
+        counter
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |used| {
+                let next = used.checked_add(amount)?;
+                match limit {
+                    Some(limit) if next > limit => None,
+                    _ => Some(next),
+                }
+            })
+            .map(|_| ())
+    }
+
+    fn release(counter: &AtomicU64, amount: u64) {
+        let _ = counter.fetch_update(Ordering::SeqCst, Ordering::SeqCst, |used| {
+            Some(used.saturating_sub(amount))
+        });
+    }
+}
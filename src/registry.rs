@@ -0,0 +1,98 @@
+use std::{
+    future::Future,
+    path::{Path, PathBuf},
+};
+
+use serde::{Deserialize, Serialize};
+use tokio::{io::AsyncWriteExt, sync::Mutex};
+
+use crate::{
+    errors::{OpendalMountError, OpendalMountResult},
+    schema::{MountOptions, OperatorInput},
+};
+
+/// Operators a mount is built from.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum MountSource {
+    Operator(OperatorInput),
+    /// A writable `upper` operator over a read only `lower` one
+    Overlay {
+        lower: OperatorInput,
+        upper: OperatorInput,
+    },
+}
+
+/// Everything needed to mount an operator again.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct MountEntry {
+    pub mount_point: String,
+    pub source: MountSource,
+    #[serde(default)]
+    pub options: MountOptions,
+    /// Index the mount had when saved, taken again when restored for the
+    /// file handles given out before a restart to reach the same mount.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub index: Option<u16>,
+}
+
+/// State file listing the mounts to restore when the server starts.
+///
+/// The file holds the parameters of the operators, credentials included,
+/// so it is only readable by its owner.
+pub struct Registry {
+    path: PathBuf,
+    /// Serializes the saves, which go through the same temporary file.
+    lock: Mutex<()>,
+}
+
+impl Registry {
+    pub fn new(path: &Path) -> Self {
+        Self {
+            path: path.to_owned(),
+            lock: Mutex::new(()),
+        }
+    }
+
+    /// Saved mounts, none if the state file does not exist yet.
+    pub async fn load(&self) -> OpendalMountResult<Vec<MountEntry>> {
+        let data = match tokio::fs::read(&self.path).await {
+            Ok(data) => data,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(vec![]),
+            Err(e) => return Err(e.into()),
+        };
+
+        serde_json::from_slice(&data)
+            .map_err(|e| OpendalMountError::InvalidState(format!("{}: {}", self.path.display(), e)))
+    }
+
+    /// Saves the mounts `entries` resolves to, only awaited once the
+    /// previous saves are done so that they cannot overwrite a newer list.
+    pub async fn save(
+        &self,
+        entries: impl Future<Output = Vec<MountEntry>>,
+    ) -> OpendalMountResult<()> {
+        let _lock = self.lock.lock().await;
+
+        let data = serde_json::to_vec_pretty(&entries.await)
+            .map_err(|e| OpendalMountError::InvalidState(e.to_string()))?;
+
+        let tmp = self.path.with_extension("tmp");
+
+        let mut options = tokio::fs::OpenOptions::new();
+        options.write(true).create(true).truncate(true);
+        #[cfg(unix)]
+        options.mode(0o600);
+
+        // Created with its permissions before holding any secret, and synced
+        // for the rename not to replace the state with an empty file on a
+        // crash.
+        let mut file = options.open(&tmp).await?;
+        file.write_all(&data).await?;
+        file.sync_all().await?;
+
+        tokio::fs::rename(&tmp, &self.path).await?;
+
+        Ok(())
+    }
+}
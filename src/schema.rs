@@ -1,10 +1,13 @@
-use std::{collections::HashMap, str::FromStr};
+use std::collections::HashMap;
 
 use async_graphql::*;
 use log::{debug, error};
-use opendal::{Operator, Scheme};
+use serde::{Deserialize, Serialize};
 
-use crate::{errors::OpendalMountError, MultiplexedFs};
+use crate::{
+    config::ReloadPlan, errors::OpendalMountError, multiplex::build_operator, MountEntry,
+    MountSource, MultiplexedFs,
+};
 
 #[derive(SimpleObject)]
 pub struct MountedFs {
@@ -13,6 +16,202 @@ pub struct MountedFs {
     pub scheme: String,
     pub root: String,
     pub name: String,
+    pub read_only: bool,
+    pub usage: QuotaUsage,
+    /// Changes that could not be applied to the secondary operator of a
+    /// mirror
+    pub divergences: Vec<Divergence>,
+}
+
+#[derive(SimpleObject)]
+pub struct QuotaUsage {
+    /// Size of the files of the mount
+    pub bytes_used: u64,
+    /// Number of files of the mount
+    pub objects: u64,
+    pub max_bytes: Option<u64>,
+    pub max_objects: Option<u64>,
+}
+
+#[derive(SimpleObject, Clone, Debug)]
+pub struct Divergence {
+    pub path: String,
+    pub operation: String,
+    pub error: String,
+    /// Seconds since the epoch
+    pub timestamp: u64,
+}
+
+#[derive(SimpleObject, Debug)]
+pub struct Snapshot {
+    /// Prefix of the snapshot in the target operator
+    pub prefix: String,
+    /// Number of files copied
+    pub files: u64,
+    /// Number of bytes copied
+    pub bytes: u64,
+    /// Number of files already present in the target
+    pub skipped: u64,
+    /// Files that could not be copied, the snapshot being resumable with
+    /// its prefix
+    pub failed: Vec<String>,
+}
+
+#[derive(SimpleObject, Debug)]
+pub struct TrashItem {
+    /// Path the object was removed from
+    pub path: String,
+    /// Path of the object in the trash
+    pub trash_path: String,
+    /// Milliseconds since the epoch
+    pub removed_at: u64,
+    pub size: u64,
+}
+
+/// Mount points changed by a reload of the config file.
+#[derive(SimpleObject, Debug)]
+pub struct Reload {
+    pub added: Vec<String>,
+    pub removed: Vec<String>,
+    /// Mounts whose operators or options changed, unmounted then mounted
+    pub remounted: Vec<String>,
+    pub unchanged: Vec<String>,
+    /// Whether the changes were only planned
+    pub dry_run: bool,
+}
+
+impl Reload {
+    fn new(plan: ReloadPlan, dry_run: bool) -> Self {
+        Self {
+            added: plan.add.into_iter().map(|e| e.mount_point).collect(),
+            removed: plan.remove,
+            remounted: plan.remount.into_iter().map(|e| e.mount_point).collect(),
+            unchanged: plan.unchanged,
+            dry_run,
+        }
+    }
+}
+
+/// A saved mount that could not be mounted again when the server started,
+/// or a mount of the config file that could not be mounted.
+#[derive(SimpleObject, Debug, Clone)]
+pub struct RestoreFailure {
+    pub mount_point: String,
+    pub error: String,
+}
+
+#[derive(InputObject, Serialize, Deserialize, Default, Debug, Clone, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct MountOptions {
+    /// Reject every change to the mount, which is also mounted read only
+    #[graphql(default)]
+    pub read_only: bool,
+    /// Directory of the operator exposed as the root of the mount
+    pub sub_path: Option<String>,
+    /// Maximum number of bytes that can be written through the mount
+    pub max_bytes: Option<u64>,
+    /// Maximum number of objects that can be created through the mount
+    pub max_objects: Option<u64>,
+    /// Maximum number of paths kept in memory to map NFS file handles
+    pub max_inodes: Option<u64>,
+    /// File holding the key used to encrypt content before it reaches the
+    /// backend, either 32 raw bytes or 64 hex digits
+    pub encryption_key_file: Option<String>,
+    /// Compress content on the backend while serving it uncompressed
+    pub compression: Option<Compression>,
+    /// Expose object versions under a read only `.versions` directory
+    #[graphql(default)]
+    pub versions: bool,
+    /// Show the metadata of every file `foo` in a hidden `.foo.meta.json`
+    #[graphql(default)]
+    pub sidecars: bool,
+    /// Also write every change to a secondary operator
+    pub mirror: Option<MirrorOptions>,
+    /// Move cold files to a second operator
+    pub tiering: Option<TieringOptions>,
+    /// Store content as chunks shared between files
+    #[graphql(default)]
+    pub dedup: bool,
+    /// Globs of file names, as `*.swp` or `~$*`, kept on a local scratch
+    /// directory until renamed to a name that does not match
+    #[graphql(default)]
+    pub local_only: Vec<String>,
+    /// Globs of file names, as `.DS_Store` or `._*`, only kept in memory and
+    /// hidden from the operator
+    #[graphql(default)]
+    pub ignore: Vec<String>,
+    /// Move removed and overwritten objects to `.trash/<timestamp>/`
+    pub trash: Option<TrashOptions>,
+    /// Tar or zip object whose content is mounted, read only, instead of
+    /// the operator
+    pub archive: Option<String>,
+}
+
+/// Compression of the content on the backend, `"zstd"` or `"gzip"` in the
+/// config file.
+#[derive(Enum, Serialize, Deserialize, Copy, Clone, Eq, PartialEq, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum Compression {
+    // Also accepted as first saved in state files.
+    #[serde(alias = "Zstd")]
+    Zstd,
+    #[serde(alias = "Gzip")]
+    Gzip,
+}
+
+#[derive(InputObject, Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct MirrorOptions {
+    pub secondary: OperatorInput,
+    #[graphql(default)]
+    #[serde(default)]
+    pub policy: MirrorPolicy,
+}
+
+/// When changes are applied to the secondary operator of a mirror.
+#[derive(Enum, Serialize, Deserialize, Copy, Clone, Eq, PartialEq, Debug, Default)]
+pub enum MirrorPolicy {
+    /// Before the change is acknowledged
+    #[default]
+    Sync,
+    /// In the background, in the order of the changes
+    Async,
+}
+
+#[derive(InputObject, Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct TrashOptions {
+    /// Seconds objects are kept in the trash, a week by default
+    #[graphql(default = 604800)]
+    #[serde(default = "default_retention_secs")]
+    pub retention_secs: u64,
+}
+
+fn default_retention_secs() -> u64 {
+    604800
+}
+
+#[derive(InputObject, Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct TieringOptions {
+    /// Operator holding the cold files
+    pub cold: OperatorInput,
+    /// Demote files unused for this many seconds
+    pub demote_after_secs: Option<u64>,
+    /// Demote the least recently used files while the mounted operator
+    /// holds more bytes than this
+    pub max_hot_bytes: Option<u64>,
+    /// Move cold files back to the mounted operator when they are read
+    #[graphql(default)]
+    #[serde(default)]
+    pub promote: bool,
+}
+
+/// Where a file of a tiered mount is stored.
+#[derive(Enum, Copy, Clone, Eq, PartialEq, Debug)]
+pub enum Tier {
+    Hot,
+    Cold,
 }
 
 pub struct Query;
@@ -26,36 +225,220 @@ impl Query {
 
         Ok(mfs.mounted_operators().await)
     }
+
+    /// Tier holding `path` in the tiered FS mounted at `mount_point`.
+    async fn tier<'ctx>(
+        &self,
+        ctx: &Context<'ctx>,
+        mount_point: String,
+        path: String,
+    ) -> async_graphql::Result<Tier> {
+        let mfs = multiplexed(ctx)?;
+
+        Ok(mfs.tier(&mount_point, &path).await?)
+    }
+
+    /// Objects in the trash of the FS mounted at `mount_point`.
+    async fn trash<'ctx>(
+        &self,
+        ctx: &Context<'ctx>,
+        mount_point: String,
+    ) -> async_graphql::Result<Vec<TrashItem>> {
+        let mfs = multiplexed(ctx)?;
+
+        Ok(mfs.trash(&mount_point).await?)
+    }
+
+    /// Saved mounts that could not be mounted again at startup, and mounts
+    /// of the config file that could not be mounted.
+    async fn restore_failures<'ctx>(
+        &self,
+        ctx: &Context<'ctx>,
+    ) -> async_graphql::Result<Vec<RestoreFailure>> {
+        let mfs = multiplexed(ctx)?;
+
+        Ok(mfs.restore_failures().await)
+    }
+}
+
+/// An operator to build, as accepted by the `mount` mutation.
+#[derive(InputObject, Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct OperatorInput {
+    pub service: String,
+    pub parameters: HashMap<String, String>,
+}
+
+fn multiplexed<'ctx>(ctx: &Context<'ctx>) -> Result<&'ctx MultiplexedFs> {
+    let mfs = ctx.data::<MultiplexedFs>().map_err(|e| {
+        error!("Multiplexed FS not found: {:#?}", e);
+        OpendalMountError::MultiplexedNotFound()
+    })?;
+
+    Ok(mfs)
 }
 
 pub struct Mutation;
 
 #[Object]
 impl Mutation {
+    /// Mounts an operator at `mount_point`, or as the `mount_point`
+    /// subdirectory of the root when the server is mounted once.
     async fn mount<'ctx>(
         &self,
         ctx: &Context<'ctx>,
         service: String,
         parameters: HashMap<String, String>,
         mount_point: String,
+        #[graphql(default)] options: MountOptions,
     ) -> async_graphql::Result<String> {
         debug!("mounting {} at {}", service, mount_point);
 
-        let mfs = ctx.data::<MultiplexedFs>().map_err(|e| {
-            error!("Multiplexed FS not found: {:#?}", e);
-            OpendalMountError::MultiplexedNotFound()
-        })?;
+        let mfs = multiplexed(ctx)?;
+
+        mfs.mount(MountEntry {
+            mount_point: mount_point.clone(),
+            source: MountSource::Operator(OperatorInput {
+                service,
+                parameters,
+            }),
+            options,
+            index: None,
+        })
+        .await?;
+
+        Ok(mount_point)
+    }
 
-        let scheme = Scheme::from_str(&service)
-            .map_err(|_| OpendalMountError::UnsupportedScheme(service))?;
+    /// Unmounts the FS mounted at `mount_point`.
+    async fn umount<'ctx>(
+        &self,
+        ctx: &Context<'ctx>,
+        mount_point: String,
+    ) -> async_graphql::Result<String> {
+        debug!("unmounting {}", mount_point);
 
-        let op = Operator::via_map(scheme, parameters).map_err(|e| {
-            error!("operator creation failure: {}", e);
-            OpendalMountError::OperatorCreateError(format!("{}", e))
-        })?;
+        let mfs = multiplexed(ctx)?;
 
-        mfs.mount_operator(&mount_point, op).await?;
+        mfs.umount(&mount_point).await?;
 
         Ok(mount_point)
     }
+
+    /// Mounts a writable `upper` operator over a read only `lower` one.
+    async fn mount_overlay<'ctx>(
+        &self,
+        ctx: &Context<'ctx>,
+        lower: OperatorInput,
+        upper: OperatorInput,
+        mount_point: String,
+        #[graphql(default)] options: MountOptions,
+    ) -> async_graphql::Result<String> {
+        debug!(
+            "mounting overlay of {} over {} at {}",
+            upper.service, lower.service, mount_point
+        );
+
+        let mfs = multiplexed(ctx)?;
+
+        mfs.mount(MountEntry {
+            mount_point: mount_point.clone(),
+            source: MountSource::Overlay { lower, upper },
+            options,
+            index: None,
+        })
+        .await?;
+
+        Ok(mount_point)
+    }
+
+    /// Copies the tree mounted at `mount_point` to a `target` operator, under
+    /// a timestamped prefix or the prefix of the snapshot to `resume`.
+    async fn snapshot<'ctx>(
+        &self,
+        ctx: &Context<'ctx>,
+        mount_point: String,
+        target: String,
+        parameters: HashMap<String, String>,
+        resume: Option<String>,
+    ) -> async_graphql::Result<Snapshot> {
+        debug!("snapshotting {} to {}", mount_point, target);
+
+        let mfs = multiplexed(ctx)?;
+        let target = build_operator(target, parameters)?;
+
+        Ok(mfs.snapshot(&mount_point, target, resume).await?)
+    }
+
+    /// Mounts the snapshot at `prefix` of an operator, read only, its files
+    /// being decoded with the `options` of the snapshotted mount, without
+    /// its sub path.
+    async fn mount_snapshot<'ctx>(
+        &self,
+        ctx: &Context<'ctx>,
+        service: String,
+        parameters: HashMap<String, String>,
+        prefix: String,
+        mount_point: String,
+        #[graphql(default)] options: MountOptions,
+    ) -> async_graphql::Result<String> {
+        debug!(
+            "mounting snapshot {} of {} at {}",
+            prefix, service, mount_point
+        );
+
+        let mfs = multiplexed(ctx)?;
+        let op = build_operator(service, parameters)?;
+
+        mfs.mount_snapshot(&mount_point, op, &prefix, options)
+            .await?;
+
+        Ok(mount_point)
+    }
+
+    /// Moves an object of the trash back to where it was removed from,
+    /// returning that path.
+    async fn restore_trash<'ctx>(
+        &self,
+        ctx: &Context<'ctx>,
+        mount_point: String,
+        trash_path: String,
+    ) -> async_graphql::Result<String> {
+        debug!("restoring {} in {}", trash_path, mount_point);
+
+        let mfs = multiplexed(ctx)?;
+
+        Ok(mfs.restore_trash(&mount_point, &trash_path).await?)
+    }
+
+    /// Copies the paths that diverged on the secondary operator of a mirror
+    /// again, returning the number of paths repaired.
+    async fn repair_mirror<'ctx>(
+        &self,
+        ctx: &Context<'ctx>,
+        mount_point: String,
+    ) -> async_graphql::Result<usize> {
+        debug!("repairing mirror at {}", mount_point);
+
+        let mfs = multiplexed(ctx)?;
+
+        Ok(mfs.repair_mirror(&mount_point).await?)
+    }
+
+    /// Reads the config file again and applies the mounts it declares, or
+    /// with `dry_run` only returns the changes it would make.
+    async fn reload<'ctx>(
+        &self,
+        ctx: &Context<'ctx>,
+        #[graphql(default)] dry_run: bool,
+    ) -> async_graphql::Result<Reload> {
+        debug!(
+            "reloading config{}",
+            if dry_run { " (dry run)" } else { "" }
+        );
+
+        let mfs = multiplexed(ctx)?;
+        let plan = mfs.reload_config(dry_run).await?;
+
+        Ok(Reload::new(plan, dry_run))
+    }
 }
@@ -0,0 +1,84 @@
+use std::collections::HashMap;
+
+use opendal::Metadata;
+use serde::{Deserialize, Serialize};
+
+use crate::storage::WriteMetadata;
+
+const PREFIX: &str = ".";
+const SUFFIX: &str = ".meta.json";
+
+/// Content of the `.<name>.meta.json` sidecar of a file.
+///
+/// Only the content type, disposition, cache control and user metadata are
+/// written back, the other fields are informative.
+#[derive(Serialize, Deserialize, Debug, Default)]
+#[serde(default)]
+pub struct Sidecar {
+    pub content_length: u64,
+    pub content_type: Option<String>,
+    pub content_disposition: Option<String>,
+    pub cache_control: Option<String>,
+    pub etag: Option<String>,
+    pub content_md5: Option<String>,
+    pub last_modified: Option<String>,
+    pub version: Option<String>,
+    pub user_metadata: HashMap<String, String>,
+}
+
+impl Sidecar {
+    pub fn render(meta: &Metadata) -> Vec<u8> {
+        let sidecar = Sidecar {
+            content_length: meta.content_length(),
+            content_type: meta.content_type().map(str::to_owned),
+            content_disposition: meta.content_disposition().map(str::to_owned),
+            cache_control: meta.cache_control().map(str::to_owned),
+            etag: meta.etag().map(str::to_owned),
+            content_md5: meta.content_md5().map(str::to_owned),
+            last_modified: meta.last_modified().map(|t| t.to_rfc3339()),
+            version: meta.version().map(str::to_owned),
+            user_metadata: meta.user_metadata().cloned().unwrap_or_default(),
+        };
+
+        let mut json = serde_json::to_vec_pretty(&sidecar).unwrap_or_default();
+        json.push(b'\n');
+
+        json
+    }
+
+    pub fn parse(data: &[u8]) -> serde_json::Result<WriteMetadata> {
+        let sidecar: Sidecar = serde_json::from_slice(data)?;
+
+        Ok(WriteMetadata {
+            content_type: sidecar.content_type,
+            content_disposition: sidecar.content_disposition,
+            cache_control: sidecar.cache_control,
+            user_metadata: sidecar.user_metadata,
+        })
+    }
+}
+
+/// Path of the sidecar of the file at `path`.
+pub fn sidecar_path(path: &str) -> String {
+    match path.rsplit_once('/') {
+        Some((dir, name)) => format!("{}/{}{}{}", dir, PREFIX, name, SUFFIX),
+        None => format!("{}{}{}", PREFIX, path, SUFFIX),
+    }
+}
+
+/// Path of the file described by the sidecar at `path`, if it is named
+/// like one.
+pub fn target_path(path: &str) -> Option<String> {
+    let (dir, name) = path.rsplit_once('/').unwrap_or(("", path));
+    let target = name.strip_prefix(PREFIX)?.strip_suffix(SUFFIX)?;
+
+    if target.is_empty() {
+        return None;
+    }
+
+    Some(if path.contains('/') {
+        format!("{}/{}", dir, target)
+    } else {
+        target.to_owned()
+    })
+}
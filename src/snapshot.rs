@@ -0,0 +1,227 @@
+use std::{
+    sync::Arc,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use futures::{stream, StreamExt};
+use log::{debug, info, warn};
+use opendal::{ErrorKind, Operator, Writer};
+
+use crate::{
+    errors::OpendalMountResult,
+    schema::Snapshot,
+    storage::{walk, Storage, WriteMetadata},
+};
+
+/// Number of files copied at once.
+const CONCURRENCY: usize = 16;
+
+/// Number of attempts to copy a file before giving up on it.
+const ATTEMPTS: usize = 3;
+
+/// Size of the reads of the copied files, each copy holding one at a time.
+const COPY_CHUNK: u64 = 8 * 1024 * 1024;
+
+/// Prefix of a new snapshot, as the current UTC time.
+pub fn new_prefix() -> String {
+    let secs = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default();
+
+    let (days, time) = (secs / 86400, secs % 86400);
+    let (year, month, day) = civil_from_days(days as i64);
+
+    format!(
+        "{:04}{:02}{:02}T{:02}{:02}{:02}Z",
+        year,
+        month,
+        day,
+        time / 3600,
+        time % 3600 / 60,
+        time % 60
+    )
+}
+
+/// Gregorian date of a number of days since the epoch, from
+/// <http://howardhinnant.github.io/date_algorithms.html>.
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    (year, month, day)
+}
+
+enum Copied {
+    File(u64),
+    Dir,
+    Skipped,
+}
+
+/// Copies the tree of `source` to `target` under `prefix`.
+///
+/// Files already present in the target with the same size and modified
+/// after the source are skipped, so calling it again with the prefix of an
+/// incomplete snapshot resumes it.
+pub async fn snapshot(
+    source: Arc<dyn Storage>,
+    target: Operator,
+    prefix: &str,
+) -> OpendalMountResult<Snapshot> {
+    let prefix = prefix.trim_matches('/').to_owned();
+    let entries = walk(source.as_ref(), "/").await?;
+
+    info!(
+        "snapshotting {} entries to {}/{}",
+        entries.len(),
+        target.info().name(),
+        prefix
+    );
+
+    let results: Vec<(String, opendal::Result<Copied>)> = stream::iter(entries)
+        .map(|entry| {
+            let source = source.clone();
+            let target = target.clone();
+            let path = format!("{}/{}", prefix, entry.path().trim_start_matches('/'));
+
+            async move {
+                let mut result = copy(source.as_ref(), &target, entry.path(), &path).await;
+
+                for attempt in 1..ATTEMPTS {
+                    match &result {
+                        Err(e) if e.kind() != ErrorKind::NotFound => {
+                            debug!("retrying copy of {:?} ({}): {}", path, attempt, e);
+                            result = copy(source.as_ref(), &target, entry.path(), &path).await;
+                        }
+                        _ => break,
+                    }
+                }
+
+                (entry.path().to_owned(), result)
+            }
+        })
+        .buffer_unordered(CONCURRENCY)
+        .collect()
+        .await;
+
+    let mut snapshot = Snapshot {
+        prefix,
+        files: 0,
+        bytes: 0,
+        skipped: 0,
+        failed: vec![],
+    };
+
+    for (path, result) in results {
+        match result {
+            Ok(Copied::File(bytes)) => {
+                snapshot.files += 1;
+                snapshot.bytes += bytes;
+            }
+            Ok(Copied::Dir) => {}
+            Ok(Copied::Skipped) => snapshot.skipped += 1,
+            // Deleted since it was listed.
+            Err(e) if e.kind() == ErrorKind::NotFound => {}
+            Err(e) => {
+                warn!("unable to snapshot {:?}: {}", path, e);
+                snapshot.failed.push(path);
+            }
+        }
+    }
+
+    Ok(snapshot)
+}
+
+async fn copy(
+    source: &dyn Storage,
+    target: &Operator,
+    from: &str,
+    to: &str,
+) -> opendal::Result<Copied> {
+    let meta = source.stat(from).await?;
+
+    if meta.is_dir() {
+        let to = format!("{}/", to.trim_end_matches('/'));
+        target.create_dir(&to).await?;
+
+        return Ok(Copied::Dir);
+    }
+
+    if let Ok(existing) = target.stat(to).await {
+        let copied = match (existing.last_modified(), meta.last_modified()) {
+            (Some(copied), Some(modified)) => copied >= modified,
+            _ => false,
+        };
+
+        if copied && existing.content_length() == meta.content_length() {
+            return Ok(Copied::Skipped);
+        }
+    }
+
+    let mut writer = writer(target, to, WriteMetadata::from(&meta)).await?;
+    let mut len = 0;
+
+    while len < meta.content_length() {
+        let end = meta.content_length().min(len + COPY_CHUNK);
+
+        let result = match source.read(from, len..end).await {
+            // Truncated since it was listed.
+            Ok(data) if data.is_empty() => break,
+            Ok(data) => {
+                len += data.len() as u64;
+                writer.write(data).await
+            }
+            Err(e) => Err(e),
+        };
+
+        if let Err(e) = result {
+            if let Err(e) = writer.abort().await {
+                debug!("unable to abort the copy to {:?}: {}", to, e);
+            }
+
+            return Err(e);
+        }
+    }
+
+    writer.close().await?;
+
+    Ok(Copied::File(len))
+}
+
+/// Writer of the file at `path` of `target`, along with the metadata the
+/// backend is able to store.
+async fn writer(target: &Operator, path: &str, metadata: WriteMetadata) -> opendal::Result<Writer> {
+    let capability = target.info().full_capability();
+    let mut writer = target.writer_with(path);
+
+    if let Some(content_type) = metadata
+        .content_type
+        .filter(|_| capability.write_with_content_type)
+    {
+        writer = writer.content_type(&content_type);
+    }
+    if let Some(disposition) = metadata
+        .content_disposition
+        .filter(|_| capability.write_with_content_disposition)
+    {
+        writer = writer.content_disposition(&disposition);
+    }
+    if let Some(cache_control) = metadata
+        .cache_control
+        .filter(|_| capability.write_with_cache_control)
+    {
+        writer = writer.cache_control(&cache_control);
+    }
+    if !metadata.user_metadata.is_empty() && capability.write_with_user_metadata {
+        writer = writer.user_metadata(metadata.user_metadata);
+    }
+
+    writer.await
+}
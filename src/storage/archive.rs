@@ -0,0 +1,790 @@
+use std::{
+    collections::{BTreeMap, HashMap, VecDeque},
+    ops::Range,
+    sync::{Arc, Mutex},
+};
+
+use async_trait::async_trait;
+use flate2::{Decompress, FlushDecompress, Status};
+use log::{debug, info};
+use opendal::{EntryMode, ErrorKind, Metadata, OperatorInfo};
+use tokio::sync::RwLock;
+
+use super::{Entry, Storage, WriteMetadata};
+
+const TAR_BLOCK: u64 = 512;
+
+const ZIP_LOCAL_HEADER: u32 = 0x04034b50;
+const ZIP_CENTRAL_HEADER: u32 = 0x02014b50;
+const ZIP_END: u32 = 0x06054b50;
+const ZIP64_END: u32 = 0x06064b50;
+const ZIP64_LOCATOR: u32 = 0x07064b50;
+/// Size of the end of central directory record without its comment.
+const ZIP_END_LEN: u64 = 22;
+const ZIP_LOCAL_HEADER_LEN: u64 = 30;
+
+const ZIP_STORED: u16 = 0;
+const ZIP_DEFLATED: u16 = 8;
+
+/// Largest GNU long name or pax extended header read.
+const MAX_TAR_EXTENSION: u64 = 1024 * 1024;
+/// Size of the reads of deflated members.
+const INFLATE_CHUNK: u64 = 1024 * 1024;
+/// Largest deflated member kept inflated between reads.
+const MAX_INFLATED: u64 = 64 * 1024 * 1024;
+/// Larger deflated members being read at once, each keeping where it was
+/// inflated up to.
+const MAX_CURSORS: usize = 8;
+
+#[derive(Debug, Clone, Copy)]
+enum Format {
+    Tar,
+    Zip,
+}
+
+#[derive(Debug, Clone)]
+enum Member {
+    Dir,
+    /// Tar member, stored as is at `offset`.
+    Tar {
+        offset: u64,
+        size: u64,
+    },
+    /// Zip member, described by its local header at `header`.
+    Zip {
+        header: u64,
+        method: u16,
+        compressed_size: u64,
+        size: u64,
+    },
+}
+
+/// Read only view of a tar or zip archive object.
+///
+/// The index of the archive is read when it is opened, then members are
+/// read with ranged reads of the archive. Tar members and stored zip
+/// members only fetch the requested bytes. Deflated zip members up to
+/// [`MAX_INFLATED`] bytes are inflated whole, the last one read being kept
+/// inflated for the next reads. Larger ones are inflated up to the end of
+/// each read, the next read going on from there unless it starts before.
+pub struct ArchiveStorage {
+    inner: Arc<dyn Storage>,
+    archive: String,
+    meta: Metadata,
+    members: BTreeMap<String, Member>,
+    /// Offsets of the data of the zip members read so far.
+    zip_data: RwLock<HashMap<String, u64>>,
+    /// Content of the last deflated member inflated whole.
+    inflated: RwLock<Option<(String, Arc<Vec<u8>>)>>,
+    /// Inflaters of the last larger deflated members read.
+    cursors: Mutex<VecDeque<Cursor>>,
+}
+
+/// Inflater of a deflated zip member, at the end of the last read.
+struct Cursor {
+    path: String,
+    inflater: Decompress,
+    /// Offset in the archive of the compressed data not fetched yet.
+    at: u64,
+    /// Compressed data fetched and not inflated yet.
+    input: Vec<u8>,
+    /// Content inflated and not read past yet.
+    output: Vec<u8>,
+    /// Offset in the member of the start of `output`.
+    start: u64,
+    /// Whether the end of the deflated stream was reached.
+    done: bool,
+}
+
+impl Cursor {
+    fn new(path: &str, data: u64) -> Self {
+        Self {
+            path: path.to_owned(),
+            inflater: Decompress::new(false),
+            at: data,
+            input: Vec::new(),
+            output: Vec::new(),
+            start: 0,
+            done: false,
+        }
+    }
+
+    /// Offset in the member of the end of the content inflated so far.
+    fn end(&self) -> u64 {
+        self.start + self.output.len() as u64
+    }
+
+    /// Drops the content inflated before `offset`.
+    fn skip_to(&mut self, offset: u64) {
+        let skipped = offset
+            .saturating_sub(self.start)
+            .min(self.output.len() as u64);
+
+        self.output.drain(..skipped as usize);
+        self.start += skipped;
+    }
+}
+
+impl ArchiveStorage {
+    /// Reads the index of the archive at `archive` in `inner`.
+    pub async fn open(inner: Arc<dyn Storage>, archive: &str) -> opendal::Result<Self> {
+        let meta = inner.stat(archive).await?;
+        if !meta.is_file() {
+            return Err(unsupported("archive is not a file"));
+        }
+
+        let format = match detect(archive) {
+            Some(format) => format,
+            None => sniff(inner.as_ref(), archive, meta.content_length()).await?,
+        };
+
+        let mut members = match format {
+            Format::Tar => read_tar_index(inner.as_ref(), archive, meta.content_length()).await?,
+            Format::Zip => read_zip_index(inner.as_ref(), archive, meta.content_length()).await?,
+        };
+
+        // Archives do not always hold entries for the parents of members.
+        let parents: Vec<String> = members
+            .keys()
+            .flat_map(|path| {
+                path.match_indices('/')
+                    .map(|(i, _)| path[..i].to_owned())
+                    .collect::<Vec<_>>()
+            })
+            .collect();
+        for parent in parents {
+            members.entry(parent).or_insert(Member::Dir);
+        }
+
+        info!(
+            "opened {:?} archive {:?} with {} members",
+            format,
+            archive,
+            members.len()
+        );
+
+        Ok(Self {
+            inner,
+            archive: archive.to_owned(),
+            meta,
+            members,
+            zip_data: RwLock::new(HashMap::new()),
+            inflated: RwLock::new(None),
+            cursors: Mutex::new(VecDeque::new()),
+        })
+    }
+
+    fn member(&self, path: &str) -> opendal::Result<&Member> {
+        self.members
+            .get(&key(path))
+            .ok_or_else(|| opendal::Error::new(ErrorKind::NotFound, "no such member in archive"))
+    }
+
+    fn metadata(&self, member: &Member) -> Metadata {
+        let meta = match member {
+            Member::Dir => Metadata::new(EntryMode::DIR),
+            Member::Tar { size, .. } | Member::Zip { size, .. } => {
+                Metadata::new(EntryMode::FILE).with_content_length(*size)
+            }
+        };
+
+        match self.meta.last_modified() {
+            Some(last_modified) => meta.with_last_modified(last_modified),
+            None => meta,
+        }
+    }
+
+    /// Offset of the data of the zip member at `path`, following its local
+    /// header at `header`.
+    async fn zip_data(&self, path: &str, header: u64) -> opendal::Result<u64> {
+        if let Some(offset) = self.zip_data.read().await.get(path) {
+            return Ok(*offset);
+        }
+
+        let len = self.meta.content_length();
+        let end = span(
+            header,
+            ZIP_LOCAL_HEADER_LEN,
+            len,
+            "invalid zip local header",
+        )?;
+
+        let local = self.inner.read(&self.archive, header..end).await?;
+        if local.len() < ZIP_LOCAL_HEADER_LEN as usize || le32(&local, 0) != ZIP_LOCAL_HEADER {
+            return Err(corrupted("invalid zip local header"));
+        }
+
+        let offset = span(
+            end,
+            le16(&local, 26) as u64 + le16(&local, 28) as u64,
+            len,
+            "invalid zip local header",
+        )?;
+
+        self.zip_data.write().await.insert(path.to_owned(), offset);
+
+        Ok(offset)
+    }
+
+    /// Content of the deflated member at `path` from `start` to `end`, its
+    /// compressed data being `compressed_size` bytes at `data`.
+    async fn inflate(
+        &self,
+        path: &str,
+        data: u64,
+        compressed_size: u64,
+        size: u64,
+        range: Range<u64>,
+    ) -> opendal::Result<Vec<u8>> {
+        let compressed_end = span(
+            data,
+            compressed_size,
+            self.meta.content_length(),
+            "truncated deflated zip member",
+        )?;
+
+        // Small members are inflated whole, as they tend to be read in full.
+        if size <= MAX_INFLATED {
+            let content = self.inflate_whole(path, data, compressed_end, size).await?;
+
+            let end = (range.end as usize).min(content.len());
+            let start = (range.start as usize).min(end);
+
+            return Ok(content[start..end].to_vec());
+        }
+
+        let cursor = {
+            let mut cursors = self.cursors.lock().unwrap();
+
+            cursors
+                .iter()
+                .position(|c| c.path == path && c.start <= range.start)
+                .and_then(|i| cursors.remove(i))
+        };
+
+        let mut cursor = match cursor {
+            Some(cursor) => cursor,
+            None => {
+                debug!("inflating {:?} from its start", path);
+                Cursor::new(path, data)
+            }
+        };
+
+        self.advance(&mut cursor, compressed_end, range.start, range.end)
+            .await?;
+
+        // The sizes of the archive are not trusted to bound the content.
+        let end = range.end.min(cursor.end()).min(size);
+        let start = range.start.min(end);
+        let content =
+            cursor.output[(start - cursor.start) as usize..(end - cursor.start) as usize].to_vec();
+
+        let mut cursors = self.cursors.lock().unwrap();
+        if cursors.len() >= MAX_CURSORS {
+            cursors.pop_front();
+        }
+        cursors.push_back(cursor);
+
+        Ok(content)
+    }
+
+    /// Content of the deflated member at `path`, kept for the next reads.
+    async fn inflate_whole(
+        &self,
+        path: &str,
+        data: u64,
+        compressed_end: u64,
+        size: u64,
+    ) -> opendal::Result<Arc<Vec<u8>>> {
+        if let Some((inflated, content)) = self.inflated.read().await.as_ref() {
+            if inflated == path {
+                return Ok(content.clone());
+            }
+        }
+
+        let mut cursor = Cursor::new(path, data);
+        self.advance(&mut cursor, compressed_end, 0, size).await?;
+
+        // The sizes of the archive are not trusted to bound the content.
+        let mut content = cursor.output;
+        content.truncate(size as usize);
+        let content = Arc::new(content);
+
+        *self.inflated.write().await = Some((path.to_owned(), content.clone()));
+
+        Ok(content)
+    }
+
+    /// Inflates with `cursor` up to `end`, or to the end of the member,
+    /// dropping the content before `start` on the way. The compressed data
+    /// ends at `compressed_end` in the archive.
+    async fn advance(
+        &self,
+        cursor: &mut Cursor,
+        compressed_end: u64,
+        start: u64,
+        end: u64,
+    ) -> opendal::Result<()> {
+        loop {
+            cursor.skip_to(start);
+
+            if cursor.done || cursor.end() >= end {
+                return Ok(());
+            }
+
+            if cursor.input.is_empty() && cursor.at < compressed_end {
+                let chunk_end = compressed_end.min(cursor.at.saturating_add(INFLATE_CHUNK));
+
+                cursor.input = self.inner.read(&self.archive, cursor.at..chunk_end).await?;
+                if cursor.input.is_empty() {
+                    return Err(corrupted("truncated deflated zip member"));
+                }
+                cursor.at += cursor.input.len() as u64;
+            }
+
+            cursor.output.reserve(INFLATE_CHUNK as usize);
+
+            let (total_in, total_out) = (cursor.inflater.total_in(), cursor.inflater.total_out());
+            let status = cursor
+                .inflater
+                .decompress_vec(&cursor.input, &mut cursor.output, FlushDecompress::None)
+                .map_err(|e| corrupted("invalid deflated zip member").set_source(e))?;
+            cursor
+                .input
+                .drain(..(cursor.inflater.total_in() - total_in) as usize);
+
+            match status {
+                Status::StreamEnd => cursor.done = true,
+                // Neither input is consumed nor output produced.
+                _ if cursor.inflater.total_in() == total_in
+                    && cursor.inflater.total_out() == total_out =>
+                {
+                    return Err(corrupted(if cursor.input.is_empty() {
+                        "truncated deflated zip member"
+                    } else {
+                        "invalid deflated zip member"
+                    }));
+                }
+                _ => {}
+            }
+        }
+    }
+}
+
+fn key(path: &str) -> String {
+    path.trim_matches('/').to_owned()
+}
+
+fn read_only() -> opendal::Error {
+    opendal::Error::new(ErrorKind::PermissionDenied, "archives are read only")
+}
+
+fn unsupported(message: &'static str) -> opendal::Error {
+    opendal::Error::new(ErrorKind::Unsupported, message)
+}
+
+fn corrupted(message: &'static str) -> opendal::Error {
+    opendal::Error::new(ErrorKind::Unexpected, message)
+}
+
+/// End of the `size` bytes at `start`, failing with `message` if they do not
+/// fit in an archive of `len` bytes.
+fn span(start: u64, size: u64, len: u64, message: &'static str) -> opendal::Result<u64> {
+    start
+        .checked_add(size)
+        .filter(|end| *end <= len)
+        .ok_or_else(|| corrupted(message))
+}
+
+fn detect(archive: &str) -> Option<Format> {
+    let lower = archive.to_ascii_lowercase();
+
+    if lower.ends_with(".zip") {
+        Some(Format::Zip)
+    } else if lower.ends_with(".tar") {
+        Some(Format::Tar)
+    } else {
+        None
+    }
+}
+
+/// Guesses the format of `archive` from its first bytes.
+async fn sniff(storage: &dyn Storage, archive: &str, len: u64) -> opendal::Result<Format> {
+    let head = storage.read(archive, 0..len.min(TAR_BLOCK)).await?;
+
+    if head.starts_with(b"PK") {
+        Ok(Format::Zip)
+    } else if head.len() == TAR_BLOCK as usize && &head[257..262] == b"ustar" {
+        Ok(Format::Tar)
+    } else {
+        Err(unsupported("unknown archive format"))
+    }
+}
+
+fn le16(buf: &[u8], at: usize) -> u16 {
+    u16::from_le_bytes([buf[at], buf[at + 1]])
+}
+
+fn le32(buf: &[u8], at: usize) -> u32 {
+    u32::from_le_bytes(buf[at..at + 4].try_into().unwrap())
+}
+
+fn le64(buf: &[u8], at: usize) -> u64 {
+    u64::from_le_bytes(buf[at..at + 8].try_into().unwrap())
+}
+
+/// Parses a numeric tar header field, in octal or in GNU base-256.
+fn tar_number(field: &[u8]) -> u64 {
+    if field[0] & 0x80 != 0 {
+        return field[1..]
+            .iter()
+            .fold((field[0] & 0x7f) as u64, |n, b| (n << 8) | *b as u64);
+    }
+
+    field
+        .iter()
+        .skip_while(|b| **b == b' ')
+        .take_while(|b| (b'0'..=b'7').contains(*b))
+        .fold(0, |n, b| (n << 3) | (b - b'0') as u64)
+}
+
+fn tar_string(field: &[u8]) -> String {
+    let end = field.iter().position(|b| *b == 0).unwrap_or(field.len());
+
+    String::from_utf8_lossy(&field[..end]).into_owned()
+}
+
+/// Values of the `path` and `size` records of a pax extended header.
+fn pax_records(data: &[u8]) -> (Option<String>, Option<u64>) {
+    let mut path = None;
+    let mut size = None;
+    let mut rest = data;
+
+    while let Some(space) = rest.iter().position(|b| *b == b' ') {
+        let len: usize = match std::str::from_utf8(&rest[..space])
+            .ok()
+            .and_then(|l| l.parse().ok())
+        {
+            Some(len) if len > space && len <= rest.len() => len,
+            _ => break,
+        };
+
+        let record = String::from_utf8_lossy(&rest[space + 1..len]);
+        if let Some((key, value)) = record.trim_end_matches('\n').split_once('=') {
+            match key {
+                "path" => path = Some(value.to_owned()),
+                "size" => size = value.parse().ok(),
+                _ => {}
+            }
+        }
+
+        rest = &rest[len..];
+    }
+
+    (path, size)
+}
+
+async fn read_tar_index(
+    storage: &dyn Storage,
+    archive: &str,
+    len: u64,
+) -> opendal::Result<BTreeMap<String, Member>> {
+    let mut members = BTreeMap::new();
+    let mut offset = 0;
+    // Overrides of the next member from GNU long name or pax headers.
+    let mut next_path: Option<String> = None;
+    let mut next_size: Option<u64> = None;
+
+    while offset.checked_add(TAR_BLOCK).is_some_and(|end| end <= len) {
+        let header = storage.read(archive, offset..offset + TAR_BLOCK).await?;
+        if header.len() < TAR_BLOCK as usize {
+            return Err(corrupted("truncated tar header"));
+        }
+        if header.iter().all(|b| *b == 0) {
+            break;
+        }
+
+        let data = offset + TAR_BLOCK;
+        let mut size = tar_number(&header[124..136]);
+
+        match header[156] {
+            b'L' | b'x' if size > MAX_TAR_EXTENSION => {
+                return Err(corrupted("tar extended header too long"));
+            }
+            b'L' => {
+                let end = span(data, size, len, "truncated tar member")?;
+                let name = storage.read(archive, data..end).await?;
+                next_path = Some(tar_string(&name));
+            }
+            b'x' => {
+                let end = span(data, size, len, "truncated tar member")?;
+                let records = storage.read(archive, data..end).await?;
+                let (path, size) = pax_records(&records);
+                next_path = path.or(next_path);
+                next_size = size.or(next_size);
+            }
+            kind => {
+                let path = next_path.take().unwrap_or_else(|| {
+                    let name = tar_string(&header[0..100]);
+                    let prefix = tar_string(&header[345..500]);
+
+                    if &header[257..262] == b"ustar" && !prefix.is_empty() {
+                        format!("{}/{}", prefix, name)
+                    } else {
+                        name
+                    }
+                });
+                size = next_size.take().unwrap_or(size);
+                span(data, size, len, "truncated tar member")?;
+                let path = key(path.trim_start_matches("./"));
+
+                match kind {
+                    b'0' | 0 | b'7' if !path.is_empty() => {
+                        members.insert(path, Member::Tar { offset: data, size });
+                    }
+                    b'5' if !path.is_empty() => {
+                        members.insert(path, Member::Dir);
+                    }
+                    _ => debug!("skipping tar member {:?} of type {}", path, kind),
+                }
+            }
+        }
+
+        offset = size
+            .div_ceil(TAR_BLOCK)
+            .checked_mul(TAR_BLOCK)
+            .and_then(|padded| data.checked_add(padded))
+            .ok_or_else(|| corrupted("truncated tar member"))?;
+    }
+
+    Ok(members)
+}
+
+async fn read_zip_index(
+    storage: &dyn Storage,
+    archive: &str,
+    len: u64,
+) -> opendal::Result<BTreeMap<String, Member>> {
+    if len < ZIP_END_LEN {
+        return Err(corrupted("zip archive too short"));
+    }
+
+    // The end record is followed by a comment of at most 64 KiB.
+    let tail_start = len.saturating_sub(ZIP_END_LEN + u16::MAX as u64);
+    let tail = storage.read(archive, tail_start..len).await?;
+
+    let end = (0..=tail.len() - ZIP_END_LEN as usize)
+        .rev()
+        .find(|i| le32(&tail, *i) == ZIP_END)
+        .ok_or_else(|| corrupted("zip end of central directory not found"))?;
+
+    let mut entries = le16(&tail, end + 10) as u64;
+    let mut cd_size = le32(&tail, end + 12) as u64;
+    let mut cd_offset = le32(&tail, end + 16) as u64;
+
+    if end >= 20 && le32(&tail, end - 20) == ZIP64_LOCATOR {
+        let zip64_end = le64(&tail, end - 20 + 8);
+        let record_end = span(zip64_end, 56, len, "invalid zip64 end of central directory")?;
+        let record = storage.read(archive, zip64_end..record_end).await?;
+        if record.len() < 56 || le32(&record, 0) != ZIP64_END {
+            return Err(corrupted("invalid zip64 end of central directory"));
+        }
+
+        entries = le64(&record, 32);
+        cd_size = le64(&record, 40);
+        cd_offset = le64(&record, 48);
+    }
+
+    let cd_end = span(cd_offset, cd_size, len, "truncated zip central directory")?;
+    let cd = storage.read(archive, cd_offset..cd_end).await?;
+
+    let mut members = BTreeMap::new();
+    let mut at = 0;
+
+    for _ in 0..entries {
+        if at + 46 > cd.len() || le32(&cd, at) != ZIP_CENTRAL_HEADER {
+            return Err(corrupted("invalid zip central directory"));
+        }
+
+        let flags = le16(&cd, at + 8);
+        let method = le16(&cd, at + 10);
+        let mut compressed_size = le32(&cd, at + 20) as u64;
+        let mut size = le32(&cd, at + 24) as u64;
+        let name_len = le16(&cd, at + 28) as usize;
+        let extra_len = le16(&cd, at + 30) as usize;
+        let comment_len = le16(&cd, at + 32) as usize;
+        let mut header = le32(&cd, at + 42) as u64;
+
+        let name_end = at + 46 + name_len;
+        let extra_end = name_end + extra_len;
+        if extra_end + comment_len > cd.len() {
+            return Err(corrupted("truncated zip central directory"));
+        }
+
+        let name = String::from_utf8_lossy(&cd[at + 46..name_end]).into_owned();
+
+        // Sizes and offsets that do not fit are moved to the zip64 extra field.
+        let mut extra = &cd[name_end..extra_end];
+        while extra.len() >= 4 {
+            let id = le16(extra, 0);
+            let field_len = (le16(extra, 2) as usize).min(extra.len() - 4);
+            let mut field = &extra[4..4 + field_len];
+
+            if id == 0x0001 {
+                for value in [&mut size, &mut compressed_size, &mut header] {
+                    if *value == u32::MAX as u64 && field.len() >= 8 {
+                        *value = le64(field, 0);
+                        field = &field[8..];
+                    }
+                }
+            }
+
+            extra = &extra[4 + field_len..];
+        }
+
+        at = extra_end + comment_len;
+
+        let path = key(&name);
+        if path.is_empty() {
+            continue;
+        }
+
+        if name.ends_with('/') {
+            members.insert(path, Member::Dir);
+        } else if flags & 1 != 0 {
+            debug!("skipping encrypted zip member {:?}", path);
+        } else {
+            span(
+                header,
+                ZIP_LOCAL_HEADER_LEN.saturating_add(compressed_size),
+                len,
+                "truncated zip member",
+            )?;
+
+            members.insert(
+                path,
+                Member::Zip {
+                    header,
+                    method,
+                    compressed_size,
+                    size,
+                },
+            );
+        }
+    }
+
+    Ok(members)
+}
+
+#[async_trait]
+impl Storage for ArchiveStorage {
+    fn info(&self) -> OperatorInfo {
+        self.inner.info()
+    }
+
+    async fn stat(&self, path: &str) -> opendal::Result<Metadata> {
+        if key(path).is_empty() {
+            return Ok(self.metadata(&Member::Dir));
+        }
+
+        Ok(self.metadata(self.member(path)?))
+    }
+
+    async fn read(&self, path: &str, range: Range<u64>) -> opendal::Result<Vec<u8>> {
+        let member = self.member(path)?.clone();
+
+        let size = match member {
+            Member::Dir => return Err(opendal::Error::new(ErrorKind::IsADirectory, "is a dir")),
+            Member::Tar { size, .. } | Member::Zip { size, .. } => size,
+        };
+        let end = range.end.min(size);
+        let start = range.start.min(end);
+
+        match member {
+            Member::Tar { offset, .. } => {
+                self.inner
+                    .read(&self.archive, offset + start..offset + end)
+                    .await
+            }
+            Member::Zip {
+                header,
+                method: ZIP_STORED,
+                ..
+            } => {
+                let data = self.zip_data(&key(path), header).await?;
+                span(
+                    data,
+                    size,
+                    self.meta.content_length(),
+                    "truncated zip member",
+                )?;
+
+                self.inner
+                    .read(&self.archive, data + start..data + end)
+                    .await
+            }
+            Member::Zip {
+                header,
+                method: ZIP_DEFLATED,
+                compressed_size,
+                ..
+            } => {
+                let data = self.zip_data(&key(path), header).await?;
+
+                self.inflate(&key(path), data, compressed_size, size, start..end)
+                    .await
+            }
+            _ => Err(unsupported("unsupported zip compression method")),
+        }
+    }
+
+    async fn write_with_metadata(
+        &self,
+        _path: &str,
+        _data: Vec<u8>,
+        _metadata: WriteMetadata,
+    ) -> opendal::Result<()> {
+        Err(read_only())
+    }
+
+    async fn append(&self, _path: &str, _data: Vec<u8>) -> opendal::Result<()> {
+        Err(read_only())
+    }
+
+    async fn list(&self, path: &str) -> opendal::Result<Vec<Entry>> {
+        let dir = key(path);
+        let prefix = if dir.is_empty() {
+            String::new()
+        } else {
+            match self.member(&dir)? {
+                Member::Dir => format!("{}/", dir),
+                _ => return Err(opendal::Error::new(ErrorKind::NotADirectory, "not a dir")),
+            }
+        };
+
+        let entries = self
+            .members
+            .range(prefix.clone()..)
+            .take_while(|(path, _)| path.starts_with(&prefix))
+            .filter(|(path, _)| !path[prefix.len()..].contains('/'))
+            .map(|(path, member)| {
+                let path = match member {
+                    Member::Dir => format!("{}/", path),
+                    _ => path.to_owned(),
+                };
+
+                Entry::new(&path, self.metadata(member))
+            })
+            .collect();
+
+        Ok(entries)
+    }
+
+    async fn create_dir(&self, _path: &str) -> opendal::Result<()> {
+        Err(read_only())
+    }
+
+    async fn delete(&self, _path: &str) -> opendal::Result<()> {
+        Err(read_only())
+    }
+}
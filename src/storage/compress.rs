@@ -0,0 +1,672 @@
+use std::{
+    collections::HashMap,
+    io::{Read, Write},
+    ops::Range,
+    sync::{Arc, Weak},
+    time::{Duration, Instant},
+};
+
+use async_trait::async_trait;
+use flate2::{read::GzDecoder, write::GzEncoder};
+use log::{debug, warn};
+use opendal::{ErrorKind, Metadata, OperatorInfo};
+use tokio::sync::Mutex;
+
+use super::{Entry, Storage, WriteMetadata};
+use crate::schema::Compression;
+
+const MAGIC: &[u8] = b"ODMZIP02";
+const HEADER_LEN: u64 = MAGIC.len() as u64;
+const FOOTER_LEN: u64 = 8 + 8 + 1 + MAGIC.len() as u64;
+const INDEX_ENTRY_LEN: u64 = 16;
+
+const CHUNK_SIZE: u64 = 128 * 1024;
+
+/// Appended bytes kept in memory before they are sealed.
+const MAX_PENDING: usize = 32 * CHUNK_SIZE as usize;
+
+/// Time without appends after which an object is sealed.
+const SEAL_AFTER: Duration = Duration::from_secs(2);
+
+const SEAL_INTERVAL: Duration = Duration::from_secs(1);
+
+const ZSTD: u8 = 1;
+const GZIP: u8 = 2;
+
+/// User metadata key holding the uncompressed size of an object, as
+/// `<size>:<stored size>` for it to be ignored once appended to.
+const LOGICAL_SIZE_KEY: &str = "opendal-mount-logical-size";
+
+/// Compresses content before it reaches the inner storage.
+///
+/// Objects are stored as a container of independently compressed chunks of
+/// up to `CHUNK_SIZE` bytes, each seal of appended data adding its chunks
+/// and a trailer indexing every chunk of the object:
+///
+/// ```text
+/// MAGIC | (chunk... | index entry... | footer)...
+/// index entry: offset (u64 LE) | stored len (u32 LE) | len (u32 LE)
+/// footer: size (u64 LE) | chunks (u64 LE) | algorithm | MAGIC
+/// ```
+///
+/// Ranged reads fetch the last footer and index, then only the chunks they
+/// overlap. The trailers left behind by earlier seals are never read. The
+/// uncompressed size is also kept in the object metadata so that `stat`
+/// does not need to read the footer when the backend supports user
+/// metadata and the object was not appended to since written.
+///
+/// Appends are buffered in memory and sealed once the object is left alone
+/// for [`SEAL_AFTER`], once the buffer reaches [`MAX_PENDING`] bytes, or on
+/// [`CompressedStorage::seal_all`]. Sealing appends to the object, and only
+/// rewrites it on backends unable to append.
+///
+/// Objects that are not containers, as written before the mount was
+/// compressed, are passed through as they are.
+pub struct CompressedStorage {
+    inner: Arc<dyn Storage>,
+    compression: Compression,
+    /// Bytes appended to objects and not sealed yet, locked per object while
+    /// it is changed.
+    pending: std::sync::Mutex<HashMap<String, Slot>>,
+}
+
+type Slot = Arc<Mutex<Option<Pending>>>;
+
+struct Pending {
+    data: Vec<u8>,
+    appended: Instant,
+}
+
+/// Decoded footer and chunk index of a container.
+struct Index {
+    size: u64,
+    algorithm: u8,
+    /// Offset, stored length and length of each chunk.
+    entries: Vec<(u64, u32, u32)>,
+    /// Position of each chunk in the content, the last one being its size.
+    starts: Vec<u64>,
+}
+
+impl Index {
+    fn decode(index_start: u64, index: &[u8], footer: &[u8]) -> opendal::Result<Self> {
+        let (size, chunks, algorithm) =
+            decode_footer(footer)?.ok_or_else(|| compress_error("corrupted footer"))?;
+
+        if index.len() as u64 != chunks * INDEX_ENTRY_LEN {
+            return Err(compress_error("corrupted chunk index"));
+        }
+
+        let mut entries = Vec::with_capacity(chunks as usize);
+        let mut starts = vec![0];
+
+        for entry in index.chunks(INDEX_ENTRY_LEN as usize) {
+            let offset = u64::from_le_bytes(entry[0..8].try_into().unwrap());
+            let stored = u32::from_le_bytes(entry[8..12].try_into().unwrap());
+            let len = u32::from_le_bytes(entry[12..16].try_into().unwrap());
+
+            let in_container = offset >= HEADER_LEN
+                && offset
+                    .checked_add(stored as u64)
+                    .is_some_and(|end| end <= index_start);
+            if !in_container || len as u64 > CHUNK_SIZE {
+                return Err(compress_error("corrupted chunk index"));
+            }
+
+            entries.push((offset, stored, len));
+            starts.push(starts.last().unwrap() + len as u64);
+        }
+
+        if *starts.last().unwrap() != size {
+            return Err(compress_error("corrupted chunk index"));
+        }
+
+        Ok(Self {
+            size,
+            algorithm,
+            entries,
+            starts,
+        })
+    }
+
+    /// Chunk holding the byte at `offset`.
+    fn chunk_at(&self, offset: u64) -> usize {
+        self.starts.partition_point(|start| *start <= offset) - 1
+    }
+}
+
+impl CompressedStorage {
+    pub fn new(inner: Arc<dyn Storage>, compression: Compression) -> Self {
+        Self {
+            inner,
+            compression,
+            pending: std::sync::Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Seals the objects left alone for [`SEAL_AFTER`] periodically while
+    /// the storage is alive.
+    pub fn spawn_sealer(self: &Arc<Self>) {
+        let compressed: Weak<Self> = Arc::downgrade(self);
+
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(SEAL_INTERVAL);
+
+            loop {
+                interval.tick().await;
+
+                match compressed.upgrade() {
+                    Some(compressed) => compressed.seal_idle(SEAL_AFTER).await,
+                    None => break,
+                }
+            }
+        });
+    }
+
+    /// Writes every buffered append to its container.
+    pub async fn seal_all(&self) {
+        self.seal_idle(Duration::ZERO).await;
+    }
+
+    /// Seals the objects not appended to for `idle`, each object being only
+    /// locked while it is sealed.
+    async fn seal_idle(&self, idle: Duration) {
+        let slots: Vec<(String, Slot)> = self
+            .pending
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(path, slot)| (path.clone(), slot.clone()))
+            .collect();
+
+        for (path, slot) in slots {
+            let mut pending = slot.lock().await;
+
+            if pending
+                .as_ref()
+                .is_some_and(|p| p.appended.elapsed() >= idle)
+            {
+                let p = pending.take().unwrap();
+
+                match self.seal(&path, &p.data).await {
+                    Ok(()) => {}
+                    Err(e) if e.kind() == ErrorKind::NotFound => {
+                        warn!("dropping appends to removed {:?}", path);
+                    }
+                    Err(e) => {
+                        warn!("unable to seal {:?}, retrying later: {}", path, e);
+                        *pending = Some(p);
+                    }
+                }
+            }
+
+            drop(pending);
+            self.release(&path, slot);
+        }
+    }
+
+    /// Lock of the object at `path`, to be given back to
+    /// [`CompressedStorage::release`].
+    fn slot(&self, path: &str) -> Slot {
+        self.pending
+            .lock()
+            .unwrap()
+            .entry(path.to_owned())
+            .or_default()
+            .clone()
+    }
+
+    /// Lock of the object at `path`, if it has pending appends or is being
+    /// changed.
+    fn existing_slot(&self, path: &str) -> Option<Slot> {
+        self.pending.lock().unwrap().get(path).cloned()
+    }
+
+    /// Forgets the lock of the object at `path` once nothing is pending and
+    /// nobody else holds it.
+    fn release(&self, path: &str, slot: Slot) {
+        let mut slots = self.pending.lock().unwrap();
+
+        let unused = slots.get(path).is_some_and(|s| Arc::ptr_eq(s, &slot))
+            && Arc::strong_count(&slot) == 2
+            && slot.try_lock().is_ok_and(|pending| pending.is_none());
+
+        if unused {
+            slots.remove(path);
+        }
+    }
+
+    fn algorithm(&self) -> u8 {
+        match self.compression {
+            Compression::Zstd => ZSTD,
+            Compression::Gzip => GZIP,
+        }
+    }
+
+    /// Index of the container at `path` of `stored` bytes, `None` if the
+    /// object is not a container.
+    async fn read_index(&self, path: &str, stored: u64) -> opendal::Result<Option<Index>> {
+        if stored < HEADER_LEN + FOOTER_LEN {
+            return Ok(None);
+        }
+
+        let footer = self.inner.read(path, stored - FOOTER_LEN..stored).await?;
+        let Some((_, chunks, _)) = decode_footer(&footer)? else {
+            return Ok(None);
+        };
+
+        let index_start = chunks
+            .checked_mul(INDEX_ENTRY_LEN)
+            .and_then(|len| (stored - FOOTER_LEN).checked_sub(len))
+            .filter(|start| *start >= HEADER_LEN)
+            .ok_or_else(|| compress_error("corrupted chunk index"))?;
+
+        let index = self
+            .inner
+            .read(path, index_start..stored - FOOTER_LEN)
+            .await?;
+
+        Index::decode(index_start, &index, &footer).map(Some)
+    }
+
+    /// Uncompressed size of the object at `path`.
+    async fn logical_size(&self, path: &str, meta: &Metadata) -> opendal::Result<u64> {
+        let stored = meta.content_length();
+        let size = meta
+            .user_metadata()
+            .and_then(|m| m.get(LOGICAL_SIZE_KEY))
+            .and_then(|s| s.split_once(':'))
+            .filter(|(_, at)| at.parse() == Ok(stored))
+            .and_then(|(size, _)| size.parse().ok());
+
+        if let Some(size) = size {
+            return Ok(size);
+        }
+
+        match self.read_index(path, stored).await? {
+            Some(index) => Ok(index.size),
+            None => Ok(stored),
+        }
+    }
+
+    /// Reads `range` from the container, without the pending appends.
+    async fn read_sealed(&self, path: &str, range: Range<u64>) -> opendal::Result<Vec<u8>> {
+        let stored = self.inner.stat(path).await?.content_length();
+
+        let Some(index) = self.read_index(path, stored).await? else {
+            let end = range.end.min(stored);
+            if range.start >= end {
+                return Ok(Vec::new());
+            }
+
+            return self.inner.read(path, range.start..end).await;
+        };
+
+        let end = range.end.min(index.size);
+
+        if range.start >= end {
+            return Ok(Vec::new());
+        }
+
+        let first = index.chunk_at(range.start);
+        let last = index.chunk_at(end - 1);
+
+        // Chunks of different seals are apart, the trailers in between are
+        // read along with them.
+        let span_start = index.entries[first..=last]
+            .iter()
+            .map(|(offset, _, _)| *offset)
+            .min()
+            .unwrap();
+        let span_end = index.entries[first..=last]
+            .iter()
+            .map(|(offset, stored, _)| offset + *stored as u64)
+            .max()
+            .unwrap();
+        let data = self.inner.read(path, span_start..span_end).await?;
+
+        let mut plain = Vec::with_capacity((end - range.start) as usize);
+        for (offset, stored, len) in &index.entries[first..=last] {
+            let start = (offset - span_start) as usize;
+            let chunk = decompress(index.algorithm, &data[start..start + *stored as usize])?;
+
+            if chunk.len() != *len as usize {
+                return Err(compress_error("corrupted chunk"));
+            }
+            plain.extend(chunk);
+        }
+
+        let skip = (range.start - index.starts[first]) as usize;
+        let take = (end - range.start) as usize;
+
+        Ok(plain[skip..skip + take].to_vec())
+    }
+
+    async fn write_container(
+        &self,
+        path: &str,
+        data: &[u8],
+        mut metadata: WriteMetadata,
+    ) -> opendal::Result<()> {
+        let mut container = MAGIC.to_vec();
+        container.extend(encode_segment(self.algorithm(), HEADER_LEN, vec![], data)?);
+
+        metadata.user_metadata.insert(
+            LOGICAL_SIZE_KEY.to_owned(),
+            format!("{}:{}", data.len(), container.len()),
+        );
+
+        self.inner
+            .write_with_metadata(path, container, metadata)
+            .await
+    }
+
+    /// Adds `data` to the container at `path`, appending its chunks along
+    /// with a new index. Objects that are not containers are appended to
+    /// as they are.
+    async fn seal(&self, path: &str, data: &[u8]) -> opendal::Result<()> {
+        let meta = self.inner.stat(path).await?;
+        let stored = meta.content_length();
+
+        if stored == 0 {
+            return self
+                .write_container(path, data, WriteMetadata::from(&meta))
+                .await;
+        }
+
+        let Some(index) = self.read_index(path, stored).await? else {
+            return self.inner.append(path, data.to_vec()).await;
+        };
+
+        let segment = encode_segment(index.algorithm, stored, index.entries, data)?;
+
+        match self.inner.append(path, segment.clone()).await {
+            Err(e) if e.kind() == ErrorKind::Unsupported => {
+                debug!("rewriting {:?} to append {} bytes", path, data.len());
+
+                let mut container = self.inner.read(path, 0..stored).await?;
+                container.extend(segment);
+
+                let mut metadata = WriteMetadata::from(&meta);
+                metadata.user_metadata.insert(
+                    LOGICAL_SIZE_KEY.to_owned(),
+                    format!("{}:{}", index.size + data.len() as u64, container.len()),
+                );
+
+                self.inner
+                    .write_with_metadata(path, container, metadata)
+                    .await
+            }
+            appended => appended,
+        }
+    }
+}
+
+fn compress_error(e: impl ToString) -> opendal::Error {
+    opendal::Error::new(ErrorKind::Unexpected, e.to_string())
+}
+
+fn compress(algorithm: u8, data: &[u8]) -> opendal::Result<Vec<u8>> {
+    match algorithm {
+        ZSTD => zstd::bulk::compress(data, 0).map_err(compress_error),
+        GZIP => {
+            let mut encoder = GzEncoder::new(Vec::new(), flate2::Compression::default());
+            encoder.write_all(data).map_err(compress_error)?;
+            encoder.finish().map_err(compress_error)
+        }
+        _ => Err(compress_error("unknown compression algorithm")),
+    }
+}
+
+fn decompress(algorithm: u8, data: &[u8]) -> opendal::Result<Vec<u8>> {
+    match algorithm {
+        ZSTD => zstd::bulk::decompress(data, CHUNK_SIZE as usize).map_err(compress_error),
+        GZIP => {
+            let mut out = Vec::with_capacity(CHUNK_SIZE as usize);
+            GzDecoder::new(data)
+                .take(CHUNK_SIZE + 1)
+                .read_to_end(&mut out)
+                .map_err(compress_error)?;
+            Ok(out)
+        }
+        _ => Err(compress_error("unknown compression algorithm")),
+    }
+}
+
+/// Size, number of chunks and algorithm of a container, `None` if `footer`
+/// is not the footer of a container.
+fn decode_footer(footer: &[u8]) -> opendal::Result<Option<(u64, u64, u8)>> {
+    if footer.len() as u64 != FOOTER_LEN || !footer.ends_with(MAGIC) {
+        return Ok(None);
+    }
+
+    let size = u64::from_le_bytes(footer[0..8].try_into().unwrap());
+    let chunks = u64::from_le_bytes(footer[8..16].try_into().unwrap());
+
+    Ok(Some((size, chunks, footer[16])))
+}
+
+/// Chunks of `data` compressed with `algorithm`, followed by the index of
+/// `entries` and of these chunks, the segment starting at `offset` in the
+/// container.
+fn encode_segment(
+    algorithm: u8,
+    offset: u64,
+    mut entries: Vec<(u64, u32, u32)>,
+    data: &[u8],
+) -> opendal::Result<Vec<u8>> {
+    let mut segment = Vec::new();
+
+    for chunk in data.chunks(CHUNK_SIZE as usize) {
+        let compressed = compress(algorithm, chunk)?;
+
+        entries.push((
+            offset + segment.len() as u64,
+            compressed.len() as u32,
+            chunk.len() as u32,
+        ));
+        segment.extend(compressed);
+    }
+
+    let mut size = 0;
+    for (offset, stored, len) in &entries {
+        segment.extend(offset.to_le_bytes());
+        segment.extend(stored.to_le_bytes());
+        segment.extend(len.to_le_bytes());
+        size += *len as u64;
+    }
+
+    segment.extend(size.to_le_bytes());
+    segment.extend((entries.len() as u64).to_le_bytes());
+    segment.push(algorithm);
+    segment.extend(MAGIC);
+
+    Ok(segment)
+}
+
+#[async_trait]
+impl Storage for CompressedStorage {
+    fn info(&self) -> OperatorInfo {
+        self.inner.info()
+    }
+
+    async fn stat(&self, path: &str) -> opendal::Result<Metadata> {
+        let mut meta = self.inner.stat(path).await?;
+
+        if meta.is_file() {
+            let pending = match self.existing_slot(path) {
+                Some(slot) => slot
+                    .lock()
+                    .await
+                    .as_ref()
+                    .map_or(0, |p| p.data.len() as u64),
+                None => 0,
+            };
+
+            let size = self.logical_size(path, &meta).await?;
+            meta.set_content_length(size + pending);
+        }
+
+        Ok(meta)
+    }
+
+    async fn read(&self, path: &str, range: Range<u64>) -> opendal::Result<Vec<u8>> {
+        let Some(slot) = self.existing_slot(path) else {
+            return self.read_sealed(path, range).await;
+        };
+
+        // Held for the pending appends not to be sealed while read.
+        let pending = slot.lock().await;
+
+        let Some(p) = pending.as_ref() else {
+            drop(pending);
+            return self.read_sealed(path, range).await;
+        };
+
+        let meta = self.inner.stat(path).await?;
+        let sealed = self.logical_size(path, &meta).await?;
+
+        let mut data = if range.start < sealed {
+            self.read_sealed(path, range.start..range.end.min(sealed))
+                .await?
+        } else {
+            Vec::new()
+        };
+
+        let start = range.start.saturating_sub(sealed).min(p.data.len() as u64) as usize;
+        let end = range.end.saturating_sub(sealed).min(p.data.len() as u64) as usize;
+        if start < end {
+            data.extend_from_slice(&p.data[start..end]);
+        }
+
+        Ok(data)
+    }
+
+    async fn write_with_metadata(
+        &self,
+        path: &str,
+        data: Vec<u8>,
+        metadata: WriteMetadata,
+    ) -> opendal::Result<()> {
+        let slot = self.slot(path);
+        let mut pending = slot.lock().await;
+
+        // The object is replaced, along with what was appended to it.
+        *pending = None;
+        let written = self.write_container(path, &data, metadata).await;
+
+        drop(pending);
+        self.release(path, slot);
+
+        written
+    }
+
+    async fn append(&self, path: &str, data: Vec<u8>) -> opendal::Result<()> {
+        let slot = self.slot(path);
+        let mut pending = slot.lock().await;
+
+        let appended = async {
+            let buffered = match pending.as_ref() {
+                Some(p) => p.data.len(),
+                None => {
+                    self.inner.stat(path).await?;
+                    0
+                }
+            };
+
+            if buffered + data.len() < MAX_PENDING {
+                let p = pending.get_or_insert_with(|| Pending {
+                    data: Vec::new(),
+                    appended: Instant::now(),
+                });
+                p.data.extend(data);
+                p.appended = Instant::now();
+
+                return Ok(());
+            }
+
+            // What was buffered before stays pending if the seal fails.
+            let mut sealed = pending.as_ref().map_or_else(Vec::new, |p| p.data.clone());
+            sealed.extend(data);
+            self.seal(path, &sealed).await?;
+
+            *pending = None;
+
+            Ok(())
+        }
+        .await;
+
+        drop(pending);
+        self.release(path, slot);
+
+        appended
+    }
+
+    async fn list(&self, path: &str) -> opendal::Result<Vec<Entry>> {
+        self.inner.list(path).await
+    }
+
+    async fn create_dir(&self, path: &str) -> opendal::Result<()> {
+        self.inner.create_dir(path).await
+    }
+
+    async fn delete(&self, path: &str) -> opendal::Result<()> {
+        let slot = self.slot(path);
+        let mut pending = slot.lock().await;
+
+        *pending = None;
+        let deleted = self.inner.delete(path).await;
+
+        drop(pending);
+        self.release(path, slot);
+
+        deleted
+    }
+
+    async fn rename(&self, from: &str, to: &str) -> opendal::Result<()> {
+        if from == to {
+            return self.inner.rename(from, to).await;
+        }
+
+        // Locked in a fixed order for two renames not to wait on each other.
+        let from_slot = self.slot(from);
+        let to_slot = self.slot(to);
+        let (mut from_pending, mut to_pending) = if from < to {
+            let from_pending = from_slot.lock().await;
+            (from_pending, to_slot.lock().await)
+        } else {
+            let to_pending = to_slot.lock().await;
+            (from_slot.lock().await, to_pending)
+        };
+
+        let renamed = async {
+            if let Some(p) = from_pending.as_ref() {
+                self.seal(from, &p.data).await?;
+                *from_pending = None;
+            }
+
+            self.inner.rename(from, to).await?;
+            *to_pending = None;
+
+            Ok(())
+        }
+        .await;
+
+        drop(from_pending);
+        drop(to_pending);
+        self.release(from, from_slot);
+        self.release(to, to_slot);
+
+        renamed
+    }
+
+    async fn list_versions(&self, path: &str) -> opendal::Result<Vec<Entry>> {
+        self.inner.list_versions(path).await
+    }
+
+    fn at_version(&self, version: &str) -> opendal::Result<Arc<dyn Storage>> {
+        Ok(Arc::new(Self::new(
+            self.inner.at_version(version)?,
+            self.compression,
+        )))
+    }
+}
@@ -0,0 +1,355 @@
+use std::{ops::Range, path::Path, sync::Arc};
+
+use aes_gcm::{
+    aead::{rand_core::RngCore, Aead, AeadCore, KeyInit, OsRng, Payload},
+    Aes256Gcm, Key, Nonce,
+};
+use async_trait::async_trait;
+use log::warn;
+use opendal::{ErrorKind, Metadata, OperatorInfo};
+
+use super::{Entry, Storage, WriteMetadata};
+use crate::errors::{OpendalMountError, OpendalMountResult};
+
+const MAGIC: &[u8] = b"ODMENC02";
+const FILE_ID_LEN: u64 = 16;
+const HEADER_LEN: u64 = MAGIC.len() as u64 + FILE_ID_LEN;
+
+const CHUNK_SIZE: u64 = 64 * 1024;
+const NONCE_LEN: u64 = 12;
+const TAG_LEN: u64 = 16;
+const OVERHEAD: u64 = NONCE_LEN + TAG_LEN;
+const STRIDE: u64 = CHUNK_SIZE + OVERHEAD;
+
+/// Encrypts content with AES-256-GCM before it reaches the inner storage.
+///
+/// Objects are made of a header, a magic followed by a random id, and of
+/// independently sealed chunks of `CHUNK_SIZE` plaintext bytes. Each chunk
+/// carries its own random nonce and is bound to the id of its object, to its
+/// index and to whether it is the last one, so ranged reads only fetch and
+/// open the chunks they overlap, and the plaintext size can be derived from
+/// the stored size alone. Chunks moved to another object or position fail
+/// to open, as does an object cut short, unless cut where an earlier append
+/// ended, which only rolls it back to an earlier content.
+pub struct EncryptedStorage {
+    inner: Arc<dyn Storage>,
+    cipher: Aes256Gcm,
+}
+
+impl EncryptedStorage {
+    pub fn new(inner: Arc<dyn Storage>, key: &[u8]) -> OpendalMountResult<Self> {
+        if key.len() != 32 {
+            return Err(OpendalMountError::InvalidKey(format!(
+                "expected 32 bytes, got {}",
+                key.len()
+            )));
+        }
+
+        Ok(Self {
+            inner,
+            cipher: Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(key)),
+        })
+    }
+
+    /// Loads the key from a file holding either 32 raw bytes or 64 hex digits.
+    pub fn from_key_file(inner: Arc<dyn Storage>, path: &Path) -> OpendalMountResult<Self> {
+        let content = std::fs::read(path)?;
+
+        let key = match std::str::from_utf8(&content).map(str::trim) {
+            Ok(hex) if hex.len() == 64 => decode_hex(hex).ok_or_else(|| {
+                OpendalMountError::InvalidKey(format!("{} is not valid hex", path.display()))
+            })?,
+            _ => content,
+        };
+
+        Self::new(inner, &key)
+    }
+
+    fn seal(
+        &self,
+        file_id: &[u8],
+        index: u64,
+        last: bool,
+        plain: &[u8],
+    ) -> opendal::Result<Vec<u8>> {
+        let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+        let payload = Payload {
+            msg: plain,
+            aad: &aad(file_id, index, last),
+        };
+
+        let sealed = self
+            .cipher
+            .encrypt(&nonce, payload)
+            .map_err(|_| crypt_error("unable to encrypt chunk"))?;
+
+        let mut chunk = nonce.to_vec();
+        chunk.extend(sealed);
+
+        Ok(chunk)
+    }
+
+    /// Opens the chunk at `index`. A chunk before the last one may have
+    /// been sealed as the last one before an append.
+    fn open(
+        &self,
+        file_id: &[u8],
+        index: u64,
+        last: bool,
+        chunk: &[u8],
+    ) -> opendal::Result<Vec<u8>> {
+        if (chunk.len() as u64) < OVERHEAD {
+            return Err(crypt_error("truncated chunk"));
+        }
+
+        let (nonce, sealed) = chunk.split_at(NONCE_LEN as usize);
+
+        let ends: &[bool] = if last { &[true] } else { &[false, true] };
+
+        ends.iter()
+            .find_map(|end| {
+                let payload = Payload {
+                    msg: sealed,
+                    aad: &aad(file_id, index, *end),
+                };
+
+                self.cipher.decrypt(Nonce::from_slice(nonce), payload).ok()
+            })
+            .ok_or_else(|| crypt_error("unable to decrypt chunk"))
+    }
+
+    /// Seals `plain` as consecutive chunks ending the object, the first one
+    /// having index `first`. Empty content is still sealed as a chunk, for
+    /// an object cut down to its header not to be taken as empty.
+    fn seal_chunks(&self, file_id: &[u8], first: u64, plain: &[u8]) -> opendal::Result<Vec<u8>> {
+        if plain.is_empty() {
+            return self.seal(file_id, first, true, plain);
+        }
+
+        let count = plain.len().div_ceil(CHUNK_SIZE as usize);
+        let mut out = Vec::with_capacity(plain.len() + count * OVERHEAD as usize);
+
+        for (i, chunk) in plain.chunks(CHUNK_SIZE as usize).enumerate() {
+            out.extend(self.seal(file_id, first + i as u64, i + 1 == count, chunk)?);
+        }
+
+        Ok(out)
+    }
+
+    /// Opens consecutive sealed chunks, the first one having index `first`
+    /// and the last one of the object `last`.
+    fn open_chunks(
+        &self,
+        file_id: &[u8],
+        first: u64,
+        last: u64,
+        sealed: &[u8],
+    ) -> opendal::Result<Vec<u8>> {
+        let mut out = Vec::with_capacity(sealed.len());
+
+        for (i, chunk) in sealed.chunks(STRIDE as usize).enumerate() {
+            let index = first + i as u64;
+            out.extend(self.open(file_id, index, index == last, chunk)?);
+        }
+
+        Ok(out)
+    }
+
+    /// Reads the id of the object at `path`.
+    async fn file_id(&self, path: &str) -> opendal::Result<Vec<u8>> {
+        let header = self.inner.read(path, 0..HEADER_LEN).await?;
+
+        Ok(file_id(&header)?.to_vec())
+    }
+}
+
+/// Header of a new object, with a random id.
+fn header() -> Vec<u8> {
+    let mut id = [0; FILE_ID_LEN as usize];
+    OsRng.fill_bytes(&mut id);
+
+    let mut header = MAGIC.to_vec();
+    header.extend(id);
+
+    header
+}
+
+/// Id of the object starting with `header`.
+fn file_id(header: &[u8]) -> opendal::Result<&[u8]> {
+    header
+        .strip_prefix(MAGIC)
+        .and_then(|id| id.get(..FILE_ID_LEN as usize))
+        .ok_or_else(|| crypt_error("object is not encrypted"))
+}
+
+/// Data a chunk is bound to: its object, its position and whether it ends
+/// the object.
+fn aad(file_id: &[u8], index: u64, last: bool) -> Vec<u8> {
+    let mut aad = file_id.to_vec();
+    aad.extend(index.to_be_bytes());
+    aad.push(last as u8);
+
+    aad
+}
+
+/// Number of chunks of an object of `stored` bytes.
+fn chunk_count(stored: u64) -> u64 {
+    stored.saturating_sub(HEADER_LEN).div_ceil(STRIDE)
+}
+
+fn crypt_error(message: &'static str) -> opendal::Error {
+    opendal::Error::new(ErrorKind::Unexpected, message)
+}
+
+fn decode_hex(hex: &str) -> Option<Vec<u8>> {
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+/// Plaintext size of an object of `stored` bytes.
+fn plain_len(stored: u64) -> u64 {
+    let body = stored.saturating_sub(HEADER_LEN);
+    let chunks = body.div_ceil(STRIDE);
+
+    body.saturating_sub(chunks * OVERHEAD)
+}
+
+#[async_trait]
+impl Storage for EncryptedStorage {
+    fn info(&self) -> OperatorInfo {
+        self.inner.info()
+    }
+
+    async fn stat(&self, path: &str) -> opendal::Result<Metadata> {
+        let mut meta = self.inner.stat(path).await?;
+
+        if meta.is_file() {
+            meta.set_content_length(plain_len(meta.content_length()));
+        }
+
+        Ok(meta)
+    }
+
+    async fn read(&self, path: &str, range: Range<u64>) -> opendal::Result<Vec<u8>> {
+        let stored = self.inner.stat(path).await?.content_length();
+
+        if stored < HEADER_LEN + OVERHEAD {
+            return Err(crypt_error("truncated object"));
+        }
+
+        let end = range.end.min(plain_len(stored));
+
+        if range.start >= end {
+            return Ok(Vec::new());
+        }
+
+        let first = range.start / CHUNK_SIZE;
+        let last = (end - 1) / CHUNK_SIZE;
+        let chunks_end = stored.min(HEADER_LEN + (last + 1) * STRIDE);
+
+        // The header is read along with the first chunk when it is needed.
+        let (file_id, sealed) = if first == 0 {
+            let mut sealed = self.inner.read(path, 0..chunks_end).await?;
+            let file_id = file_id(&sealed)?.to_vec();
+            sealed.drain(..HEADER_LEN as usize);
+
+            (file_id, sealed)
+        } else {
+            let file_id = self.file_id(path).await?;
+            let sealed = self
+                .inner
+                .read(path, HEADER_LEN + first * STRIDE..chunks_end)
+                .await?;
+
+            (file_id, sealed)
+        };
+
+        let plain = self.open_chunks(&file_id, first, chunk_count(stored) - 1, &sealed)?;
+        let skip = (range.start - first * CHUNK_SIZE) as usize;
+        let take = (end - range.start) as usize;
+
+        Ok(plain[skip..skip + take].to_vec())
+    }
+
+    async fn write_with_metadata(
+        &self,
+        path: &str,
+        data: Vec<u8>,
+        metadata: WriteMetadata,
+    ) -> opendal::Result<()> {
+        let mut sealed = header();
+        let file_id = file_id(&sealed)?.to_vec();
+        sealed.extend(self.seal_chunks(&file_id, 0, &data)?);
+
+        self.inner.write_with_metadata(path, sealed, metadata).await
+    }
+
+    async fn append(&self, path: &str, data: Vec<u8>) -> opendal::Result<()> {
+        let stored = self.inner.stat(path).await?.content_length();
+
+        if stored < HEADER_LEN {
+            return self.write(path, data).await;
+        }
+
+        if data.is_empty() {
+            return Ok(());
+        }
+
+        let plain = plain_len(stored);
+        let tail = plain / CHUNK_SIZE;
+
+        if plain > 0 && plain % CHUNK_SIZE == 0 {
+            // The last chunk is full, new chunks can simply follow it, the
+            // last one ending the object from then on.
+            let file_id = self.file_id(path).await?;
+            let sealed = self.seal_chunks(&file_id, tail, &data)?;
+
+            return self.inner.append(path, sealed).await;
+        }
+
+        // The last chunk is partial: keep the sealed prefix as is and reseal
+        // the tail together with the new data.
+        warn!("rewriting {:?} to append to a partial chunk", path);
+
+        let tail_start = HEADER_LEN + tail * STRIDE;
+        let mut object = self.inner.read(path, 0..stored).await?;
+        let file_id = file_id(&object)?.to_vec();
+
+        let mut tail_plain = self.open(&file_id, tail, true, &object[tail_start as usize..])?;
+        tail_plain.extend(data);
+
+        object.truncate(tail_start as usize);
+        object.extend(self.seal_chunks(&file_id, tail, &tail_plain)?);
+
+        self.inner.write(path, object).await
+    }
+
+    async fn list(&self, path: &str) -> opendal::Result<Vec<Entry>> {
+        self.inner.list(path).await
+    }
+
+    async fn create_dir(&self, path: &str) -> opendal::Result<()> {
+        self.inner.create_dir(path).await
+    }
+
+    async fn delete(&self, path: &str) -> opendal::Result<()> {
+        self.inner.delete(path).await
+    }
+
+    async fn rename(&self, from: &str, to: &str) -> opendal::Result<()> {
+        self.inner.rename(from, to).await
+    }
+
+    async fn list_versions(&self, path: &str) -> opendal::Result<Vec<Entry>> {
+        self.inner.list_versions(path).await
+    }
+
+    fn at_version(&self, version: &str) -> opendal::Result<Arc<dyn Storage>> {
+        Ok(Arc::new(Self {
+            inner: self.inner.at_version(version)?,
+            cipher: self.cipher.clone(),
+        }))
+    }
+}
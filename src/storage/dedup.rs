@@ -0,0 +1,725 @@
+use std::{
+    collections::{HashMap, HashSet},
+    num::NonZeroUsize,
+    ops::Range,
+    sync::{Arc, Weak},
+    time::{Duration, Instant},
+};
+
+use async_trait::async_trait;
+use fastcdc::v2020::FastCDC;
+use log::{debug, info, warn};
+use lru::LruCache;
+use opendal::{ErrorKind, Metadata, OperatorInfo};
+use serde::{Deserialize, Serialize};
+use tokio::sync::{Mutex, RwLock};
+
+use super::{walk, Entry, Storage, WriteMetadata};
+
+/// Directory of the chunk store, at the root of the inner storage.
+const CHUNKS_DIR: &str = ".chunks";
+
+/// Delay between two collections of the unreferenced chunks.
+const COLLECT_INTERVAL: Duration = Duration::from_secs(3600);
+
+const MIN_CHUNK_SIZE: u32 = 16 * 1024;
+const AVG_CHUNK_SIZE: u32 = 64 * 1024;
+const MAX_CHUNK_SIZE: u32 = 256 * 1024;
+
+/// User metadata key holding the logical size of a file.
+const LOGICAL_SIZE_KEY: &str = "opendal-mount-dedup-size";
+
+/// Manifests kept parsed between reads.
+const MANIFEST_CACHE_CAPACITY: usize = 1024;
+
+/// Appended bytes kept in memory before the manifest is rewritten.
+const MAX_PENDING: usize = 32 * MAX_CHUNK_SIZE as usize;
+
+/// Time without appends after which they are written.
+const FLUSH_AFTER: Duration = Duration::from_secs(2);
+
+const FLUSH_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Stores content as content-defined chunks shared between files.
+///
+/// Content is split with FastCDC and every chunk is stored once under
+/// `.chunks/<xx>/<blake3 hash>`. Files hold a JSON manifest listing their
+/// chunks, so ranged reads only fetch the chunks they overlap. Manifests
+/// are cached by the version of the object holding them. Deleting a file
+/// only deletes its manifest, as other files may share its chunks.
+/// Chunks no longer referenced by any manifest are deleted by
+/// [`DedupStorage::collect`], which runs periodically once
+/// [`DedupStorage::spawn_collector`] is called.
+///
+/// Appends are buffered in memory and written with a single manifest
+/// rewrite once the file is left alone for [`FLUSH_AFTER`], once the
+/// buffer reaches [`MAX_PENDING`] bytes, or on [`DedupStorage::flush_all`].
+pub struct DedupStorage {
+    inner: Arc<dyn Storage>,
+    /// Held while chunks are stored and referenced by a manifest, and
+    /// exclusively while a collection starts.
+    collecting: RwLock<()>,
+    /// Chunks stored or referenced since the running collection started,
+    /// which it keeps.
+    touched: Mutex<Option<HashSet<String>>>,
+    /// Manifests by path, along with the version of their object.
+    manifests: std::sync::Mutex<LruCache<String, (String, Arc<Manifest>)>>,
+    /// Bytes appended to files and not written yet, locked per file while
+    /// it is changed.
+    pending: std::sync::Mutex<HashMap<String, Slot>>,
+}
+
+type Slot = Arc<Mutex<Option<Pending>>>;
+
+struct Pending {
+    data: Vec<u8>,
+    appended: Instant,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
+struct Manifest {
+    size: u64,
+    chunks: Vec<ChunkRef>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct ChunkRef {
+    hash: String,
+    len: u64,
+}
+
+/// Version of the object described by `meta`, `None` if it cannot be told
+/// apart from the next one.
+fn version(meta: &Metadata) -> Option<String> {
+    match (meta.etag(), meta.last_modified()) {
+        (Some(etag), _) => Some(etag.to_owned()),
+        (None, Some(modified)) => Some(format!("{}:{}", meta.content_length(), modified)),
+        (None, None) => None,
+    }
+}
+
+fn chunk_path(hash: &str) -> String {
+    format!("{}/{}/{}", CHUNKS_DIR, &hash[..2], hash)
+}
+
+fn manifest_error(e: impl ToString) -> opendal::Error {
+    opendal::Error::new(ErrorKind::Unexpected, e.to_string())
+}
+
+/// Whether `path` is in the chunk store, which is hidden from the files.
+fn is_chunk(path: &str) -> bool {
+    let key = path.trim_matches('/');
+
+    key == CHUNKS_DIR || key.starts_with(&format!("{}/", CHUNKS_DIR))
+}
+
+fn hidden() -> opendal::Error {
+    opendal::Error::new(ErrorKind::NotFound, "no such file")
+}
+
+fn reserved() -> opendal::Error {
+    opendal::Error::new(ErrorKind::PermissionDenied, "reserved for the chunk store")
+}
+
+impl DedupStorage {
+    pub fn new(inner: Arc<dyn Storage>) -> Self {
+        let capacity = NonZeroUsize::new(MANIFEST_CACHE_CAPACITY).unwrap();
+
+        Self {
+            inner,
+            collecting: RwLock::new(()),
+            touched: Mutex::new(None),
+            manifests: std::sync::Mutex::new(LruCache::new(capacity)),
+            pending: std::sync::Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Writes the appends of the files left alone for [`FLUSH_AFTER`]
+    /// periodically while the storage is alive.
+    pub fn spawn_flusher(self: &Arc<Self>) {
+        let dedup: Weak<Self> = Arc::downgrade(self);
+
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(FLUSH_INTERVAL);
+
+            loop {
+                interval.tick().await;
+
+                match dedup.upgrade() {
+                    Some(dedup) => dedup.flush_idle(FLUSH_AFTER).await,
+                    None => break,
+                }
+            }
+        });
+    }
+
+    /// Writes every buffered append.
+    pub async fn flush_all(&self) {
+        self.flush_idle(Duration::ZERO).await;
+    }
+
+    /// Writes the appends of the files not appended to for `idle`, each
+    /// file being only locked while written.
+    async fn flush_idle(&self, idle: Duration) {
+        let slots: Vec<(String, Slot)> = self
+            .pending
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(path, slot)| (path.clone(), slot.clone()))
+            .collect();
+
+        for (path, slot) in slots {
+            let mut pending = slot.lock().await;
+
+            if pending
+                .as_ref()
+                .is_some_and(|p| p.appended.elapsed() >= idle)
+            {
+                let p = pending.take().unwrap();
+
+                match self.write_appended(&path, &p.data).await {
+                    Ok(()) => {}
+                    Err(e) if e.kind() == ErrorKind::NotFound => {
+                        warn!("dropping appends to removed {:?}", path);
+                    }
+                    Err(e) => {
+                        warn!("unable to append to {:?}, retrying later: {}", path, e);
+                        *pending = Some(p);
+                    }
+                }
+            }
+
+            drop(pending);
+            self.release(&path, slot);
+        }
+    }
+
+    /// Lock of the file at `path`, to be given back to
+    /// [`DedupStorage::release`].
+    fn slot(&self, path: &str) -> Slot {
+        self.pending
+            .lock()
+            .unwrap()
+            .entry(path.to_owned())
+            .or_default()
+            .clone()
+    }
+
+    /// Lock of the file at `path`, if it has pending appends or is being
+    /// changed.
+    fn existing_slot(&self, path: &str) -> Option<Slot> {
+        self.pending.lock().unwrap().get(path).cloned()
+    }
+
+    /// Forgets the lock of the file at `path` once nothing is pending and
+    /// nobody else holds it.
+    fn release(&self, path: &str, slot: Slot) {
+        let mut slots = self.pending.lock().unwrap();
+
+        let unused = slots.get(path).is_some_and(|s| Arc::ptr_eq(s, &slot))
+            && Arc::strong_count(&slot) == 2
+            && slot.try_lock().is_ok_and(|pending| pending.is_none());
+
+        if unused {
+            slots.remove(path);
+        }
+    }
+
+    /// Runs [`DedupStorage::collect`] periodically while the storage is
+    /// alive.
+    pub fn spawn_collector(self: &Arc<Self>) {
+        let dedup: Weak<Self> = Arc::downgrade(self);
+
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(COLLECT_INTERVAL);
+
+            loop {
+                interval.tick().await;
+
+                match dedup.upgrade() {
+                    Some(dedup) => {
+                        dedup.collect().await;
+                    }
+                    None => break,
+                }
+            }
+        });
+    }
+
+    /// Deletes the chunks no manifest references, returning the number of
+    /// chunks deleted.
+    ///
+    /// Files keep being written meanwhile, the chunks they store or
+    /// reference from the start of the collection on being kept.
+    pub async fn collect(&self) -> usize {
+        {
+            // Writes in progress go on until their manifest is written.
+            let _collecting = self.collecting.write().await;
+
+            let mut touched = self.touched.lock().await;
+            if touched.is_some() {
+                return 0;
+            }
+            *touched = Some(HashSet::new());
+        }
+
+        let collected = self.sweep().await;
+        *self.touched.lock().await = None;
+
+        if collected > 0 {
+            info!("collected {} unreferenced chunks", collected);
+        }
+
+        collected
+    }
+
+    async fn sweep(&self) -> usize {
+        let entries = match walk(self.inner.as_ref(), "/").await {
+            Ok(entries) => entries,
+            Err(e) => {
+                warn!("unable to list the chunk store: {}", e);
+                return 0;
+            }
+        };
+
+        let (chunks, manifests): (Vec<Entry>, Vec<Entry>) = entries
+            .into_iter()
+            .filter(|e| !e.metadata().is_dir() && !e.path().ends_with('/'))
+            .partition(|e| is_chunk(e.path()));
+
+        let mut referenced = HashSet::new();
+        for entry in manifests {
+            match self.read_manifest(entry.path()).await {
+                Ok(manifest) => referenced.extend(manifest.chunks.iter().map(|c| c.hash.clone())),
+                // Chunks it references cannot be told apart from orphans.
+                Err(e) => {
+                    warn!("unable to read manifest {:?}: {}", entry.path(), e);
+                    return 0;
+                }
+            }
+        }
+
+        let mut collected = 0;
+
+        for chunk in chunks {
+            if referenced.contains(chunk.name()) {
+                continue;
+            }
+
+            // Held for the chunk not to be referenced while deleted.
+            let touched = self.touched.lock().await;
+            if touched.as_ref().is_some_and(|t| t.contains(chunk.name())) {
+                continue;
+            }
+
+            match self.inner.delete(chunk.path()).await {
+                Ok(()) => collected += 1,
+                Err(e) => warn!("unable to delete chunk {:?}: {}", chunk.path(), e),
+            }
+        }
+
+        collected
+    }
+
+    /// Keeps the chunk `hash` from the running collection, if any.
+    async fn touch(&self, hash: &str) {
+        if let Some(touched) = self.touched.lock().await.as_mut() {
+            touched.insert(hash.to_owned());
+        }
+    }
+
+    async fn read_manifest(&self, path: &str) -> opendal::Result<Arc<Manifest>> {
+        let meta = self.inner.stat(path).await?;
+
+        self.manifest(path, &meta).await
+    }
+
+    /// Manifest of the file at `path`, held by an object described by
+    /// `meta`.
+    async fn manifest(&self, path: &str, meta: &Metadata) -> opendal::Result<Arc<Manifest>> {
+        let version = version(meta);
+
+        if let Some(version) = &version {
+            if let Some((cached, manifest)) = self.manifests.lock().unwrap().get(path) {
+                if cached == version {
+                    return Ok(manifest.clone());
+                }
+            }
+        }
+
+        let data = self.inner.read(path, 0..meta.content_length()).await?;
+        let manifest: Arc<Manifest> =
+            Arc::new(serde_json::from_slice(&data).map_err(manifest_error)?);
+
+        if let Some(version) = version {
+            self.manifests
+                .lock()
+                .unwrap()
+                .put(path.to_owned(), (version, manifest.clone()));
+        }
+
+        Ok(manifest)
+    }
+
+    async fn write_manifest(
+        &self,
+        path: &str,
+        manifest: &Manifest,
+        mut metadata: WriteMetadata,
+    ) -> opendal::Result<()> {
+        let data = serde_json::to_vec(manifest).map_err(manifest_error)?;
+
+        self.manifests.lock().unwrap().pop(path);
+
+        metadata
+            .user_metadata
+            .insert(LOGICAL_SIZE_KEY.to_owned(), manifest.size.to_string());
+
+        self.inner.write_with_metadata(path, data, metadata).await
+    }
+
+    /// Splits `data` in chunks, storing the ones missing from the store.
+    async fn store(&self, data: &[u8]) -> opendal::Result<Vec<ChunkRef>> {
+        let mut chunks = Vec::new();
+
+        for chunk in FastCDC::new(data, MIN_CHUNK_SIZE, AVG_CHUNK_SIZE, MAX_CHUNK_SIZE) {
+            let content = &data[chunk.offset..chunk.offset + chunk.length];
+            let hash = blake3::hash(content).to_hex().to_string();
+            let path = chunk_path(&hash);
+
+            self.touch(&hash).await;
+
+            if self.inner.stat(&path).await.is_err() {
+                self.inner.write(&path, content.to_vec()).await?;
+            } else {
+                debug!("chunk {} already stored", hash);
+            }
+
+            chunks.push(ChunkRef {
+                hash,
+                len: chunk.length as u64,
+            });
+        }
+
+        Ok(chunks)
+    }
+
+    /// Adds `data` to the file at `path`, rewriting its manifest once.
+    async fn write_appended(&self, path: &str, data: &[u8]) -> opendal::Result<()> {
+        let _collecting = self.collecting.read().await;
+
+        let meta = self.inner.stat(path).await?;
+        let mut manifest = Manifest::clone(&*self.manifest(path, &meta).await?);
+
+        // Chunk boundaries depend on the content that follows them, so the
+        // last chunk is split again along with the appended data.
+        let mut tail = match manifest.chunks.pop() {
+            Some(last) => {
+                manifest.size -= last.len;
+                self.inner
+                    .read(&chunk_path(&last.hash), 0..last.len)
+                    .await?
+            }
+            None => Vec::new(),
+        };
+        tail.extend(data);
+
+        manifest.size += tail.len() as u64;
+        manifest.chunks.extend(self.store(&tail).await?);
+
+        self.write_manifest(path, &manifest, WriteMetadata::from(&meta))
+            .await
+    }
+
+    /// Size of the file at `path` as written, held by an object described
+    /// by `meta`.
+    async fn written_size(&self, path: &str, meta: &Metadata) -> opendal::Result<u64> {
+        let size = meta
+            .user_metadata()
+            .and_then(|m| m.get(LOGICAL_SIZE_KEY))
+            .and_then(|s| s.parse().ok());
+
+        match size {
+            Some(size) => Ok(size),
+            None => Ok(self.manifest(path, meta).await?.size),
+        }
+    }
+
+    /// Reads `range` from the chunks of the file at `path`, without the
+    /// pending appends.
+    async fn read_written(&self, path: &str, range: Range<u64>) -> opendal::Result<Vec<u8>> {
+        let manifest = self.read_manifest(path).await?;
+        let end = range.end.min(manifest.size);
+
+        let mut data = Vec::new();
+        let mut offset = 0;
+
+        for chunk in &manifest.chunks {
+            let chunk_end = offset + chunk.len;
+
+            if chunk_end > range.start && offset < end {
+                let start = range.start.max(offset) - offset;
+                let stop = end.min(chunk_end) - offset;
+
+                data.extend(
+                    self.inner
+                        .read(&chunk_path(&chunk.hash), start..stop)
+                        .await?,
+                );
+            }
+
+            if chunk_end >= end {
+                break;
+            }
+            offset = chunk_end;
+        }
+
+        Ok(data)
+    }
+}
+
+#[async_trait]
+impl Storage for DedupStorage {
+    fn info(&self) -> OperatorInfo {
+        self.inner.info()
+    }
+
+    async fn stat(&self, path: &str) -> opendal::Result<Metadata> {
+        if is_chunk(path) {
+            return Err(hidden());
+        }
+
+        let mut meta = self.inner.stat(path).await?;
+
+        if meta.is_file() {
+            let pending = match self.existing_slot(path) {
+                Some(slot) => slot
+                    .lock()
+                    .await
+                    .as_ref()
+                    .map_or(0, |p| p.data.len() as u64),
+                None => 0,
+            };
+
+            let size = self.written_size(path, &meta).await?;
+            meta.set_content_length(size + pending);
+        }
+
+        Ok(meta)
+    }
+
+    async fn read(&self, path: &str, range: Range<u64>) -> opendal::Result<Vec<u8>> {
+        if is_chunk(path) {
+            return Err(hidden());
+        }
+
+        let Some(slot) = self.existing_slot(path) else {
+            return self.read_written(path, range).await;
+        };
+
+        // Held for the pending appends not to be written while read.
+        let pending = slot.lock().await;
+
+        let Some(p) = pending.as_ref() else {
+            drop(pending);
+            return self.read_written(path, range).await;
+        };
+
+        let meta = self.inner.stat(path).await?;
+        let written = self.written_size(path, &meta).await?;
+
+        let mut data = if range.start < written {
+            self.read_written(path, range.start..range.end.min(written))
+                .await?
+        } else {
+            Vec::new()
+        };
+
+        let start = range.start.saturating_sub(written).min(p.data.len() as u64) as usize;
+        let end = range.end.saturating_sub(written).min(p.data.len() as u64) as usize;
+        if start < end {
+            data.extend_from_slice(&p.data[start..end]);
+        }
+
+        Ok(data)
+    }
+
+    async fn write_with_metadata(
+        &self,
+        path: &str,
+        data: Vec<u8>,
+        metadata: WriteMetadata,
+    ) -> opendal::Result<()> {
+        if is_chunk(path) {
+            return Err(reserved());
+        }
+
+        let slot = self.slot(path);
+        let mut pending = slot.lock().await;
+
+        // The file is replaced, along with what was appended to it.
+        *pending = None;
+
+        let written = async {
+            let _collecting = self.collecting.read().await;
+
+            let manifest = Manifest {
+                size: data.len() as u64,
+                chunks: self.store(&data).await?,
+            };
+
+            self.write_manifest(path, &manifest, metadata).await
+        }
+        .await;
+
+        drop(pending);
+        self.release(path, slot);
+
+        written
+    }
+
+    async fn append(&self, path: &str, data: Vec<u8>) -> opendal::Result<()> {
+        if is_chunk(path) {
+            return Err(reserved());
+        }
+
+        let slot = self.slot(path);
+        let mut pending = slot.lock().await;
+
+        let appended = async {
+            let buffered = match pending.as_ref() {
+                Some(p) => p.data.len(),
+                None => {
+                    self.inner.stat(path).await?;
+                    0
+                }
+            };
+
+            if buffered + data.len() < MAX_PENDING {
+                let p = pending.get_or_insert_with(|| Pending {
+                    data: Vec::new(),
+                    appended: Instant::now(),
+                });
+                p.data.extend(data);
+                p.appended = Instant::now();
+
+                return Ok(());
+            }
+
+            // What was buffered before stays pending if the write fails.
+            let mut appended = pending.as_ref().map_or_else(Vec::new, |p| p.data.clone());
+            appended.extend(data);
+            self.write_appended(path, &appended).await?;
+
+            *pending = None;
+
+            Ok(())
+        }
+        .await;
+
+        drop(pending);
+        self.release(path, slot);
+
+        appended
+    }
+
+    async fn list(&self, path: &str) -> opendal::Result<Vec<Entry>> {
+        if is_chunk(path) {
+            return Err(hidden());
+        }
+
+        let mut entries = self.inner.list(path).await?;
+
+        if path.trim_matches('/').is_empty() {
+            entries.retain(|e| e.name().trim_end_matches('/') != CHUNKS_DIR);
+        }
+
+        Ok(entries)
+    }
+
+    async fn create_dir(&self, path: &str) -> opendal::Result<()> {
+        if is_chunk(path) {
+            return Err(reserved());
+        }
+
+        self.inner.create_dir(path).await
+    }
+
+    async fn delete(&self, path: &str) -> opendal::Result<()> {
+        if is_chunk(path) {
+            return Err(hidden());
+        }
+
+        let slot = self.slot(path);
+        let mut pending = slot.lock().await;
+
+        *pending = None;
+        self.manifests.lock().unwrap().pop(path);
+        let deleted = self.inner.delete(path).await;
+
+        drop(pending);
+        self.release(path, slot);
+
+        deleted
+    }
+
+    async fn rename(&self, from: &str, to: &str) -> opendal::Result<()> {
+        if is_chunk(from) {
+            return Err(hidden());
+        }
+        if is_chunk(to) {
+            return Err(reserved());
+        }
+
+        if from == to {
+            return self.inner.rename(from, to).await;
+        }
+
+        // Locked in a fixed order for two renames not to wait on each other.
+        let from_slot = self.slot(from);
+        let to_slot = self.slot(to);
+        let (mut from_pending, mut to_pending) = if from < to {
+            let from_pending = from_slot.lock().await;
+            (from_pending, to_slot.lock().await)
+        } else {
+            let to_pending = to_slot.lock().await;
+            (from_slot.lock().await, to_pending)
+        };
+
+        let renamed = async {
+            if let Some(p) = from_pending.as_ref() {
+                self.write_appended(from, &p.data).await?;
+                *from_pending = None;
+            }
+
+            let _collecting = self.collecting.read().await;
+
+            // The running collection may have listed the directories of
+            // both paths already.
+            if self.touched.lock().await.is_some() {
+                let meta = self.inner.stat(from).await?;
+                if meta.is_file() {
+                    for chunk in &self.manifest(from, &meta).await?.chunks {
+                        self.touch(&chunk.hash).await;
+                    }
+                }
+            }
+
+            self.manifests.lock().unwrap().pop(from);
+            self.manifests.lock().unwrap().pop(to);
+            self.inner.rename(from, to).await?;
+            *to_pending = None;
+
+            Ok(())
+        }
+        .await;
+
+        drop(from_pending);
+        drop(to_pending);
+        self.release(from, from_slot);
+        self.release(to, to_slot);
+
+        renamed
+    }
+}
@@ -0,0 +1,431 @@
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    ops::Range,
+    sync::{Arc, Mutex},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use async_trait::async_trait;
+use log::{info, warn};
+use opendal::{ErrorKind, Metadata, OperatorInfo};
+use tokio::sync::{mpsc, oneshot, RwLock};
+
+use super::{Entry, Storage, WriteMetadata};
+use crate::schema::{Divergence, MirrorPolicy};
+
+/// Changes waiting to be applied to the secondary storage of an
+/// asynchronous mirror, writes waiting for room past it.
+const MAX_QUEUED: usize = 1024;
+
+/// Divergences kept, the oldest being dropped past it.
+const MAX_DIVERGENCES: usize = 10_000;
+
+/// A change to replay on the secondary storage.
+enum Change {
+    Write(String, Vec<u8>, WriteMetadata),
+    Append(String, Vec<u8>),
+    CreateDir(String),
+    Delete(String),
+}
+
+impl Change {
+    fn path(&self) -> &str {
+        match self {
+            Change::Write(path, ..) => path,
+            Change::Append(path, _) => path,
+            Change::CreateDir(path) => path,
+            Change::Delete(path) => path,
+        }
+    }
+
+    fn operation(&self) -> &'static str {
+        match self {
+            Change::Write(..) => "write",
+            Change::Append(..) => "append",
+            Change::CreateDir(_) => "create_dir",
+            Change::Delete(_) => "delete",
+        }
+    }
+
+    async fn apply(self, storage: &dyn Storage) -> opendal::Result<()> {
+        match self {
+            Change::Write(path, data, metadata) => {
+                storage.write_with_metadata(&path, data, metadata).await
+            }
+            Change::Append(path, data) => storage.append(&path, data).await,
+            Change::CreateDir(path) => storage.create_dir(&path).await,
+            Change::Delete(path) => storage.delete(&path).await,
+        }
+    }
+}
+
+/// An item of the queue of an asynchronous mirror.
+enum Queued {
+    Change(Change),
+    /// Signaled once the changes queued before are applied.
+    Flush(oneshot::Sender<()>),
+}
+
+/// Changes that could not be applied to the secondary storage, the last
+/// one of each path only.
+#[derive(Clone, Default)]
+struct DivergenceLog(Arc<RwLock<VecDeque<Divergence>>>);
+
+impl DivergenceLog {
+    async fn record(&self, path: &str, operation: &str, error: &opendal::Error) {
+        warn!("mirror diverged on {} {:?}: {}", operation, path, error);
+
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or_default();
+
+        let mut log = self.0.write().await;
+
+        log.retain(|d| d.path != path);
+        if log.len() >= MAX_DIVERGENCES {
+            if let Some(dropped) = log.pop_front() {
+                warn!("divergence log full, forgetting {:?}", dropped.path);
+            }
+        }
+
+        log.push_back(Divergence {
+            path: path.to_owned(),
+            operation: operation.to_owned(),
+            error: error.to_string(),
+            timestamp,
+        });
+    }
+
+    /// Whether the last change of `path` that failed is a deletion, the
+    /// secondary storage then holding an object that no longer exists.
+    async fn is_deleted(&self, path: &str) -> bool {
+        self.0
+            .read()
+            .await
+            .iter()
+            .any(|d| d.path == path && d.operation == "delete")
+    }
+
+    /// Applies `change` to `storage`, recording a failure.
+    async fn apply(&self, storage: &dyn Storage, change: Change) {
+        let path = change.path().to_owned();
+        let operation = change.operation();
+
+        if let Err(e) = change.apply(storage).await {
+            self.record(&path, operation, &e).await;
+        }
+    }
+}
+
+/// Deletions queued for the secondary storage and not applied yet, with the
+/// number of times each path is queued.
+#[derive(Clone, Default)]
+struct QueuedDeletes(Arc<Mutex<HashMap<String, usize>>>);
+
+impl QueuedDeletes {
+    fn push(&self, path: &str) {
+        *self.0.lock().unwrap().entry(path.to_owned()).or_default() += 1;
+    }
+
+    fn pop(&self, path: &str) {
+        let mut deletes = self.0.lock().unwrap();
+
+        if let Some(count) = deletes.get_mut(path) {
+            *count -= 1;
+            if *count == 0 {
+                deletes.remove(path);
+            }
+        }
+    }
+
+    /// Whether `path` or a directory holding it is waiting to be deleted.
+    fn contains(&self, path: &str) -> bool {
+        self.0
+            .lock()
+            .unwrap()
+            .keys()
+            .any(|deleted| deleted == path || (deleted.ends_with('/') && path.starts_with(deleted)))
+    }
+}
+
+/// Writes to a primary and a secondary storage.
+///
+/// Reads are served by the primary storage, falling back to the secondary
+/// one when they fail, objects missing from the primary storage included.
+/// Listings merge the entries of both storages.
+/// Changes are applied to the primary storage first, then to the
+/// secondary one either before returning or in the background, depending
+/// on the policy. Changes that fail on the secondary storage are logged
+/// until [`MirrorStorage::repair`] copies the affected paths again.
+pub struct MirrorStorage {
+    primary: Arc<dyn Storage>,
+    secondary: Arc<dyn Storage>,
+    log: DivergenceLog,
+    /// Queue of changes replayed in order on the secondary storage, when
+    /// mirroring asynchronously.
+    queue: Option<mpsc::Sender<Queued>>,
+    /// Deletions in the queue, for the objects they remove not to be looked
+    /// for on the secondary storage meanwhile.
+    deletes: QueuedDeletes,
+}
+
+impl MirrorStorage {
+    pub fn new(
+        primary: Arc<dyn Storage>,
+        secondary: Arc<dyn Storage>,
+        policy: MirrorPolicy,
+    ) -> Self {
+        let log = DivergenceLog::default();
+        let deletes = QueuedDeletes::default();
+
+        let queue = match policy {
+            MirrorPolicy::Sync => None,
+            MirrorPolicy::Async => {
+                let (tx, mut rx) = mpsc::channel::<Queued>(MAX_QUEUED);
+                let secondary = secondary.clone();
+                let log = log.clone();
+                let deletes = deletes.clone();
+
+                tokio::spawn(async move {
+                    while let Some(queued) = rx.recv().await {
+                        match queued {
+                            Queued::Change(Change::Delete(path)) => {
+                                let change = Change::Delete(path.clone());
+                                log.apply(secondary.as_ref(), change).await;
+                                deletes.pop(&path);
+                            }
+                            Queued::Change(change) => log.apply(secondary.as_ref(), change).await,
+                            Queued::Flush(done) => {
+                                let _ = done.send(());
+                            }
+                        }
+                    }
+                });
+
+                Some(tx)
+            }
+        };
+
+        Self {
+            primary,
+            secondary,
+            log,
+            queue,
+            deletes,
+        }
+    }
+
+    /// Waits for the changes queued for the secondary storage to be applied.
+    pub async fn flush(&self) {
+        if let Some(queue) = &self.queue {
+            let (done, flushed) = oneshot::channel();
+
+            if queue.send(Queued::Flush(done)).await.is_ok() {
+                let _ = flushed.await;
+            }
+        }
+    }
+
+    pub async fn divergences(&self) -> Vec<Divergence> {
+        self.log.0.read().await.iter().cloned().collect()
+    }
+
+    /// Copies every diverged path from the primary to the secondary storage,
+    /// returning the number of paths repaired.
+    pub async fn repair(&self) -> usize {
+        // Queued changes would otherwise land after the repair, or fail and
+        // be logged again.
+        self.flush().await;
+
+        let mut paths: Vec<String> = self
+            .log
+            .0
+            .read()
+            .await
+            .iter()
+            .map(|d| d.path.clone())
+            .collect();
+        paths.sort();
+        paths.dedup();
+
+        let mut repaired = 0;
+
+        for path in paths {
+            match self.repair_path(&path).await {
+                Ok(()) => {
+                    info!("repaired {:?} on secondary storage", path);
+
+                    self.log.0.write().await.retain(|d| d.path != path);
+                    repaired += 1;
+                }
+                Err(e) => warn!("unable to repair {:?}: {}", path, e),
+            }
+        }
+
+        repaired
+    }
+
+    async fn repair_path(&self, path: &str) -> opendal::Result<()> {
+        match self.primary.stat(path).await {
+            Ok(meta) if meta.is_dir() => self.secondary.create_dir(path).await,
+            Ok(meta) => {
+                let data = self.primary.read(path, 0..meta.content_length()).await?;
+
+                self.secondary
+                    .write_with_metadata(path, data, WriteMetadata::from(&meta))
+                    .await
+            }
+            Err(e) if e.kind() == ErrorKind::NotFound => match self.secondary.delete(path).await {
+                Err(e) if e.kind() != ErrorKind::NotFound => Err(e),
+                _ => Ok(()),
+            },
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Whether to try the secondary storage after `e` on the primary one.
+    /// An object missing from the primary storage is looked for on the
+    /// secondary one, unless its deletion failed or is queued there.
+    async fn falls_back(&self, path: &str, e: &opendal::Error) -> bool {
+        e.kind() != ErrorKind::NotFound || !self.is_deleted(path).await
+    }
+
+    async fn is_deleted(&self, path: &str) -> bool {
+        self.deletes.contains(path) || self.log.is_deleted(path).await
+    }
+
+    async fn mirror(&self, change: Change) {
+        match &self.queue {
+            Some(queue) => {
+                let path = change.path().to_owned();
+                let operation = change.operation();
+                let delete = matches!(change, Change::Delete(_));
+
+                if delete {
+                    self.deletes.push(&path);
+                }
+
+                if queue.send(Queued::Change(change)).await.is_err() {
+                    if delete {
+                        self.deletes.pop(&path);
+                    }
+
+                    let e = opendal::Error::new(ErrorKind::Unexpected, "mirror queue closed");
+                    self.log.record(&path, operation, &e).await;
+                }
+            }
+            None => self.log.apply(self.secondary.as_ref(), change).await,
+        }
+    }
+}
+
+#[async_trait]
+impl Storage for MirrorStorage {
+    fn info(&self) -> OperatorInfo {
+        self.primary.info()
+    }
+
+    async fn stat(&self, path: &str) -> opendal::Result<Metadata> {
+        match self.primary.stat(path).await {
+            Err(e) if self.falls_back(path, &e).await => {
+                warn!(
+                    "falling back to secondary storage to stat {:?}: {}",
+                    path, e
+                );
+                self.secondary.stat(path).await
+            }
+            meta => meta,
+        }
+    }
+
+    async fn read(&self, path: &str, range: Range<u64>) -> opendal::Result<Vec<u8>> {
+        match self.primary.read(path, range.clone()).await {
+            Err(e) if self.falls_back(path, &e).await => {
+                warn!(
+                    "falling back to secondary storage to read {:?}: {}",
+                    path, e
+                );
+                self.secondary.read(path, range).await
+            }
+            data => data,
+        }
+    }
+
+    async fn write_with_metadata(
+        &self,
+        path: &str,
+        data: Vec<u8>,
+        metadata: WriteMetadata,
+    ) -> opendal::Result<()> {
+        self.primary
+            .write_with_metadata(path, data.clone(), metadata.clone())
+            .await?;
+
+        self.mirror(Change::Write(path.to_owned(), data, metadata))
+            .await;
+
+        Ok(())
+    }
+
+    async fn append(&self, path: &str, data: Vec<u8>) -> opendal::Result<()> {
+        self.primary.append(path, data.clone()).await?;
+
+        self.mirror(Change::Append(path.to_owned(), data)).await;
+
+        Ok(())
+    }
+
+    async fn list(&self, path: &str) -> opendal::Result<Vec<Entry>> {
+        let (mut entries, listed) = match self.primary.list(path).await {
+            Err(e) if self.falls_back(path, &e).await => {
+                warn!(
+                    "falling back to secondary storage to list {:?}: {}",
+                    path, e
+                );
+                (Vec::new(), false)
+            }
+            entries => (entries?, true),
+        };
+
+        let secondary = match self.secondary.list(path).await {
+            Ok(secondary) => secondary,
+            Err(e) if !listed => return Err(e),
+            Err(e) => {
+                warn!("unable to list {:?} on secondary storage: {}", path, e);
+                Vec::new()
+            }
+        };
+
+        let names: HashSet<String> = entries
+            .iter()
+            .map(|e| e.path().trim_end_matches('/').to_owned())
+            .collect();
+
+        for entry in secondary {
+            if !names.contains(entry.path().trim_end_matches('/'))
+                && !self.is_deleted(entry.path()).await
+            {
+                entries.push(entry);
+            }
+        }
+
+        Ok(entries)
+    }
+
+    async fn create_dir(&self, path: &str) -> opendal::Result<()> {
+        self.primary.create_dir(path).await?;
+
+        self.mirror(Change::CreateDir(path.to_owned())).await;
+
+        Ok(())
+    }
+
+    async fn delete(&self, path: &str) -> opendal::Result<()> {
+        self.primary.delete(path).await?;
+
+        self.mirror(Change::Delete(path.to_owned())).await;
+
+        Ok(())
+    }
+}
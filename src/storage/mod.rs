@@ -0,0 +1,299 @@
+mod archive;
+mod compress;
+mod crypt;
+mod dedup;
+mod mirror;
+mod overlay;
+mod scratch;
+mod subpath;
+mod tier;
+mod trash;
+mod version;
+
+pub use archive::ArchiveStorage;
+pub use compress::CompressedStorage;
+pub use crypt::EncryptedStorage;
+pub use dedup::DedupStorage;
+pub use mirror::MirrorStorage;
+pub use overlay::OverlayStorage;
+pub use scratch::ScratchStorage;
+pub use subpath::SubPathStorage;
+pub use tier::{TierPolicy, TieredStorage};
+pub use trash::TrashStorage;
+pub use version::VersionedOperator;
+
+use std::{collections::HashMap, ops::Range, sync::Arc};
+
+use async_trait::async_trait;
+use opendal::{ErrorKind, Metadata, Operator, OperatorInfo};
+
+/// Size of the objects read in memory to be rewritten, past which copying
+/// them without help from the backend fails.
+pub const MAX_BUFFERED_LEN: u64 = 64 * 1024 * 1024;
+
+/// An entry returned by [`Storage::list`].
+#[derive(Debug, Clone)]
+pub struct Entry {
+    path: String,
+    metadata: Metadata,
+}
+
+impl Entry {
+    pub fn new(path: &str, metadata: Metadata) -> Self {
+        Self {
+            path: path.to_owned(),
+            metadata,
+        }
+    }
+
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    /// Last component of the path, keeping the trailing `/` of directories.
+    pub fn name(&self) -> &str {
+        let trimmed = self.path.trim_end_matches('/');
+        let start = trimmed.rfind('/').map(|i| i + 1).unwrap_or(0);
+
+        &self.path[start..]
+    }
+
+    pub fn metadata(&self) -> &Metadata {
+        &self.metadata
+    }
+}
+
+impl From<opendal::Entry> for Entry {
+    fn from(entry: opendal::Entry) -> Self {
+        Self::new(entry.path(), entry.metadata().clone())
+    }
+}
+
+/// Metadata stored along with the content of an object.
+#[derive(Debug, Clone, Default)]
+pub struct WriteMetadata {
+    pub content_type: Option<String>,
+    pub content_disposition: Option<String>,
+    pub cache_control: Option<String>,
+    pub user_metadata: HashMap<String, String>,
+}
+
+impl From<&Metadata> for WriteMetadata {
+    fn from(meta: &Metadata) -> Self {
+        Self {
+            content_type: meta.content_type().map(str::to_owned),
+            content_disposition: meta.content_disposition().map(str::to_owned),
+            cache_control: meta.cache_control().map(str::to_owned),
+            user_metadata: meta.user_metadata().cloned().unwrap_or_default(),
+        }
+    }
+}
+
+/// The subset of [`Operator`] used by [`crate::OpendalFs`].
+///
+/// Implementations wrap another storage to transform content on its way
+/// to and from the backend, the innermost one being an [`Operator`].
+#[async_trait]
+pub trait Storage: Send + Sync {
+    fn info(&self) -> OperatorInfo;
+
+    async fn stat(&self, path: &str) -> opendal::Result<Metadata>;
+
+    async fn read(&self, path: &str, range: Range<u64>) -> opendal::Result<Vec<u8>>;
+
+    async fn write(&self, path: &str, data: Vec<u8>) -> opendal::Result<()> {
+        self.write_with_metadata(path, data, WriteMetadata::default())
+            .await
+    }
+
+    /// Writes `data` along with its metadata, each field being dropped when
+    /// the backend is unable to store it.
+    async fn write_with_metadata(
+        &self,
+        path: &str,
+        data: Vec<u8>,
+        metadata: WriteMetadata,
+    ) -> opendal::Result<()>;
+
+    async fn append(&self, path: &str, data: Vec<u8>) -> opendal::Result<()>;
+
+    /// Lists the children of the directory at `path`, without the
+    /// directory itself.
+    async fn list(&self, path: &str) -> opendal::Result<Vec<Entry>>;
+
+    async fn create_dir(&self, path: &str) -> opendal::Result<()>;
+
+    async fn delete(&self, path: &str) -> opendal::Result<()>;
+
+    /// Moves the file at `from` to `to`, by default copying then deleting
+    /// it.
+    async fn rename(&self, from: &str, to: &str) -> opendal::Result<()> {
+        copy_and_delete(self, from, to).await
+    }
+
+    /// Lists every version of the object at `path`, each entry carrying its
+    /// version id in its metadata.
+    async fn list_versions(&self, _path: &str) -> opendal::Result<Vec<Entry>> {
+        Err(opendal::Error::new(
+            ErrorKind::Unsupported,
+            "versions are not supported",
+        ))
+    }
+
+    /// A read only view of the storage where objects are read at `version`.
+    fn at_version(&self, _version: &str) -> opendal::Result<Arc<dyn Storage>> {
+        Err(opendal::Error::new(
+            ErrorKind::Unsupported,
+            "versions are not supported",
+        ))
+    }
+}
+
+/// Moves the file at `from` to `to` by copying it along with its metadata,
+/// through memory and up to [`MAX_BUFFERED_LEN`] bytes.
+pub async fn copy_and_delete<S: Storage + ?Sized>(
+    storage: &S,
+    from: &str,
+    to: &str,
+) -> opendal::Result<()> {
+    let meta = storage.stat(from).await?;
+    if meta.is_dir() {
+        return Err(opendal::Error::new(
+            ErrorKind::Unsupported,
+            "renaming directories is not supported",
+        ));
+    }
+    if meta.content_length() > MAX_BUFFERED_LEN {
+        return Err(opendal::Error::new(
+            ErrorKind::Unsupported,
+            "object is too large to be copied",
+        ));
+    }
+
+    let data = storage.read(from, 0..meta.content_length()).await?;
+    storage
+        .write_with_metadata(to, data, WriteMetadata::from(&meta))
+        .await?;
+
+    storage.delete(from).await
+}
+
+/// Lists every entry under the directory at `dir`, recursively.
+pub async fn walk(storage: &dyn Storage, dir: &str) -> opendal::Result<Vec<Entry>> {
+    let mut entries = Vec::new();
+    let mut dirs = vec![dir.to_owned()];
+
+    while let Some(dir) = dirs.pop() {
+        for entry in storage.list(&dir).await? {
+            if entry.metadata().is_dir() || entry.path().ends_with('/') {
+                dirs.push(entry.path().to_owned());
+            }
+
+            entries.push(entry);
+        }
+    }
+
+    Ok(entries)
+}
+
+#[async_trait]
+impl Storage for Operator {
+    fn info(&self) -> OperatorInfo {
+        Operator::info(self)
+    }
+
+    async fn stat(&self, path: &str) -> opendal::Result<Metadata> {
+        Operator::stat(self, path).await
+    }
+
+    async fn read(&self, path: &str, range: Range<u64>) -> opendal::Result<Vec<u8>> {
+        let data = self.read_with(path).range(range).await?;
+
+        Ok(data.to_vec())
+    }
+
+    async fn write_with_metadata(
+        &self,
+        path: &str,
+        data: Vec<u8>,
+        metadata: WriteMetadata,
+    ) -> opendal::Result<()> {
+        let capability = self.info().full_capability();
+        let mut write = self.write_with(path, data);
+
+        if let Some(content_type) = metadata
+            .content_type
+            .filter(|_| capability.write_with_content_type)
+        {
+            write = write.content_type(&content_type);
+        }
+        if let Some(disposition) = metadata
+            .content_disposition
+            .filter(|_| capability.write_with_content_disposition)
+        {
+            write = write.content_disposition(&disposition);
+        }
+        if let Some(cache_control) = metadata
+            .cache_control
+            .filter(|_| capability.write_with_cache_control)
+        {
+            write = write.cache_control(&cache_control);
+        }
+        if !metadata.user_metadata.is_empty() && capability.write_with_user_metadata {
+            write = write.user_metadata(metadata.user_metadata);
+        }
+
+        write.await
+    }
+
+    async fn append(&self, path: &str, data: Vec<u8>) -> opendal::Result<()> {
+        self.write_with(path, data).append(true).await
+    }
+
+    async fn list(&self, path: &str) -> opendal::Result<Vec<Entry>> {
+        let dir = path.trim_matches('/');
+        let entries = Operator::list(self, path).await?;
+
+        Ok(entries
+            .into_iter()
+            .filter(|e| e.path().trim_matches('/') != dir)
+            .map(Entry::from)
+            .collect())
+    }
+
+    async fn create_dir(&self, path: &str) -> opendal::Result<()> {
+        Operator::create_dir(self, path).await
+    }
+
+    async fn delete(&self, path: &str) -> opendal::Result<()> {
+        Operator::delete(self, path).await
+    }
+
+    async fn rename(&self, from: &str, to: &str) -> opendal::Result<()> {
+        let capability = self.info().full_capability();
+
+        if capability.rename {
+            Operator::rename(self, from, to).await
+        } else if capability.copy && Operator::stat(self, from).await?.is_file() {
+            Operator::copy(self, from, to).await?;
+            Operator::delete(self, from).await
+        } else {
+            copy_and_delete(self, from, to).await
+        }
+    }
+
+    async fn list_versions(&self, path: &str) -> opendal::Result<Vec<Entry>> {
+        let key = path.trim_start_matches('/');
+        let entries = self.list_with(path).version(true).await?;
+
+        Ok(entries
+            .into_iter()
+            .filter(|e| e.path() == key && e.metadata().version().is_some())
+            .map(Entry::from)
+            .collect())
+    }
+
+    fn at_version(&self, version: &str) -> opendal::Result<Arc<dyn Storage>> {
+        Ok(Arc::new(VersionedOperator::new(self.clone(), version)))
+    }
+}
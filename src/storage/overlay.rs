@@ -0,0 +1,241 @@
+use std::{collections::HashSet, ops::Range, sync::Arc};
+
+use async_trait::async_trait;
+use log::debug;
+use opendal::{ErrorKind, Metadata, OperatorInfo};
+
+use super::{Entry, Storage, WriteMetadata};
+
+/// Prefix of the markers hiding deleted lower objects, as in aufs.
+const WHITEOUT_PREFIX: &str = ".wh.";
+
+/// Marker of the upper directories hiding the lower directory they cover.
+const OPAQUE_NAME: &str = ".wh..opq";
+
+/// Union of a read only lower storage and a writable upper one.
+///
+/// Objects are looked up in the upper storage first. Changes to objects
+/// that only exist in the lower storage copy them up first, and deleting
+/// them leaves a `.wh.<name>` whiteout marker in the upper storage.
+///
+/// Removing a directory drops the whiteouts of its children along with it,
+/// and a directory made again where one was removed holds a `.wh..opq`
+/// marker for the lower children to stay deleted.
+pub struct OverlayStorage {
+    lower: Arc<dyn Storage>,
+    upper: Arc<dyn Storage>,
+}
+
+impl OverlayStorage {
+    pub fn new(lower: Arc<dyn Storage>, upper: Arc<dyn Storage>) -> Self {
+        Self { lower, upper }
+    }
+
+    /// Whether `path` or one of its ancestors has been deleted, or one of
+    /// its ancestors made opaque, hiding it in the lower storage.
+    async fn is_whiteout(&self, path: &str) -> bool {
+        let trimmed = path.trim_matches('/');
+
+        for (i, _) in trimmed.match_indices('/') {
+            if self.is_opaque(&trimmed[..i]).await {
+                return true;
+            }
+        }
+
+        let ancestors = trimmed
+            .match_indices('/')
+            .map(|(i, _)| &trimmed[..i])
+            .chain(std::iter::once(trimmed));
+
+        for ancestor in ancestors {
+            if let Some(whiteout) = whiteout_path(ancestor) {
+                if self.upper.stat(&whiteout).await.is_ok() {
+                    return true;
+                }
+            }
+        }
+
+        false
+    }
+
+    /// Whether the upper directory at `dir` hides the lower one.
+    async fn is_opaque(&self, dir: &str) -> bool {
+        match opaque_path(dir) {
+            Some(opaque) => self.upper.stat(&opaque).await.is_ok(),
+            None => false,
+        }
+    }
+
+    async fn clear_whiteout(&self, path: &str) -> opendal::Result<()> {
+        if let Some(whiteout) = whiteout_path(path) {
+            if self.upper.stat(&whiteout).await.is_ok() {
+                self.upper.delete(&whiteout).await?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Storage holding the visible version of `path`.
+    async fn layer(&self, path: &str) -> opendal::Result<&Arc<dyn Storage>> {
+        if self.upper.stat(path).await.is_ok() {
+            return Ok(&self.upper);
+        }
+
+        if self.is_whiteout(path).await {
+            return Err(not_found());
+        }
+
+        Ok(&self.lower)
+    }
+}
+
+fn not_found() -> opendal::Error {
+    opendal::Error::new(ErrorKind::NotFound, "object has been deleted")
+}
+
+/// Path of the opaque marker of the directory at `dir`, `None` for the
+/// root.
+fn opaque_path(dir: &str) -> Option<String> {
+    let trimmed = dir.trim_matches('/');
+
+    (!trimmed.is_empty()).then(|| format!("{}/{}", trimmed, OPAQUE_NAME))
+}
+
+/// Path of the whiteout marker of `path`, `None` for the root.
+fn whiteout_path(path: &str) -> Option<String> {
+    let trimmed = path.trim_matches('/');
+    if trimmed.is_empty() {
+        return None;
+    }
+
+    Some(match trimmed.rsplit_once('/') {
+        Some((dir, name)) => format!("{}/{}{}", dir, WHITEOUT_PREFIX, name),
+        None => format!("{}{}", WHITEOUT_PREFIX, trimmed),
+    })
+}
+
+#[async_trait]
+impl Storage for OverlayStorage {
+    fn info(&self) -> OperatorInfo {
+        self.upper.info()
+    }
+
+    async fn stat(&self, path: &str) -> opendal::Result<Metadata> {
+        self.layer(path).await?.stat(path).await
+    }
+
+    async fn read(&self, path: &str, range: Range<u64>) -> opendal::Result<Vec<u8>> {
+        self.layer(path).await?.read(path, range).await
+    }
+
+    async fn write_with_metadata(
+        &self,
+        path: &str,
+        data: Vec<u8>,
+        metadata: WriteMetadata,
+    ) -> opendal::Result<()> {
+        self.upper.write_with_metadata(path, data, metadata).await?;
+
+        self.clear_whiteout(path).await
+    }
+
+    async fn append(&self, path: &str, data: Vec<u8>) -> opendal::Result<()> {
+        if self.upper.stat(path).await.is_ok() {
+            return self.upper.append(path, data).await;
+        }
+
+        if self.is_whiteout(path).await {
+            return self.write(path, data).await;
+        }
+
+        debug!("copying up {:?}", path);
+
+        let meta = self.lower.stat(path).await?;
+        let mut content = self.lower.read(path, 0..meta.content_length()).await?;
+        content.extend(data);
+
+        self.write_with_metadata(path, content, WriteMetadata::from(&meta))
+            .await
+    }
+
+    async fn list(&self, path: &str) -> opendal::Result<Vec<Entry>> {
+        let upper = self.upper.list(path).await;
+        let lower = if self.is_whiteout(path).await || self.is_opaque(path).await {
+            Ok(Vec::new())
+        } else {
+            self.lower.list(path).await
+        };
+
+        let (upper, lower) = match (upper, lower) {
+            (Err(e), Err(_)) => return Err(e),
+            (upper, lower) => (upper.unwrap_or_default(), lower.unwrap_or_default()),
+        };
+
+        let mut hidden: HashSet<String> = HashSet::new();
+        let mut entries = Vec::new();
+
+        for entry in upper {
+            if entry.name() == OPAQUE_NAME {
+                continue;
+            }
+
+            match entry.name().strip_prefix(WHITEOUT_PREFIX) {
+                Some(name) => {
+                    hidden.insert(name.to_owned());
+                }
+                None => {
+                    hidden.insert(entry.name().trim_end_matches('/').to_owned());
+                    entries.push(entry);
+                }
+            }
+        }
+
+        entries.extend(
+            lower
+                .into_iter()
+                .filter(|e| !hidden.contains(e.name().trim_end_matches('/'))),
+        );
+
+        Ok(entries)
+    }
+
+    async fn create_dir(&self, path: &str) -> opendal::Result<()> {
+        let whiteout = self.is_whiteout(path).await;
+
+        self.upper.create_dir(path).await?;
+
+        // The lower directory was removed, its children stay removed.
+        if whiteout && self.lower.stat(path).await.is_ok() {
+            if let Some(opaque) = opaque_path(path) {
+                self.upper.write(&opaque, Vec::new()).await?;
+            }
+        }
+
+        self.clear_whiteout(path).await
+    }
+
+    async fn delete(&self, path: &str) -> opendal::Result<()> {
+        if self.upper.stat(path).await.is_ok() {
+            // The markers of a directory go with it, the whiteout of the
+            // directory hiding the lower children from now on.
+            if path.ends_with('/') {
+                for entry in self.upper.list(path).await.unwrap_or_default() {
+                    if entry.name().starts_with(WHITEOUT_PREFIX) {
+                        self.upper.delete(entry.path()).await?;
+                    }
+                }
+            }
+
+            self.upper.delete(path).await?;
+        }
+
+        if self.lower.stat(path).await.is_ok() {
+            if let Some(whiteout) = whiteout_path(path) {
+                self.upper.write(&whiteout, Vec::new()).await?;
+            }
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,249 @@
+use std::{
+    collections::HashMap,
+    ops::Range,
+    sync::{Arc, Mutex},
+};
+
+use async_trait::async_trait;
+use globset::GlobSet;
+use log::{debug, warn};
+use opendal::{ErrorKind, Metadata, OperatorInfo};
+
+use super::{copy_and_delete, walk, Entry, Storage, WriteMetadata};
+
+/// Default size of the files the scratch storage can hold.
+const DEFAULT_MAX_SCRATCH_BYTES: u64 = 1024 * 1024 * 1024;
+
+/// Keeps the files whose name matches a set of patterns in a local scratch
+/// storage.
+///
+/// Such files, as the temporary files of editors, never reach the inner
+/// storage unless they are renamed to a name that does not match. Matching
+/// objects already in the inner storage are hidden. Writes fail once the
+/// scratch storage would hold more than its maximum size.
+pub struct ScratchStorage {
+    inner: Arc<dyn Storage>,
+    scratch: Arc<dyn Storage>,
+    patterns: GlobSet,
+    max_bytes: u64,
+    /// Size of the files written to the scratch storage, which starts empty.
+    sizes: Mutex<HashMap<String, u64>>,
+}
+
+impl ScratchStorage {
+    pub fn new(inner: Arc<dyn Storage>, scratch: Arc<dyn Storage>, patterns: GlobSet) -> Self {
+        Self {
+            inner,
+            scratch,
+            patterns,
+            max_bytes: DEFAULT_MAX_SCRATCH_BYTES,
+            sizes: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn with_max_bytes(mut self, max_bytes: u64) -> Self {
+        self.max_bytes = max_bytes;
+        self
+    }
+
+    fn is_local(&self, path: &str) -> bool {
+        let name = path.trim_end_matches('/').rsplit('/').next().unwrap_or("");
+
+        !name.is_empty() && !path.ends_with('/') && self.patterns.is_match(name)
+    }
+
+    fn storage(&self, path: &str) -> &Arc<dyn Storage> {
+        if self.is_local(path) {
+            &self.scratch
+        } else {
+            &self.inner
+        }
+    }
+
+    /// Accounts for the local file at `path` growing to `size` bytes,
+    /// failing if the scratch storage would exceed its maximum size. The
+    /// previous size is returned to be put back if the write fails.
+    fn resize(&self, path: &str, size: impl FnOnce(u64) -> u64) -> opendal::Result<u64> {
+        let mut sizes = self.sizes.lock().unwrap();
+
+        let previous = sizes.get(key(path)).copied().unwrap_or_default();
+        let size = size(previous);
+        let total: u64 = sizes.values().sum::<u64>() - previous + size;
+
+        if total > self.max_bytes {
+            warn!("scratch storage is full, not writing {:?}", path);
+
+            return Err(opendal::Error::new(
+                ErrorKind::Unexpected,
+                "scratch storage is full",
+            ));
+        }
+
+        sizes.insert(key(path).to_owned(), size);
+
+        Ok(previous)
+    }
+
+    /// Moves the local files under the directory `from` to `to`, along
+    /// with their sizes.
+    async fn rename_local_dir(&self, from: &str, to: &str) -> opendal::Result<()> {
+        let from_dir = format!("{}/", key(from).trim_end_matches('/'));
+        let to_dir = format!("{}/", key(to).trim_end_matches('/'));
+
+        let entries = match walk(self.scratch.as_ref(), &from_dir).await {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(()),
+            Err(e) => return Err(e),
+        };
+
+        for entry in entries {
+            if entry.metadata().is_dir() || entry.path().ends_with('/') {
+                continue;
+            }
+
+            let Some(child) = key(entry.path()).strip_prefix(&from_dir) else {
+                continue;
+            };
+            let moved = format!("{}{}", to_dir, child);
+
+            debug!("moving local {:?} to {:?}", entry.path(), moved);
+            self.scratch.rename(entry.path(), &moved).await?;
+
+            let mut sizes = self.sizes.lock().unwrap();
+            if let Some(size) = sizes.remove(key(entry.path())) {
+                sizes.insert(moved, size);
+            }
+        }
+
+        Ok(())
+    }
+
+    fn restore_size(&self, path: &str, size: u64) {
+        self.sizes
+            .lock()
+            .unwrap()
+            .insert(key(path).to_owned(), size);
+    }
+}
+
+fn key(path: &str) -> &str {
+    path.trim_start_matches('/')
+}
+
+#[async_trait]
+impl Storage for ScratchStorage {
+    fn info(&self) -> OperatorInfo {
+        self.inner.info()
+    }
+
+    async fn stat(&self, path: &str) -> opendal::Result<Metadata> {
+        self.storage(path).stat(path).await
+    }
+
+    async fn read(&self, path: &str, range: Range<u64>) -> opendal::Result<Vec<u8>> {
+        self.storage(path).read(path, range).await
+    }
+
+    async fn write_with_metadata(
+        &self,
+        path: &str,
+        data: Vec<u8>,
+        metadata: WriteMetadata,
+    ) -> opendal::Result<()> {
+        if !self.is_local(path) {
+            return self.inner.write_with_metadata(path, data, metadata).await;
+        }
+
+        let previous = self.resize(path, |_| data.len() as u64)?;
+
+        let result = self.scratch.write_with_metadata(path, data, metadata).await;
+        if result.is_err() {
+            self.restore_size(path, previous);
+        }
+
+        result
+    }
+
+    async fn append(&self, path: &str, data: Vec<u8>) -> opendal::Result<()> {
+        if !self.is_local(path) {
+            return self.inner.append(path, data).await;
+        }
+
+        let len = data.len() as u64;
+        let previous = self.resize(path, |size| size + len)?;
+
+        let result = self.scratch.append(path, data).await;
+        if result.is_err() {
+            self.restore_size(path, previous);
+        }
+
+        result
+    }
+
+    async fn list(&self, path: &str) -> opendal::Result<Vec<Entry>> {
+        let mut entries = self.inner.list(path).await?;
+        entries.retain(|e| !self.is_local(e.path()));
+
+        let local = match self.scratch.list(path).await {
+            Ok(local) => local,
+            Err(e) if e.kind() == ErrorKind::NotFound => vec![],
+            Err(e) => return Err(e),
+        };
+
+        entries.extend(local.into_iter().filter(|e| self.is_local(e.path())));
+
+        Ok(entries)
+    }
+
+    async fn create_dir(&self, path: &str) -> opendal::Result<()> {
+        self.inner.create_dir(path).await
+    }
+
+    async fn delete(&self, path: &str) -> opendal::Result<()> {
+        if !self.is_local(path) {
+            return self.inner.delete(path).await;
+        }
+
+        self.scratch.delete(path).await?;
+        self.sizes.lock().unwrap().remove(key(path));
+
+        Ok(())
+    }
+
+    async fn rename(&self, from: &str, to: &str) -> opendal::Result<()> {
+        match (self.is_local(from), self.is_local(to)) {
+            (false, false) => {
+                let is_dir = self.inner.stat(from).await.is_ok_and(|meta| meta.is_dir());
+
+                self.inner.rename(from, to).await?;
+
+                // The local files of a directory go with it.
+                if is_dir {
+                    self.rename_local_dir(from, to).await?;
+                }
+
+                Ok(())
+            }
+            (true, true) => {
+                self.scratch.rename(from, to).await?;
+
+                let mut sizes = self.sizes.lock().unwrap();
+                match sizes.remove(key(from)) {
+                    Some(size) => {
+                        sizes.insert(key(to).to_owned(), size);
+                    }
+                    // The file it replaces is gone all the same.
+                    None => {
+                        sizes.remove(key(to));
+                    }
+                }
+
+                Ok(())
+            }
+            _ => {
+                debug!("moving {:?} to {:?} across the scratch storage", from, to);
+                copy_and_delete(self, from, to).await
+            }
+        }
+    }
+}
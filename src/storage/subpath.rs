@@ -0,0 +1,146 @@
+use std::{ops::Range, sync::Arc};
+
+use async_trait::async_trait;
+use opendal::{ErrorKind, Metadata, OperatorInfo};
+
+use super::{Entry, Storage, WriteMetadata};
+
+/// Confines every path to a directory of the inner storage.
+pub struct SubPathStorage {
+    inner: Arc<dyn Storage>,
+    /// Normalized sub path, with a trailing `/`.
+    prefix: String,
+}
+
+impl SubPathStorage {
+    /// Fails if `sub_path` tries to escape the root of `inner`.
+    pub fn new(inner: Arc<dyn Storage>, sub_path: &str) -> opendal::Result<Self> {
+        let prefix = normalize(sub_path)?;
+
+        Ok(Self {
+            inner,
+            prefix: if prefix.is_empty() {
+                prefix
+            } else {
+                format!("{}/", prefix)
+            },
+        })
+    }
+
+    pub fn prefix(&self) -> &str {
+        &self.prefix
+    }
+
+    /// Path in the inner storage of `path`, failing if it escapes the sub
+    /// path.
+    pub fn resolve(&self, path: &str) -> opendal::Result<String> {
+        let relative = normalize(path)?;
+
+        let mut resolved = format!("{}{}", self.prefix, relative);
+        if path.ends_with('/') && !relative.is_empty() {
+            resolved.push('/');
+        }
+        if resolved.is_empty() {
+            resolved.push('/');
+        }
+
+        Ok(resolved)
+    }
+
+    fn strip(&self, entry: Entry) -> Entry {
+        match entry.path().strip_prefix(&self.prefix) {
+            Some(path) => Entry::new(path, entry.metadata().clone()),
+            None => entry,
+        }
+    }
+}
+
+/// Joins the components of `path`, failing on `..` escaping the root.
+pub fn normalize(path: &str) -> opendal::Result<String> {
+    let mut components: Vec<&str> = Vec::new();
+
+    for component in path.split('/') {
+        match component {
+            "" | "." => {}
+            ".." => {
+                components.pop().ok_or_else(|| {
+                    opendal::Error::new(
+                        ErrorKind::PermissionDenied,
+                        "path escapes the root of the mount",
+                    )
+                })?;
+            }
+            component => components.push(component),
+        }
+    }
+
+    Ok(components.join("/"))
+}
+
+#[async_trait]
+impl Storage for SubPathStorage {
+    fn info(&self) -> OperatorInfo {
+        self.inner.info()
+    }
+
+    async fn stat(&self, path: &str) -> opendal::Result<Metadata> {
+        self.inner.stat(&self.resolve(path)?).await
+    }
+
+    async fn read(&self, path: &str, range: Range<u64>) -> opendal::Result<Vec<u8>> {
+        self.inner.read(&self.resolve(path)?, range).await
+    }
+
+    async fn write_with_metadata(
+        &self,
+        path: &str,
+        data: Vec<u8>,
+        metadata: WriteMetadata,
+    ) -> opendal::Result<()> {
+        self.inner
+            .write_with_metadata(&self.resolve(path)?, data, metadata)
+            .await
+    }
+
+    async fn append(&self, path: &str, data: Vec<u8>) -> opendal::Result<()> {
+        self.inner.append(&self.resolve(path)?, data).await
+    }
+
+    async fn list(&self, path: &str) -> opendal::Result<Vec<Entry>> {
+        let mut dir = self.resolve(path)?;
+        if !dir.ends_with('/') {
+            dir.push('/');
+        }
+
+        let entries = self.inner.list(&dir).await?;
+
+        Ok(entries.into_iter().map(|e| self.strip(e)).collect())
+    }
+
+    async fn create_dir(&self, path: &str) -> opendal::Result<()> {
+        self.inner.create_dir(&self.resolve(path)?).await
+    }
+
+    async fn delete(&self, path: &str) -> opendal::Result<()> {
+        self.inner.delete(&self.resolve(path)?).await
+    }
+
+    async fn rename(&self, from: &str, to: &str) -> opendal::Result<()> {
+        self.inner
+            .rename(&self.resolve(from)?, &self.resolve(to)?)
+            .await
+    }
+
+    async fn list_versions(&self, path: &str) -> opendal::Result<Vec<Entry>> {
+        let entries = self.inner.list_versions(&self.resolve(path)?).await?;
+
+        Ok(entries.into_iter().map(|e| self.strip(e)).collect())
+    }
+
+    fn at_version(&self, version: &str) -> opendal::Result<Arc<dyn Storage>> {
+        Ok(Arc::new(Self {
+            inner: self.inner.at_version(version)?,
+            prefix: self.prefix.clone(),
+        }))
+    }
+}
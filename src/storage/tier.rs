@@ -0,0 +1,330 @@
+use std::{
+    collections::{hash_map::DefaultHasher, HashMap, HashSet},
+    hash::{Hash, Hasher},
+    ops::Range,
+    sync::{Arc, Weak},
+    time::{Duration, SystemTime},
+};
+
+use async_trait::async_trait;
+use log::{debug, info, warn};
+use opendal::{ErrorKind, Metadata, OperatorInfo};
+use tokio::sync::{Mutex, MutexGuard, RwLock};
+
+use super::{walk, Entry, Storage, WriteMetadata};
+use crate::schema::Tier;
+
+/// Delay between two demotion sweeps.
+const SWEEP_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Number of locks the paths are spread over.
+const LOCKS: usize = 64;
+
+/// When files are moved from the hot to the cold tier.
+#[derive(Debug, Clone, Default)]
+pub struct TierPolicy {
+    /// Demote files that have not been used for this long.
+    pub max_age: Option<Duration>,
+    /// Demote the least recently used files while the hot tier holds more
+    /// bytes than this.
+    pub max_hot_bytes: Option<u64>,
+    /// Move demoted files back to the hot tier when they are read.
+    pub promote: bool,
+}
+
+/// Keeps recently used files on a hot storage and moves the others to a
+/// cold one.
+///
+/// Every change lands in the hot tier, reads are served by the tier holding
+/// the file. Files are demoted by [`TieredStorage::demote`], which runs
+/// periodically once [`TieredStorage::spawn_sweeper`] is called.
+pub struct TieredStorage {
+    hot: Arc<dyn Storage>,
+    cold: Arc<dyn Storage>,
+    policy: TierPolicy,
+    /// Last use of the hot files read or written since the mount.
+    used: RwLock<HashMap<String, SystemTime>>,
+    /// Serializes the changes to a path with its moves between tiers, so
+    /// that a write is not lost to a demotion copying the file before it.
+    locks: Vec<Mutex<()>>,
+}
+
+impl TieredStorage {
+    pub fn new(hot: Arc<dyn Storage>, cold: Arc<dyn Storage>, policy: TierPolicy) -> Self {
+        Self {
+            hot,
+            cold,
+            policy,
+            used: RwLock::new(HashMap::new()),
+            locks: (0..LOCKS).map(|_| Mutex::new(())).collect(),
+        }
+    }
+
+    async fn lock(&self, path: &str) -> MutexGuard<'_, ()> {
+        let mut hasher = DefaultHasher::new();
+        key(path).hash(&mut hasher);
+
+        self.locks[hasher.finish() as usize % LOCKS].lock().await
+    }
+
+    /// Runs [`TieredStorage::demote`] periodically while the storage is
+    /// alive.
+    pub fn spawn_sweeper(self: &Arc<Self>) {
+        let tiered: Weak<Self> = Arc::downgrade(self);
+
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(SWEEP_INTERVAL);
+
+            loop {
+                interval.tick().await;
+
+                match tiered.upgrade() {
+                    Some(tiered) => {
+                        tiered.demote().await;
+                    }
+                    None => break,
+                }
+            }
+        });
+    }
+
+    /// Tier holding `path`.
+    pub async fn tier(&self, path: &str) -> opendal::Result<Tier> {
+        match self.hot.stat(path).await {
+            Ok(_) => Ok(Tier::Hot),
+            Err(e) if e.kind() == ErrorKind::NotFound => {
+                self.cold.stat(path).await?;
+                Ok(Tier::Cold)
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Moves the files exceeding the policy to the cold tier, returning the
+    /// number of files demoted.
+    pub async fn demote(&self) -> usize {
+        let mut files = match self.hot_files().await {
+            Ok(files) => files,
+            Err(e) => {
+                warn!("unable to list the hot tier: {}", e);
+                return 0;
+            }
+        };
+
+        let now = SystemTime::now();
+        {
+            let mut used = self.used.write().await;
+
+            // Files removed or moved behind the back of the storage.
+            let hot: HashSet<String> = files.iter().map(|(path, ..)| key(path)).collect();
+            used.retain(|path, _| hot.contains(path));
+
+            for (path, _, last_used) in files.iter_mut() {
+                if let Some(t) = used.get(&key(path)) {
+                    *last_used = *t;
+                }
+            }
+        }
+
+        // Least recently used first.
+        files.sort_by_key(|(_, _, last_used)| *last_used);
+
+        let mut hot_bytes: u64 = files.iter().map(|(_, size, _)| size).sum();
+        let mut demoted = 0;
+
+        for (path, size, last_used) in files {
+            let too_old = match self.policy.max_age {
+                Some(max_age) => now.duration_since(last_used).unwrap_or_default() > max_age,
+                None => false,
+            };
+            let too_big = match self.policy.max_hot_bytes {
+                Some(max_hot_bytes) => hot_bytes > max_hot_bytes,
+                None => false,
+            };
+
+            if !too_old && !too_big {
+                continue;
+            }
+
+            let _lock = self.lock(&path).await;
+
+            // Used since listed, the file is left for the next sweep.
+            let used = self.used.read().await.get(&key(&path)).copied();
+            if used.is_some_and(|used| used > last_used) {
+                continue;
+            }
+
+            match self.move_to(&path, &self.hot, &self.cold).await {
+                Ok(()) => {
+                    debug!("demoted {:?}", path);
+
+                    self.used.write().await.remove(&key(&path));
+                    hot_bytes -= size;
+                    demoted += 1;
+                }
+                Err(e) => warn!("unable to demote {:?}: {}", path, e),
+            }
+        }
+
+        if demoted > 0 {
+            info!("demoted {} files to the cold tier", demoted);
+        }
+
+        demoted
+    }
+
+    /// Collects the path, size and last modification of the files in the
+    /// hot tier.
+    async fn hot_files(&self) -> opendal::Result<Vec<(String, u64, SystemTime)>> {
+        let mut files = Vec::new();
+
+        for entry in walk(self.hot.as_ref(), "/").await? {
+            if entry.metadata().is_dir() || entry.path().ends_with('/') {
+                continue;
+            }
+
+            let meta = match entry.metadata().last_modified() {
+                Some(_) => entry.metadata().clone(),
+                None => self.hot.stat(entry.path()).await?,
+            };
+            let modified = meta
+                .last_modified()
+                .map(SystemTime::from)
+                .unwrap_or(SystemTime::UNIX_EPOCH);
+
+            files.push((entry.path().to_owned(), meta.content_length(), modified));
+        }
+
+        Ok(files)
+    }
+
+    async fn move_to(
+        &self,
+        path: &str,
+        from: &Arc<dyn Storage>,
+        to: &Arc<dyn Storage>,
+    ) -> opendal::Result<()> {
+        let meta = from.stat(path).await?;
+        let data = from.read(path, 0..meta.content_length()).await?;
+
+        to.write_with_metadata(path, data, WriteMetadata::from(&meta))
+            .await?;
+        from.delete(path).await
+    }
+
+    async fn touch(&self, path: &str) {
+        self.used.write().await.insert(key(path), SystemTime::now());
+    }
+
+    async fn delete_cold(&self, path: &str) -> opendal::Result<()> {
+        match self.cold.delete(path).await {
+            Err(e) if e.kind() != ErrorKind::NotFound => Err(e),
+            _ => Ok(()),
+        }
+    }
+}
+
+fn key(path: &str) -> String {
+    path.trim_start_matches('/').to_owned()
+}
+
+#[async_trait]
+impl Storage for TieredStorage {
+    fn info(&self) -> OperatorInfo {
+        self.hot.info()
+    }
+
+    async fn stat(&self, path: &str) -> opendal::Result<Metadata> {
+        match self.hot.stat(path).await {
+            Err(e) if e.kind() == ErrorKind::NotFound => self.cold.stat(path).await,
+            meta => meta,
+        }
+    }
+
+    async fn read(&self, path: &str, range: Range<u64>) -> opendal::Result<Vec<u8>> {
+        if let Tier::Cold = self.tier(path).await? {
+            if !self.policy.promote {
+                return self.cold.read(path, range).await;
+            }
+
+            let _lock = self.lock(path).await;
+
+            // Promoted by another read while waiting for the lock.
+            if let Tier::Cold = self.tier(path).await? {
+                debug!("promoting {:?}", path);
+                self.move_to(path, &self.cold, &self.hot).await?;
+            }
+        }
+
+        self.touch(path).await;
+
+        self.hot.read(path, range).await
+    }
+
+    async fn write_with_metadata(
+        &self,
+        path: &str,
+        data: Vec<u8>,
+        metadata: WriteMetadata,
+    ) -> opendal::Result<()> {
+        let _lock = self.lock(path).await;
+
+        self.hot.write_with_metadata(path, data, metadata).await?;
+        self.touch(path).await;
+
+        self.delete_cold(path).await
+    }
+
+    async fn append(&self, path: &str, data: Vec<u8>) -> opendal::Result<()> {
+        let _lock = self.lock(path).await;
+
+        if let Ok(Tier::Cold) = self.tier(path).await {
+            self.move_to(path, &self.cold, &self.hot).await?;
+        }
+
+        self.hot.append(path, data).await?;
+        self.touch(path).await;
+
+        Ok(())
+    }
+
+    async fn list(&self, path: &str) -> opendal::Result<Vec<Entry>> {
+        let hot = self.hot.list(path).await;
+        let cold = self.cold.list(path).await;
+
+        let (hot, cold) = match (hot, cold) {
+            (Err(e), Err(_)) => return Err(e),
+            (hot, cold) => (hot.unwrap_or_default(), cold.unwrap_or_default()),
+        };
+
+        let names: HashSet<String> = hot
+            .iter()
+            .map(|e| e.name().trim_end_matches('/').to_owned())
+            .collect();
+
+        let mut entries = hot;
+        entries.extend(
+            cold.into_iter()
+                .filter(|e| !names.contains(e.name().trim_end_matches('/'))),
+        );
+
+        Ok(entries)
+    }
+
+    async fn create_dir(&self, path: &str) -> opendal::Result<()> {
+        self.hot.create_dir(path).await
+    }
+
+    async fn delete(&self, path: &str) -> opendal::Result<()> {
+        let _lock = self.lock(path).await;
+
+        match self.hot.delete(path).await {
+            Err(e) if e.kind() != ErrorKind::NotFound => return Err(e),
+            _ => {}
+        }
+
+        self.used.write().await.remove(&key(path));
+
+        self.delete_cold(path).await
+    }
+}
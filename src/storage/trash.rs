@@ -0,0 +1,356 @@
+use std::{
+    ops::Range,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Weak,
+    },
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use async_trait::async_trait;
+use log::{debug, info, warn};
+use opendal::{ErrorKind, Metadata, OperatorInfo};
+
+use super::{walk, Entry, Storage, WriteMetadata};
+use crate::schema::TrashItem;
+
+/// Directory holding the removed and overwritten objects, at the root of
+/// the inner storage.
+const TRASH_DIR: &str = ".trash";
+
+/// Delay between two retention sweeps.
+const SWEEP_INTERVAL: Duration = Duration::from_secs(3600);
+
+/// Moves removed and overwritten objects to `.trash/<timestamp>-<sequence>/`
+/// instead of deleting them.
+///
+/// The timestamp is the time of the removal, in milliseconds since the
+/// epoch, the sequence telling apart the removals of the same millisecond.
+/// Objects are renamed into the trash, which only copies them on backends
+/// unable to rename. The trash is hidden from the files of the storage, its objects
+/// being listed by [`TrashStorage::items`] and moved back by
+/// [`TrashStorage::restore`].
+pub struct TrashStorage {
+    inner: Arc<dyn Storage>,
+    retention: Duration,
+    /// Number of objects moved to the trash so far.
+    sequence: AtomicU64,
+}
+
+fn key(path: &str) -> &str {
+    path.trim_start_matches('/')
+}
+
+fn in_trash(path: &str) -> bool {
+    let path = key(path);
+
+    path == TRASH_DIR || path.starts_with(&format!("{}/", TRASH_DIR))
+}
+
+fn hidden() -> opendal::Error {
+    opendal::Error::new(ErrorKind::NotFound, "no such file")
+}
+
+fn reserved() -> opendal::Error {
+    opendal::Error::new(ErrorKind::PermissionDenied, "reserved for the trash")
+}
+
+fn not_in_trash() -> opendal::Error {
+    opendal::Error::new(ErrorKind::NotFound, "not in the trash")
+}
+
+/// Time of the removals in the trash directory named `name`, written as
+/// `<timestamp>` before sequences were added.
+fn removed_at(name: &str) -> Option<u128> {
+    let timestamp = match name.split_once('-') {
+        Some((timestamp, sequence)) => {
+            sequence.parse::<u64>().ok()?;
+            timestamp
+        }
+        None => name,
+    };
+
+    timestamp.parse().ok()
+}
+
+fn now_millis() -> u128 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis())
+        .unwrap_or_default()
+}
+
+impl TrashStorage {
+    pub fn new(inner: Arc<dyn Storage>, retention: Duration) -> Self {
+        Self {
+            inner,
+            retention,
+            sequence: AtomicU64::new(0),
+        }
+    }
+
+    /// Purges expired trash entries periodically while the storage is
+    /// alive.
+    pub fn spawn_sweeper(self: &Arc<Self>) {
+        let trash: Weak<Self> = Arc::downgrade(self);
+
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(SWEEP_INTERVAL);
+
+            loop {
+                interval.tick().await;
+
+                match trash.upgrade() {
+                    Some(trash) => {
+                        trash.purge().await;
+                    }
+                    None => break,
+                }
+            }
+        });
+    }
+
+    /// Moves the file at `path` to the trash, if any, returning whether it
+    /// was moved.
+    async fn move_to_trash(&self, path: &str) -> opendal::Result<bool> {
+        let meta = match self.inner.stat(path).await {
+            Ok(meta) => meta,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(false),
+            Err(e) => return Err(e),
+        };
+
+        // Overwriting an empty file, as done right after creating it, does
+        // not lose anything.
+        if meta.is_dir() || meta.content_length() == 0 {
+            return Ok(false);
+        }
+
+        let trash_path = format!(
+            "{}/{}-{}/{}",
+            TRASH_DIR,
+            now_millis(),
+            self.sequence.fetch_add(1, Ordering::Relaxed),
+            key(path)
+        );
+        debug!("moving {:?} to {:?}", path, trash_path);
+
+        self.inner.rename(path, &trash_path).await?;
+
+        Ok(true)
+    }
+
+    /// Lists the objects in the trash.
+    pub async fn items(&self) -> opendal::Result<Vec<TrashItem>> {
+        let entries = match walk(self.inner.as_ref(), &format!("{}/", TRASH_DIR)).await {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(vec![]),
+            Err(e) => return Err(e),
+        };
+
+        let mut items = Vec::new();
+
+        for entry in entries {
+            if entry.metadata().is_dir() || entry.path().ends_with('/') {
+                continue;
+            }
+
+            let trash_path = key(entry.path());
+            let Some((timestamp, path)) = trash_path
+                .strip_prefix(&format!("{}/", TRASH_DIR))
+                .and_then(|rest| rest.split_once('/'))
+            else {
+                continue;
+            };
+            let Some(removed_at) = removed_at(timestamp) else {
+                continue;
+            };
+
+            items.push(TrashItem {
+                path: path.to_owned(),
+                trash_path: trash_path.to_owned(),
+                removed_at,
+                size: entry.metadata().content_length(),
+            });
+        }
+
+        items.sort_by(|a, b| a.trash_path.cmp(&b.trash_path));
+
+        Ok(items)
+    }
+
+    /// Moves the object at `trash_path` back to its original path, returning
+    /// that path.
+    pub async fn restore(&self, trash_path: &str) -> opendal::Result<String> {
+        let components: Vec<&str> = key(trash_path).split('/').collect();
+
+        // Only `.trash/<timestamp>-<sequence>/<path>`, without any `.` or
+        // `..` leading out of the trash.
+        match components.as_slice() {
+            [TRASH_DIR, dir, path @ ..]
+                if removed_at(dir).is_some()
+                    && !path.is_empty()
+                    && path.iter().all(|c| !matches!(*c, "" | "." | "..")) => {}
+            _ => return Err(not_in_trash()),
+        }
+
+        let trash_path = components.join("/");
+        let path = components[2..].join("/");
+
+        let meta = self.inner.stat(&trash_path).await?;
+        if meta.is_dir() {
+            return Err(not_in_trash());
+        }
+
+        self.move_to_trash(&path).await?;
+        self.inner.rename(&trash_path, &path).await?;
+
+        info!("restored {:?} from {:?}", path, trash_path);
+
+        Ok(path)
+    }
+
+    /// Deletes the trash entries older than the retention, returning the
+    /// number of objects deleted.
+    pub async fn purge(&self) -> usize {
+        let expired = match self.inner.list(&format!("{}/", TRASH_DIR)).await {
+            Ok(entries) => {
+                let limit = now_millis().saturating_sub(self.retention.as_millis());
+
+                entries
+                    .into_iter()
+                    .filter(|e| {
+                        removed_at(e.name().trim_end_matches('/')).is_some_and(|t| t < limit)
+                    })
+                    .collect()
+            }
+            Err(e) if e.kind() == ErrorKind::NotFound => vec![],
+            Err(e) => {
+                warn!("unable to list the trash: {}", e);
+                vec![]
+            }
+        };
+
+        let mut purged = 0;
+
+        for dir in expired {
+            match self.delete_all(dir.path()).await {
+                Ok(count) => purged += count,
+                Err(e) => warn!("unable to purge {:?}: {}", dir.path(), e),
+            }
+        }
+
+        if purged > 0 {
+            info!("purged {} objects from the trash", purged);
+        }
+
+        purged
+    }
+
+    /// Deletes the directory at `dir` and everything it holds.
+    async fn delete_all(&self, dir: &str) -> opendal::Result<usize> {
+        let mut entries = walk(self.inner.as_ref(), dir).await?;
+        let count = entries.iter().filter(|e| e.metadata().is_file()).count();
+
+        // Children are listed after their parents.
+        entries.reverse();
+        for entry in entries {
+            self.inner.delete(entry.path()).await?;
+        }
+        self.inner.delete(dir).await?;
+
+        Ok(count)
+    }
+}
+
+#[async_trait]
+impl Storage for TrashStorage {
+    fn info(&self) -> OperatorInfo {
+        self.inner.info()
+    }
+
+    async fn stat(&self, path: &str) -> opendal::Result<Metadata> {
+        if in_trash(path) {
+            return Err(hidden());
+        }
+
+        self.inner.stat(path).await
+    }
+
+    async fn read(&self, path: &str, range: Range<u64>) -> opendal::Result<Vec<u8>> {
+        if in_trash(path) {
+            return Err(hidden());
+        }
+
+        self.inner.read(path, range).await
+    }
+
+    async fn write_with_metadata(
+        &self,
+        path: &str,
+        data: Vec<u8>,
+        metadata: WriteMetadata,
+    ) -> opendal::Result<()> {
+        if in_trash(path) {
+            return Err(reserved());
+        }
+
+        self.move_to_trash(path).await?;
+
+        self.inner.write_with_metadata(path, data, metadata).await
+    }
+
+    async fn append(&self, path: &str, data: Vec<u8>) -> opendal::Result<()> {
+        if in_trash(path) {
+            return Err(reserved());
+        }
+
+        self.inner.append(path, data).await
+    }
+
+    async fn list(&self, path: &str) -> opendal::Result<Vec<Entry>> {
+        if in_trash(path) {
+            return Err(hidden());
+        }
+
+        let mut entries = self.inner.list(path).await?;
+
+        if path.trim_matches('/').is_empty() {
+            entries.retain(|e| e.name().trim_end_matches('/') != TRASH_DIR);
+        }
+
+        Ok(entries)
+    }
+
+    async fn create_dir(&self, path: &str) -> opendal::Result<()> {
+        if in_trash(path) {
+            return Err(reserved());
+        }
+
+        self.inner.create_dir(path).await
+    }
+
+    async fn delete(&self, path: &str) -> opendal::Result<()> {
+        if in_trash(path) {
+            return Err(hidden());
+        }
+
+        if self.move_to_trash(path).await? {
+            return Ok(());
+        }
+
+        self.inner.delete(path).await
+    }
+
+    async fn rename(&self, from: &str, to: &str) -> opendal::Result<()> {
+        if in_trash(from) {
+            return Err(hidden());
+        }
+        if in_trash(to) {
+            return Err(reserved());
+        }
+
+        self.move_to_trash(to).await?;
+
+        self.inner.rename(from, to).await
+    }
+}
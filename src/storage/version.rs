@@ -0,0 +1,72 @@
+use std::ops::Range;
+
+use async_trait::async_trait;
+use opendal::{ErrorKind, Metadata, Operator, OperatorInfo};
+
+use super::{Entry, Storage, WriteMetadata};
+
+/// Read only view of an operator pinned to a version of its objects.
+pub struct VersionedOperator {
+    op: Operator,
+    version: String,
+}
+
+impl VersionedOperator {
+    pub fn new(op: Operator, version: &str) -> Self {
+        Self {
+            op,
+            version: version.to_owned(),
+        }
+    }
+}
+
+fn read_only() -> opendal::Error {
+    opendal::Error::new(ErrorKind::PermissionDenied, "object versions are read only")
+}
+
+#[async_trait]
+impl Storage for VersionedOperator {
+    fn info(&self) -> OperatorInfo {
+        self.op.info()
+    }
+
+    async fn stat(&self, path: &str) -> opendal::Result<Metadata> {
+        self.op.stat_with(path).version(&self.version).await
+    }
+
+    async fn read(&self, path: &str, range: Range<u64>) -> opendal::Result<Vec<u8>> {
+        let data = self
+            .op
+            .read_with(path)
+            .range(range)
+            .version(&self.version)
+            .await?;
+
+        Ok(data.to_vec())
+    }
+
+    async fn write_with_metadata(
+        &self,
+        _path: &str,
+        _data: Vec<u8>,
+        _metadata: WriteMetadata,
+    ) -> opendal::Result<()> {
+        Err(read_only())
+    }
+
+    async fn append(&self, _path: &str, _data: Vec<u8>) -> opendal::Result<()> {
+        Err(read_only())
+    }
+
+    async fn list(&self, path: &str) -> opendal::Result<Vec<Entry>> {
+        Storage::list(&self.op, path).await
+    }
+
+    async fn create_dir(&self, _path: &str) -> opendal::Result<()> {
+        Err(read_only())
+    }
+
+    async fn delete(&self, _path: &str) -> opendal::Result<()> {
+        Err(read_only())
+    }
+}
@@ -0,0 +1,70 @@
+use opendal::{EntryMode, Metadata};
+
+use crate::storage::Storage;
+
+/// Root of the virtual tree exposing object versions.
+pub const VERSIONS_DIR: &str = "/.versions";
+pub const VERSIONS_NAME: &str = ".versions";
+
+/// A node of the `.versions` tree.
+///
+/// The tree mirrors the directories of the mount, each file being replaced
+/// by a directory holding one read only file per version.
+pub enum VersionNode {
+    /// A directory of the mount, listed with its children.
+    Tree(String),
+    /// A file of the mount, listed with its versions.
+    Versions(String),
+    /// A version of a file.
+    Version { path: String, version: String },
+}
+
+/// Path of the mount matching a path inside the `.versions` tree.
+pub fn strip(path: &str) -> Option<&str> {
+    if path == VERSIONS_DIR {
+        Some("/")
+    } else {
+        path.strip_prefix(VERSIONS_DIR)
+            .filter(|rest| rest.starts_with('/'))
+    }
+}
+
+/// Path inside the `.versions` tree of a path of the mount.
+pub fn versions_path(path: &str) -> String {
+    format!("{}/{}", VERSIONS_DIR, path.trim_start_matches('/'))
+}
+
+impl VersionNode {
+    /// Resolves a path of the `.versions` tree, `None` if it does not exist.
+    pub async fn resolve(storage: &dyn Storage, path: &str) -> Option<(Self, Metadata)> {
+        let inner = strip(path)?;
+
+        if inner == "/" {
+            return Some((
+                VersionNode::Tree(inner.to_owned()),
+                Metadata::new(EntryMode::DIR),
+            ));
+        }
+
+        if let Ok(meta) = storage.stat(inner).await {
+            return if meta.is_dir() {
+                Some((VersionNode::Tree(inner.to_owned()), meta))
+            } else {
+                Some((VersionNode::Versions(inner.to_owned()), meta))
+            };
+        }
+
+        let (parent, version) = inner.trim_end_matches('/').rsplit_once('/')?;
+        let meta = storage.at_version(version).ok()?.stat(parent).await.ok()?;
+
+        meta.is_file().then(|| {
+            (
+                VersionNode::Version {
+                    path: parent.to_owned(),
+                    version: version.to_owned(),
+                },
+                meta,
+            )
+        })
+    }
+}
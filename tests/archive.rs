@@ -0,0 +1,214 @@
+mod common;
+
+use std::{
+    io::Write,
+    ops::Range,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+};
+
+use async_trait::async_trait;
+use common::TestFixture;
+use opendal::{Metadata, Operator, OperatorInfo};
+use opendal_mount::storage::{ArchiveStorage, Entry, Storage, WriteMetadata};
+use zip::{write::SimpleFileOptions, CompressionMethod, ZipWriter};
+
+/// Storage counting its reads.
+struct Counted {
+    inner: Operator,
+    reads: AtomicUsize,
+}
+
+#[async_trait]
+impl Storage for Counted {
+    fn info(&self) -> OperatorInfo {
+        self.inner.info()
+    }
+
+    async fn stat(&self, path: &str) -> opendal::Result<Metadata> {
+        Storage::stat(&self.inner, path).await
+    }
+
+    async fn read(&self, path: &str, range: Range<u64>) -> opendal::Result<Vec<u8>> {
+        self.reads.fetch_add(1, Ordering::Relaxed);
+
+        Storage::read(&self.inner, path, range).await
+    }
+
+    async fn write_with_metadata(
+        &self,
+        path: &str,
+        data: Vec<u8>,
+        metadata: WriteMetadata,
+    ) -> opendal::Result<()> {
+        self.inner.write_with_metadata(path, data, metadata).await
+    }
+
+    async fn append(&self, path: &str, data: Vec<u8>) -> opendal::Result<()> {
+        Storage::append(&self.inner, path, data).await
+    }
+
+    async fn list(&self, path: &str) -> opendal::Result<Vec<Entry>> {
+        Storage::list(&self.inner, path).await
+    }
+
+    async fn create_dir(&self, path: &str) -> opendal::Result<()> {
+        Storage::create_dir(&self.inner, path).await
+    }
+
+    async fn delete(&self, path: &str) -> opendal::Result<()> {
+        Storage::delete(&self.inner, path).await
+    }
+}
+
+fn tar_archive() -> anyhow::Result<Vec<u8>> {
+    let mut builder = tar::Builder::new(Vec::new());
+
+    for (path, data) in [
+        ("a.txt", &b"hello world"[..]),
+        ("dir/b.txt", b"bye"),
+        (&format!("dir/{}.txt", "n".repeat(120)), b"long"),
+    ] {
+        let mut header = tar::Header::new_gnu();
+        header.set_size(data.len() as u64);
+        header.set_mode(0o644);
+        builder.append_data(&mut header, path, data)?;
+    }
+
+    Ok(builder.into_inner()?)
+}
+
+fn zip_archive() -> anyhow::Result<Vec<u8>> {
+    let mut writer = ZipWriter::new(std::io::Cursor::new(Vec::new()));
+
+    writer.start_file(
+        "a.txt",
+        SimpleFileOptions::default().compression_method(CompressionMethod::Stored),
+    )?;
+    writer.write_all(b"hello world")?;
+
+    writer.add_directory("dir/", SimpleFileOptions::default())?;
+    writer.start_file(
+        "dir/b.txt",
+        SimpleFileOptions::default().compression_method(CompressionMethod::Deflated),
+    )?;
+    writer.write_all(b"bye")?;
+
+    Ok(writer.finish()?.into_inner())
+}
+
+async fn names(storage: &ArchiveStorage, path: &str) -> anyhow::Result<Vec<String>> {
+    let mut names: Vec<String> = storage
+        .list(path)
+        .await?
+        .iter()
+        .map(|e| e.name().to_owned())
+        .collect();
+    names.sort();
+
+    Ok(names)
+}
+
+#[tokio::test]
+async fn tar_members() -> anyhow::Result<()> {
+    let fixture = TestFixture::new()?;
+    fixture.base.write("data.tar", tar_archive()?).await?;
+
+    let archive = ArchiveStorage::open(Arc::new(fixture.base.clone()), "data.tar").await?;
+
+    assert_eq!(names(&archive, "/").await?, vec!["a.txt", "dir/"]);
+    assert_eq!(names(&archive, "dir/").await?.len(), 2);
+
+    assert_eq!(archive.stat("a.txt").await?.content_length(), 11);
+    assert_eq!(archive.read("a.txt", 6..11).await?, b"world");
+    assert_eq!(
+        archive
+            .read(&format!("dir/{}.txt", "n".repeat(120)), 0..100)
+            .await?,
+        b"long"
+    );
+
+    assert!(archive.write("c.txt", b"c".to_vec()).await.is_err());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn zip_members() -> anyhow::Result<()> {
+    let fixture = TestFixture::new()?;
+    fixture.base.write("data.zip", zip_archive()?).await?;
+
+    let archive = ArchiveStorage::open(Arc::new(fixture.base.clone()), "data.zip").await?;
+
+    assert_eq!(names(&archive, "/").await?, vec!["a.txt", "dir/"]);
+    assert_eq!(names(&archive, "dir").await?, vec!["b.txt"]);
+
+    assert_eq!(archive.read("a.txt", 0..5).await?, b"hello");
+    assert_eq!(archive.read("dir/b.txt", 1..3).await?, b"ye");
+    assert!(archive.stat("dir").await?.is_dir());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn oversized_members_are_rejected() -> anyhow::Result<()> {
+    let fixture = TestFixture::new()?;
+
+    let mut tar = tar_archive()?;
+    tar[124..136].copy_from_slice(b"77777777777\0");
+    fixture.base.write("data.tar", tar).await?;
+
+    assert!(
+        ArchiveStorage::open(Arc::new(fixture.base.clone()), "data.tar")
+            .await
+            .is_err()
+    );
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn large_deflated_members_are_read_on() -> anyhow::Result<()> {
+    let fixture = TestFixture::new()?;
+
+    let content: Vec<u8> = (0..70 * 1024 * 1024).map(|i| (i % 251) as u8).collect();
+
+    let mut writer = ZipWriter::new(std::io::Cursor::new(Vec::new()));
+    writer.start_file(
+        "large.bin",
+        SimpleFileOptions::default().compression_method(CompressionMethod::Deflated),
+    )?;
+    writer.write_all(&content)?;
+    fixture
+        .base
+        .write("data.zip", writer.finish()?.into_inner())
+        .await?;
+
+    let counted = Arc::new(Counted {
+        inner: fixture.base.clone(),
+        reads: AtomicUsize::new(0),
+    });
+    let archive = ArchiveStorage::open(counted.clone(), "data.zip").await?;
+
+    let at = 65 * 1024 * 1024;
+    assert_eq!(
+        archive.read("large.bin", at..at + 1024).await?,
+        &content[at as usize..at as usize + 1024]
+    );
+
+    // Read on from where the last read stopped, without fetching the
+    // compressed data again.
+    let reads = counted.reads.load(Ordering::Relaxed);
+    assert_eq!(
+        archive.read("large.bin", at + 1024..at + 4096).await?,
+        &content[at as usize + 1024..at as usize + 4096]
+    );
+    assert_eq!(counted.reads.load(Ordering::Relaxed), reads);
+
+    // Reading before starts over.
+    assert_eq!(archive.read("large.bin", 0..16).await?, &content[..16]);
+
+    Ok(())
+}
@@ -14,6 +14,7 @@ fn init() {
     pretty_env_logger::init();
 }
 
+#[allow(dead_code)]
 #[async_trait]
 pub trait ListDir {
     async fn entries(&self, path: &str) -> anyhow::Result<Vec<String>>;
@@ -31,19 +32,20 @@ impl ListDir for Operator {
     }
 }
 
+#[allow(dead_code)]
 pub struct TestFixture {
     pub root: TempDir,
     pub base: Operator,
 }
 
+#[allow(dead_code)]
 impl TestFixture {
     pub fn new() -> anyhow::Result<Self> {
         let root: tempfile::TempDir = tempfile::tempdir()?;
         let base_root = root.path().join("base");
 
         let base = {
-            let mut builder = Fs::default();
-            builder.root(base_root.to_str().unwrap());
+            let builder = Fs::default().root(base_root.to_str().unwrap());
 
             Operator::new(builder)?.finish()
         };
@@ -0,0 +1,144 @@
+mod common;
+
+use std::sync::Arc;
+
+use common::TestFixture;
+use nfsserve::{nfs::sattr3, vfs::NFSFileSystem};
+use opendal_mount::{schema::Compression, storage::CompressedStorage, OpendalFs};
+
+async fn roundtrip(compression: Compression) -> anyhow::Result<()> {
+    let fixture = TestFixture::new()?;
+    let storage = Arc::new(CompressedStorage::new(
+        Arc::new(fixture.base.clone()),
+        compression,
+    ));
+    let fs = OpendalFs::with_storage(storage.clone());
+
+    let content: Vec<u8> = b"2024-07-01 INFO request served\n"
+        .iter()
+        .copied()
+        .cycle()
+        .take(300_000)
+        .collect();
+
+    let (id, _) = fs
+        .create(
+            fs.root_dir(),
+            &b"app.log".to_vec().into(),
+            sattr3::default(),
+        )
+        .await
+        .unwrap();
+    fs.write(id, 0, &content[..150_000]).await.unwrap();
+    fs.write(id, 150_000, &content[150_000..]).await.unwrap();
+
+    // The appended half is read from memory until sealed.
+    let attr = fs.getattr(id).await.unwrap();
+    assert_eq!(attr.size, content.len() as u64);
+    let (data, _) = fs.read(id, 120_000, 100_000).await.unwrap();
+    assert_eq!(data, &content[120_000..220_000]);
+
+    storage.seal_all().await;
+
+    let attr = fs.getattr(id).await.unwrap();
+    assert_eq!(attr.size, content.len() as u64);
+
+    let stored = fixture.base.stat("app.log").await?.content_length();
+    assert!(stored < content.len() as u64 / 10);
+
+    let (data, _) = fs.read(id, 120_000, 100_000).await.unwrap();
+    assert_eq!(data, &content[120_000..220_000]);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn zstd_roundtrip() -> anyhow::Result<()> {
+    roundtrip(Compression::Zstd).await
+}
+
+#[tokio::test]
+async fn gzip_roundtrip() -> anyhow::Result<()> {
+    roundtrip(Compression::Gzip).await
+}
+
+#[tokio::test]
+async fn seals_are_appended() -> anyhow::Result<()> {
+    let fixture = TestFixture::new()?;
+    let storage = Arc::new(CompressedStorage::new(
+        Arc::new(fixture.base.clone()),
+        Compression::Zstd,
+    ));
+    let fs = OpendalFs::with_storage(storage.clone());
+
+    let content: Vec<u8> = b"2024-07-01 INFO request served\n"
+        .iter()
+        .copied()
+        .cycle()
+        .take(400_000)
+        .collect();
+
+    let (id, _) = fs
+        .create(
+            fs.root_dir(),
+            &b"app.log".to_vec().into(),
+            sattr3::default(),
+        )
+        .await
+        .unwrap();
+
+    fs.write(id, 0, &content[..100_000]).await.unwrap();
+
+    let mut stored = fixture.base.read("app.log").await?.to_vec();
+    for (i, part) in content.chunks(100_000).enumerate().skip(1) {
+        fs.write(id, i as u64 * 100_000, part).await.unwrap();
+        storage.seal_all().await;
+
+        // Earlier seals are left as they are.
+        let sealed = fixture.base.read("app.log").await?.to_vec();
+        assert!(sealed.len() > stored.len());
+        assert_eq!(sealed[..stored.len()], stored[..]);
+        stored = sealed;
+    }
+
+    let attr = fs.getattr(id).await.unwrap();
+    assert_eq!(attr.size, content.len() as u64);
+
+    let (data, _) = fs.read(id, 50_000, 300_000).await.unwrap();
+    assert_eq!(data, &content[50_000..350_000]);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn uncompressed_objects_are_passed_through() -> anyhow::Result<()> {
+    let fixture = TestFixture::new()?;
+    let storage = Arc::new(CompressedStorage::new(
+        Arc::new(fixture.base.clone()),
+        Compression::Gzip,
+    ));
+    let fs = OpendalFs::with_storage(storage.clone());
+
+    fixture
+        .base
+        .write("plain.txt", "written before compression")
+        .await?;
+
+    let id = fs
+        .lookup(fs.root_dir(), &b"plain.txt".to_vec().into())
+        .await
+        .unwrap();
+    let attr = fs.getattr(id).await.unwrap();
+    assert_eq!(attr.size, 26);
+
+    let (data, _) = fs.read(id, 8, 100).await.unwrap();
+    assert_eq!(data, b"before compression");
+
+    fs.write(id, 26, b", then appended").await.unwrap();
+    storage.seal_all().await;
+
+    let data = fixture.base.read("plain.txt").await?.to_vec();
+    assert_eq!(data, b"written before compression, then appended");
+
+    Ok(())
+}
@@ -0,0 +1,104 @@
+mod common;
+
+use opendal_mount::{
+    config::Config,
+    errors::OpendalMountError,
+    schema::{Compression, OperatorInput},
+    MountSource,
+};
+
+#[test]
+fn mounts_inherit_defaults() -> anyhow::Result<()> {
+    let config = Config::parse(
+        r#"
+        [server]
+        port = 1300
+
+        [defaults]
+        read_only = true
+        compression = "zstd"
+
+        [[mounts]]
+        mount_point = "/mnt/a"
+        service = "memory"
+
+        [[mounts]]
+        mount_point = "/mnt/b"
+        scheme = "memory"
+        options = { read_only = false, sub_path = "data" }
+        "#,
+    )?;
+
+    assert_eq!(config.server.port, Some(1300));
+
+    let entries = config.entries();
+    assert_eq!(entries.len(), 2);
+    assert!(entries[0].options.read_only);
+    assert!(!entries[1].options.read_only);
+    assert_eq!(entries[1].options.sub_path.as_deref(), Some("data"));
+    assert_eq!(entries[1].options.compression, Some(Compression::Zstd));
+
+    Ok(())
+}
+
+#[test]
+fn secrets_stay_out_of_the_file() -> anyhow::Result<()> {
+    let dir = tempfile::tempdir()?;
+    let secret = dir.path().join("secret");
+    std::fs::write(&secret, "s3cr3t\n")?;
+
+    std::env::set_var("OPENDAL_MOUNT_TEST_BUCKET", "bucket");
+    std::env::set_var("OPENDAL_MOUNT_TEST_SECRET", secret.to_str().unwrap());
+
+    let config = Config::parse(
+        r#"
+        [[mounts]]
+        mount_point = "/mnt/s3"
+        service = "s3"
+
+        [mounts.parameters]
+        bucket = "${OPENDAL_MOUNT_TEST_BUCKET}"
+        secret_access_key_file = "${OPENDAL_MOUNT_TEST_SECRET}"
+        region = "$$region"
+        "#,
+    )?;
+
+    let MountSource::Operator(OperatorInput { parameters, .. }) = &config.entries()[0].source
+    else {
+        panic!("not an operator");
+    };
+    assert_eq!(parameters["bucket"], "bucket");
+    assert_eq!(parameters["secret_access_key"], "s3cr3t");
+    assert_eq!(parameters["region"], "$region");
+    assert!(!parameters.contains_key("secret_access_key_file"));
+
+    Ok(())
+}
+
+#[test]
+fn unset_variables_are_errors() {
+    let config = Config::parse(
+        r#"
+        [[mounts]]
+        mount_point = "/mnt/a"
+        service = "memory"
+        parameters = { root = "${OPENDAL_MOUNT_TEST_UNSET}" }
+        "#,
+    );
+
+    assert!(matches!(config, Err(OpendalMountError::InvalidConfig(_))));
+}
+
+#[test]
+fn unknown_options_are_errors() {
+    for options in [
+        "[defaults]\nreadonly = true",
+        "[defaults]\ntrash = { retention = 60 }",
+        "[[mounts]]\nmount_point = \"/mnt/a\"\nservice = \"memory\"\noptions = { encryption_key = \"k\" }",
+    ] {
+        assert!(matches!(
+            Config::parse(options),
+            Err(OpendalMountError::InvalidConfig(_))
+        ));
+    }
+}
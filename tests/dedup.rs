@@ -0,0 +1,150 @@
+mod common;
+
+use std::sync::Arc;
+
+use common::TestFixture;
+use opendal::Operator;
+use opendal_mount::storage::{DedupStorage, Storage};
+
+/// Deterministic incompressible content.
+fn content(len: usize, seed: u64) -> Vec<u8> {
+    let mut state = seed;
+
+    (0..len)
+        .map(|_| {
+            state = state
+                .wrapping_mul(6364136223846793005)
+                .wrapping_add(1442695040888963407);
+            (state >> 33) as u8
+        })
+        .collect()
+}
+
+async fn chunks(op: &Operator) -> anyhow::Result<usize> {
+    let entries = op.list_with(".chunks/").recursive(true).await?;
+
+    Ok(entries.iter().filter(|e| e.metadata().is_file()).count())
+}
+
+#[tokio::test]
+async fn identical_content_is_stored_once() -> anyhow::Result<()> {
+    let fixture = TestFixture::new()?;
+    let storage = DedupStorage::new(Arc::new(fixture.base.clone()));
+
+    let data = content(1024 * 1024, 7);
+
+    storage.write("a.bin", data.clone()).await?;
+    let stored = chunks(&fixture.base).await?;
+    assert!(stored > 1);
+
+    storage.write("dir/b.bin", data.clone()).await?;
+    assert_eq!(chunks(&fixture.base).await?, stored);
+
+    assert_eq!(
+        storage.stat("dir/b.bin").await?.content_length(),
+        data.len() as u64
+    );
+    assert_eq!(
+        storage.read("dir/b.bin", 100_000..400_000).await?,
+        &data[100_000..400_000]
+    );
+
+    let names: Vec<String> = storage
+        .list("/")
+        .await?
+        .iter()
+        .map(|e| e.name().to_owned())
+        .collect();
+    assert!(!names.iter().any(|n| n.starts_with(".chunks")));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn append_extends_manifest() -> anyhow::Result<()> {
+    let fixture = TestFixture::new()?;
+    let storage = DedupStorage::new(Arc::new(fixture.base.clone()));
+
+    let data = content(300 * 1024, 3);
+
+    storage.write("a.bin", data[..200 * 1024].to_vec()).await?;
+    storage.append("a.bin", data[200 * 1024..].to_vec()).await?;
+
+    assert_eq!(
+        storage.stat("a.bin").await?.content_length(),
+        data.len() as u64
+    );
+    assert_eq!(storage.read("a.bin", 0..data.len() as u64).await?, data);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn unreferenced_chunks_are_collected() -> anyhow::Result<()> {
+    let fixture = TestFixture::new()?;
+    let storage = DedupStorage::new(Arc::new(fixture.base.clone()));
+
+    let data = content(300 * 1024, 5);
+
+    storage.write("a.bin", data[..200 * 1024].to_vec()).await?;
+    storage.write("b.bin", content(100 * 1024, 9)).await?;
+    storage.append("a.bin", data[200 * 1024..].to_vec()).await?;
+    storage.delete("b.bin").await?;
+
+    let stored = chunks(&fixture.base).await?;
+    let collected = storage.collect().await;
+    assert!(collected > 0);
+    assert_eq!(chunks(&fixture.base).await?, stored - collected);
+
+    assert_eq!(storage.read("a.bin", 0..data.len() as u64).await?, data);
+    assert_eq!(storage.collect().await, 0);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn chunk_store_is_hidden() -> anyhow::Result<()> {
+    let fixture = TestFixture::new()?;
+    let storage = DedupStorage::new(Arc::new(fixture.base.clone()));
+
+    storage.write("a.bin", content(1024, 1)).await?;
+
+    assert!(storage.stat(".chunks").await.is_err());
+    assert!(storage.list(".chunks/").await.is_err());
+    assert!(storage.write(".chunks/x", b"x".to_vec()).await.is_err());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn appends_rewrite_the_manifest_once() -> anyhow::Result<()> {
+    let fixture = TestFixture::new()?;
+    let storage = DedupStorage::new(Arc::new(fixture.base.clone()));
+
+    let data = content(300 * 1024, 11);
+
+    storage.write("a.bin", data[..100 * 1024].to_vec()).await?;
+    let manifest = fixture.base.read("a.bin").await?.to_vec();
+
+    for part in data[100 * 1024..].chunks(10 * 1024) {
+        storage.append("a.bin", part.to_vec()).await?;
+    }
+
+    // Appends are read from memory until flushed.
+    assert_eq!(fixture.base.read("a.bin").await?.to_vec(), manifest);
+    assert_eq!(
+        storage.stat("a.bin").await?.content_length(),
+        data.len() as u64
+    );
+    assert_eq!(
+        storage.read("a.bin", 50_000..250_000).await?,
+        &data[50_000..250_000]
+    );
+
+    storage.flush_all().await;
+
+    assert_ne!(fixture.base.read("a.bin").await?.to_vec(), manifest);
+    assert_eq!(storage.read("a.bin", 0..data.len() as u64).await?, data);
+
+    Ok(())
+}
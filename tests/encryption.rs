@@ -0,0 +1,140 @@
+mod common;
+
+use std::sync::Arc;
+
+use common::TestFixture;
+use nfsserve::{nfs::sattr3, vfs::NFSFileSystem};
+use opendal_mount::{
+    storage::{EncryptedStorage, Storage},
+    OpendalFs,
+};
+
+fn encrypted_fs(fixture: &TestFixture) -> anyhow::Result<OpendalFs> {
+    let key_file = fixture.root.path().join("key");
+    std::fs::write(&key_file, "42".repeat(32))?;
+
+    let storage = EncryptedStorage::from_key_file(Arc::new(fixture.base.clone()), &key_file)?;
+
+    Ok(OpendalFs::with_storage(Arc::new(storage)))
+}
+
+#[tokio::test]
+async fn content_is_encrypted_at_rest() -> anyhow::Result<()> {
+    let fixture = TestFixture::new()?;
+    let fs = encrypted_fs(&fixture)?;
+
+    let (id, _) = fs
+        .create(
+            fs.root_dir(),
+            &b"secret.txt".to_vec().into(),
+            sattr3::default(),
+        )
+        .await
+        .unwrap();
+    let attr = fs.write(id, 0, b"top secret").await.unwrap();
+
+    assert_eq!(attr.size, 10);
+
+    let stored = fixture.base.read("secret.txt").await?.to_vec();
+    assert!(!stored.windows(10).any(|w| w == b"top secret"));
+
+    let (data, _) = fs.read(id, 4, 6).await.unwrap();
+    assert_eq!(data, b"secret");
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn ranged_reads_span_chunks() -> anyhow::Result<()> {
+    let fixture = TestFixture::new()?;
+    let fs = encrypted_fs(&fixture)?;
+
+    let content: Vec<u8> = (0..200_000u32).map(|i| (i % 251) as u8).collect();
+
+    let (id, _) = fs
+        .create(
+            fs.root_dir(),
+            &b"data.bin".to_vec().into(),
+            sattr3::default(),
+        )
+        .await
+        .unwrap();
+    fs.write(id, 0, &content[..100_000]).await.unwrap();
+    let attr = fs.write(id, 100_000, &content[100_000..]).await.unwrap();
+
+    assert_eq!(attr.size, content.len() as u64);
+
+    let (data, _) = fs.read(id, 65_000, 70_000).await.unwrap();
+    assert_eq!(data, &content[65_000..135_000]);
+
+    Ok(())
+}
+
+const HEADER_LEN: usize = 24;
+const STRIDE: usize = 64 * 1024 + 28;
+
+fn encrypted_storage(fixture: &TestFixture) -> anyhow::Result<EncryptedStorage> {
+    Ok(EncryptedStorage::new(
+        Arc::new(fixture.base.clone()),
+        &[42; 32],
+    )?)
+}
+
+#[tokio::test]
+async fn chunks_are_bound_to_their_object() -> anyhow::Result<()> {
+    let fixture = TestFixture::new()?;
+    let storage = encrypted_storage(&fixture)?;
+
+    storage.write("a.bin", vec![b'a'; 100_000]).await?;
+    storage.write("b.bin", vec![b'b'; 100_000]).await?;
+
+    let a = fixture.base.read("a.bin").await?.to_vec();
+    let mut b = fixture.base.read("b.bin").await?.to_vec();
+    b[HEADER_LEN..HEADER_LEN + STRIDE].copy_from_slice(&a[HEADER_LEN..HEADER_LEN + STRIDE]);
+    fixture.base.write("b.bin", b).await?;
+
+    assert!(storage.read("b.bin", 0..10).await.is_err());
+    assert_eq!(storage.read("a.bin", 0..10).await?, vec![b'a'; 10]);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn truncated_objects_fail_to_decrypt() -> anyhow::Result<()> {
+    let fixture = TestFixture::new()?;
+    let storage = encrypted_storage(&fixture)?;
+
+    storage.write("a.bin", vec![b'a'; 200_000]).await?;
+
+    let stored = fixture.base.read("a.bin").await?.to_vec();
+    fixture
+        .base
+        .write("a.bin", stored[..HEADER_LEN + 2 * STRIDE].to_vec())
+        .await?;
+
+    assert!(storage.read("a.bin", 65_536..65_546).await.is_err());
+
+    fixture
+        .base
+        .write("a.bin", stored[..HEADER_LEN].to_vec())
+        .await?;
+    assert!(storage.read("a.bin", 0..10).await.is_err());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn appends_after_full_chunks() -> anyhow::Result<()> {
+    let fixture = TestFixture::new()?;
+    let storage = encrypted_storage(&fixture)?;
+
+    storage.write("a.bin", vec![b'a'; 64 * 1024]).await?;
+    storage.append("a.bin", vec![b'b'; 10]).await?;
+
+    let content = storage.read("a.bin", 0..64 * 1024 + 10).await?;
+    assert_eq!(content.len(), 64 * 1024 + 10);
+    assert!(content[..64 * 1024].iter().all(|b| *b == b'a'));
+    assert_eq!(&content[64 * 1024..], &[b'b'; 10]);
+
+    Ok(())
+}
@@ -0,0 +1,71 @@
+mod common;
+
+use common::TestFixture;
+use nfsserve::{
+    nfs::{nfsstat3, sattr3},
+    vfs::NFSFileSystem,
+};
+use opendal_mount::OpendalFs;
+
+#[tokio::test]
+async fn replaced_file_handles_are_stale() -> anyhow::Result<()> {
+    let fixture = TestFixture::new()?;
+    let fs = OpendalFs::new(fixture.base.clone());
+    let name = b"a.txt".to_vec().into();
+
+    let (id, _) = fs
+        .create(fs.root_dir(), &name, sattr3::default())
+        .await
+        .unwrap();
+    fs.write(id, 0, b"old").await.unwrap();
+    let old = fs.id_to_fh(id);
+    assert!(matches!(fs.fh_to_id(&old), Ok(i) if i == id));
+
+    fs.remove(fs.root_dir(), &name).await.unwrap();
+
+    let (new_id, _) = fs
+        .create(fs.root_dir(), &name, sattr3::default())
+        .await
+        .unwrap();
+    fs.write(new_id, 0, b"new").await.unwrap();
+    assert_eq!(new_id, id);
+
+    assert!(matches!(fs.fh_to_id(&old), Err(nfsstat3::NFS3ERR_STALE)));
+    assert!(matches!(fs.fh_to_id(&fs.id_to_fh(new_id)), Ok(i) if i == new_id));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn removed_file_handles_stay_stale_after_eviction() -> anyhow::Result<()> {
+    let fixture = TestFixture::new()?;
+    for i in 0..10 {
+        fixture
+            .base
+            .write(&format!("{}.txt", i), format!("file {}", i))
+            .await?;
+    }
+
+    let fs = OpendalFs::new(fixture.base.clone()).with_inode_capacity(3);
+    let name = b"a.txt".to_vec().into();
+
+    let (id, _) = fs
+        .create(fs.root_dir(), &name, sattr3::default())
+        .await
+        .unwrap();
+    let old = fs.id_to_fh(id);
+    fs.remove(fs.root_dir(), &name).await.unwrap();
+
+    // Listing the root fills the table with other paths.
+    fs.readdir(fs.root_dir(), 0, 100).await.unwrap();
+
+    let (new_id, _) = fs
+        .create(fs.root_dir(), &name, sattr3::default())
+        .await
+        .unwrap();
+    assert_eq!(new_id, id);
+
+    assert!(matches!(fs.fh_to_id(&old), Err(nfsstat3::NFS3ERR_STALE)));
+
+    Ok(())
+}
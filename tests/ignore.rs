@@ -0,0 +1,57 @@
+mod common;
+
+use common::TestFixture;
+use globset::{Glob, GlobSetBuilder};
+use nfsserve::{nfs::sattr3, vfs::NFSFileSystem};
+use opendal_mount::OpendalFs;
+
+fn ignoring_fs(fixture: &TestFixture) -> anyhow::Result<OpendalFs> {
+    let mut patterns = GlobSetBuilder::new();
+    patterns.add(Glob::new(".DS_Store")?);
+    patterns.add(Glob::new("._*")?);
+
+    Ok(OpendalFs::new(fixture.base.clone()).with_ignored(patterns.build()?)?)
+}
+
+#[tokio::test]
+async fn ignored_writes_never_reach_operator() -> anyhow::Result<()> {
+    let fixture = TestFixture::new()?;
+    let fs = ignoring_fs(&fixture)?;
+
+    let (id, _) = fs
+        .create(
+            fs.root_dir(),
+            &b"._a.txt".to_vec().into(),
+            sattr3::default(),
+        )
+        .await
+        .unwrap();
+    fs.write(id, 0, b"resource fork").await.unwrap();
+
+    let (data, _) = fs.read(id, 0, 100).await.unwrap();
+    assert_eq!(data, b"resource fork");
+
+    assert!(!fixture.base.exists("._a.txt").await?);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn ignored_objects_are_hidden() -> anyhow::Result<()> {
+    let fixture = TestFixture::new()?;
+    fixture.base.write(".DS_Store", "junk").await?;
+    fixture.base.write("a.txt", "hello").await?;
+
+    let fs = ignoring_fs(&fixture)?;
+
+    let listing = fs.readdir(fs.root_dir(), 0, 100).await.unwrap();
+    let names: Vec<String> = listing
+        .entries
+        .iter()
+        .map(|e| String::from_utf8_lossy(&e.name).into_owned())
+        .collect();
+
+    assert_eq!(names, vec!["a.txt"]);
+
+    Ok(())
+}
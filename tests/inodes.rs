@@ -0,0 +1,237 @@
+mod common;
+
+use common::TestFixture;
+use nfsserve::{
+    nfs::{nfsstat3, sattr3},
+    vfs::NFSFileSystem,
+};
+use opendal_mount::OpendalFs;
+
+#[tokio::test]
+async fn evicted_handles_stay_valid() -> anyhow::Result<()> {
+    let fixture = TestFixture::new()?;
+    for i in 0..10 {
+        fixture
+            .base
+            .write(&format!("dir/{}.txt", i), format!("file {}", i))
+            .await?;
+    }
+
+    let fs = OpendalFs::new(fixture.base.clone()).with_inode_capacity(3);
+
+    let dir = fs
+        .lookup(fs.root_dir(), &b"dir".to_vec().into())
+        .await
+        .unwrap();
+    let first = fs.lookup(dir, &b"0.txt".to_vec().into()).await.unwrap();
+
+    // Listing the directory evicts the first file from the table.
+    let listing = fs.readdir(dir, 0, 100).await.unwrap();
+    assert_eq!(listing.entries.len(), 10);
+
+    let (data, _) = fs.read(first, 0, 100).await.unwrap();
+    assert_eq!(data, b"file 0");
+
+    assert_eq!(
+        fs.lookup(dir, &b"0.txt".to_vec().into()).await.unwrap(),
+        first
+    );
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn renamed_handles_survive_eviction() -> anyhow::Result<()> {
+    let fixture = TestFixture::new()?;
+    for i in 0..10 {
+        fixture
+            .base
+            .write(&format!("dir/{}.txt", i), format!("file {}", i))
+            .await?;
+    }
+
+    let fs = OpendalFs::new(fixture.base.clone()).with_inode_capacity(3);
+
+    let dir = fs
+        .lookup(fs.root_dir(), &b"dir".to_vec().into())
+        .await
+        .unwrap();
+    let first = fs.lookup(dir, &b"0.txt".to_vec().into()).await.unwrap();
+
+    fs.rename(
+        dir,
+        &b"0.txt".to_vec().into(),
+        dir,
+        &b"renamed.txt".to_vec().into(),
+    )
+    .await
+    .unwrap();
+
+    let listing = fs.readdir(dir, 0, 100).await.unwrap();
+    let renamed = listing
+        .entries
+        .iter()
+        .find(|e| e.name.0 == b"renamed.txt")
+        .unwrap();
+    assert_eq!(renamed.fileid, first);
+
+    let (data, _) = fs.read(first, 0, 100).await.unwrap();
+    assert_eq!(data, b"file 0");
+
+    assert_eq!(
+        fs.lookup(dir, &b"renamed.txt".to_vec().into())
+            .await
+            .unwrap(),
+        first
+    );
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn renamed_directories_keep_child_handles() -> anyhow::Result<()> {
+    let fixture = TestFixture::new()?;
+    fixture.base.write("dir/a.txt", "hello").await?;
+
+    let fs = OpendalFs::new(fixture.base.clone());
+
+    let dir = fs
+        .lookup(fs.root_dir(), &b"dir".to_vec().into())
+        .await
+        .unwrap();
+    let child = fs.lookup(dir, &b"a.txt".to_vec().into()).await.unwrap();
+
+    fs.rename(
+        fs.root_dir(),
+        &b"dir".to_vec().into(),
+        fs.root_dir(),
+        &b"moved".to_vec().into(),
+    )
+    .await
+    .unwrap();
+
+    let (data, _) = fs.read(child, 0, 100).await.unwrap();
+    assert_eq!(data, b"hello");
+
+    let moved = fs
+        .lookup(fs.root_dir(), &b"moved".to_vec().into())
+        .await
+        .unwrap();
+    assert_eq!(moved, dir);
+    assert_eq!(
+        fs.lookup(moved, &b"a.txt".to_vec().into()).await.unwrap(),
+        child
+    );
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn unknown_handles_are_stale() -> anyhow::Result<()> {
+    let fixture = TestFixture::new()?;
+    fixture.base.write("a.txt", "hello").await?;
+
+    let fs = OpendalFs::new(fixture.base.clone());
+
+    assert!(matches!(
+        fs.getattr(0xdead_beef).await,
+        Err(nfsstat3::NFS3ERR_STALE)
+    ));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn inodes_are_stable() -> anyhow::Result<()> {
+    let fixture = TestFixture::new()?;
+    fixture.base.write("dir/a.txt", "hello").await?;
+
+    let fs = OpendalFs::new(fixture.base.clone());
+
+    let dir = fs
+        .lookup(fs.root_dir(), &b"dir".to_vec().into())
+        .await
+        .unwrap();
+
+    // Handles given out by earlier builds of the server are still valid.
+    assert_eq!(
+        fs.lookup(dir, &b"a.txt".to_vec().into()).await.unwrap(),
+        0x3eb2_72b4_9a9b
+    );
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn evicted_handles_are_searched_in_recent_directories() -> anyhow::Result<()> {
+    let fixture = TestFixture::new()?;
+    fixture.base.write("far/f.txt", "far").await?;
+    for i in 0..71 {
+        fixture.base.write(&format!("d{}/x.txt", i), "x").await?;
+    }
+
+    let fs = OpendalFs::new(fixture.base.clone()).with_inode_capacity(72);
+
+    let far = fs
+        .lookup(fs.root_dir(), &b"far".to_vec().into())
+        .await
+        .unwrap();
+    let file = fs.lookup(far, &b"f.txt".to_vec().into()).await.unwrap();
+    fs.getattr(far).await.unwrap();
+
+    // The file is evicted first, then its directory falls behind the 64
+    // most recently used ones.
+    for i in 0..71 {
+        fs.lookup(fs.root_dir(), &format!("d{}", i).into_bytes().into())
+            .await
+            .unwrap();
+    }
+
+    assert!(matches!(
+        fs.getattr(file).await,
+        Err(nfsstat3::NFS3ERR_STALE)
+    ));
+
+    // Found again once its directory is used.
+    fs.getattr(far).await.unwrap();
+    let (data, _) = fs.read(file, 0, 100).await.unwrap();
+    assert_eq!(data, b"far");
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn removed_handles_stay_stale_once_forgotten() -> anyhow::Result<()> {
+    let fixture = TestFixture::new()?;
+    fixture.base.write("a.txt", "old").await?;
+
+    let fs = OpendalFs::new(fixture.base.clone()).with_inode_capacity(3);
+
+    let id = fs
+        .lookup(fs.root_dir(), &b"a.txt".to_vec().into())
+        .await
+        .unwrap();
+    let fh = fs.id_to_fh(id);
+    fs.remove(fs.root_dir(), &b"a.txt".to_vec().into())
+        .await
+        .unwrap();
+
+    // More removals than generations kept.
+    for i in 0..10 {
+        let name = format!("{}.txt", i).into_bytes();
+        fs.create(fs.root_dir(), &name.clone().into(), sattr3::default())
+            .await
+            .unwrap();
+        fs.remove(fs.root_dir(), &name.into()).await.unwrap();
+    }
+
+    let (id, _) = fs
+        .create(fs.root_dir(), &b"a.txt".to_vec().into(), sattr3::default())
+        .await
+        .unwrap();
+
+    assert!(matches!(fs.fh_to_id(&fh), Err(nfsstat3::NFS3ERR_STALE)));
+    assert_eq!(fs.fh_to_id(&fs.id_to_fh(id)).unwrap(), id);
+
+    Ok(())
+}
@@ -0,0 +1,232 @@
+mod common;
+
+use std::sync::Arc;
+
+use common::TestFixture;
+use globset::{Glob, GlobSetBuilder};
+use nfsserve::{nfs::sattr3, vfs::NFSFileSystem};
+use opendal::{
+    services::{Fs, Memory},
+    Operator,
+};
+use opendal_mount::{schema::MountOptions, storage::ScratchStorage, MultiplexedFs, OpendalFs};
+
+fn local_only_fs(fixture: &TestFixture) -> anyhow::Result<(Operator, OpendalFs)> {
+    let scratch_root = fixture.root.path().join("scratch");
+    let scratch = Operator::new(Fs::default().root(scratch_root.to_str().unwrap()))?.finish();
+
+    let mut patterns = GlobSetBuilder::new();
+    patterns.add(Glob::new("*.swp")?);
+    patterns.add(Glob::new("~$*")?);
+
+    let fs = OpendalFs::new(fixture.base.clone())
+        .with_local_only(patterns.build()?, Arc::new(scratch.clone()));
+
+    Ok((scratch, fs))
+}
+
+#[tokio::test]
+async fn temp_files_stay_local() -> anyhow::Result<()> {
+    let fixture = TestFixture::new()?;
+    let (scratch, fs) = local_only_fs(&fixture)?;
+
+    let (id, _) = fs
+        .create(
+            fs.root_dir(),
+            &b".a.txt.swp".to_vec().into(),
+            sattr3::default(),
+        )
+        .await
+        .unwrap();
+    fs.write(id, 0, b"draft").await.unwrap();
+
+    assert!(!fixture.base.exists(".a.txt.swp").await?);
+    assert_eq!(scratch.read(".a.txt.swp").await?.to_vec(), b"draft");
+
+    let listing = fs.readdir(fs.root_dir(), 0, 100).await.unwrap();
+    assert!(listing
+        .entries
+        .iter()
+        .any(|e| e.name.0.as_slice() == b".a.txt.swp"));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn rename_uploads() -> anyhow::Result<()> {
+    let fixture = TestFixture::new()?;
+    let (scratch, fs) = local_only_fs(&fixture)?;
+
+    let (id, _) = fs
+        .create(
+            fs.root_dir(),
+            &b"~$report.docx".to_vec().into(),
+            sattr3::default(),
+        )
+        .await
+        .unwrap();
+    fs.write(id, 0, b"report").await.unwrap();
+
+    fs.rename(
+        fs.root_dir(),
+        &b"~$report.docx".to_vec().into(),
+        fs.root_dir(),
+        &b"report.docx".to_vec().into(),
+    )
+    .await
+    .unwrap();
+
+    assert_eq!(fixture.base.read("report.docx").await?.to_vec(), b"report");
+    assert!(!scratch.exists("~$report.docx").await?);
+
+    let (data, _) = fs.read(id, 0, 100).await.unwrap();
+    assert_eq!(data, b"report");
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn scratch_size_is_bounded() -> anyhow::Result<()> {
+    let fixture = TestFixture::new()?;
+    let scratch_root = fixture.root.path().join("scratch");
+    let scratch = Operator::new(Fs::default().root(scratch_root.to_str().unwrap()))?.finish();
+
+    let mut patterns = GlobSetBuilder::new();
+    patterns.add(Glob::new("*.swp")?);
+
+    let storage = ScratchStorage::new(
+        Arc::new(fixture.base.clone()),
+        Arc::new(scratch),
+        patterns.build()?,
+    )
+    .with_max_bytes(8);
+
+    storage.write("a.swp", b"12345".to_vec()).await?;
+    assert!(storage.append("a.swp", b"6789".to_vec()).await.is_err());
+    assert!(storage.write("b.swp", b"6789".to_vec()).await.is_err());
+
+    storage.delete("a.swp").await?;
+    storage.write("b.swp", b"6789".to_vec()).await?;
+
+    // Files reaching the operator are not bounded.
+    storage.write("c.txt", b"123456789".to_vec()).await?;
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn renamed_files_keep_their_size() -> anyhow::Result<()> {
+    let fixture = TestFixture::new()?;
+    let scratch_root = fixture.root.path().join("scratch");
+    let scratch = Operator::new(Fs::default().root(scratch_root.to_str().unwrap()))?.finish();
+
+    let mut patterns = GlobSetBuilder::new();
+    patterns.add(Glob::new("*.swp")?);
+
+    let storage = ScratchStorage::new(
+        Arc::new(fixture.base.clone()),
+        Arc::new(scratch.clone()),
+        patterns.build()?,
+    )
+    .with_max_bytes(8);
+
+    // Written behind the back of the storage, its size is not known.
+    scratch.write("a.swp", "1").await?;
+    storage.write("b.swp", b"12345".to_vec()).await?;
+
+    storage.rename("a.swp", "b.swp").await?;
+    storage.write("c.swp", b"12345".to_vec()).await?;
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn local_files_move_with_their_directory() -> anyhow::Result<()> {
+    let fixture = TestFixture::new()?;
+    let (scratch, fs) = local_only_fs(&fixture)?;
+
+    let (dir, _) = fs
+        .mkdir(fs.root_dir(), &b"dir".to_vec().into())
+        .await
+        .unwrap();
+    let (id, _) = fs
+        .create(dir, &b".a.txt.swp".to_vec().into(), sattr3::default())
+        .await
+        .unwrap();
+    fs.write(id, 0, b"draft").await.unwrap();
+
+    fs.rename(
+        fs.root_dir(),
+        &b"dir".to_vec().into(),
+        fs.root_dir(),
+        &b"moved".to_vec().into(),
+    )
+    .await
+    .unwrap();
+
+    assert!(!scratch.exists("dir/.a.txt.swp").await?);
+    assert_eq!(scratch.read("moved/.a.txt.swp").await?.to_vec(), b"draft");
+
+    let id = fs.path_to_id(b"moved/.a.txt.swp").await.unwrap();
+    assert_eq!(fs.read(id, 0, 10).await.unwrap().0, b"draft");
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn stale_scratch_is_cleaned() -> anyhow::Result<()> {
+    let stale = std::env::temp_dir()
+        .join("opendal-mount")
+        .join(format!("stale-{}", std::process::id()));
+    std::fs::create_dir_all(stale.join("mount"))?;
+    std::fs::write(stale.join("mount/.a.swp"), "draft")?;
+
+    let fs = MultiplexedFs::new("127.0.0.1", 0).with_root_mount("/tmp/cloud");
+    let options = MountOptions {
+        local_only: vec!["*.swp".to_owned()],
+        ..Default::default()
+    };
+    fs.mount_operator("m", Operator::new(Memory::default())?.finish(), options)
+        .await?;
+
+    let dir = fs.path_to_id(b"/m").await.unwrap();
+    let (id, _) = fs
+        .create(dir, &b".b.swp".to_vec().into(), sattr3::default())
+        .await
+        .unwrap();
+    fs.write(id, 0, b"draft").await.unwrap();
+
+    MultiplexedFs::clean_scratch();
+    assert!(!stale.exists());
+
+    // The scratch files of running servers are kept.
+    assert_eq!(fs.read(id, 0, 10).await.unwrap().0, b"draft");
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn matching_objects_are_hidden() -> anyhow::Result<()> {
+    let fixture = TestFixture::new()?;
+    fixture.base.write(".old.txt.swp", "stale").await?;
+    fixture.base.write("a.txt", "hello").await?;
+
+    let (_, fs) = local_only_fs(&fixture)?;
+
+    // Listed, they could not be looked up, as their name leads to the
+    // scratch storage.
+    let listing = fs.readdir(fs.root_dir(), 0, 100).await.unwrap();
+    let names: Vec<&[u8]> = listing
+        .entries
+        .iter()
+        .map(|e| e.name.0.as_slice())
+        .collect();
+    assert_eq!(names, [b"a.txt".as_slice()]);
+
+    assert!(fs
+        .lookup(fs.root_dir(), &b".old.txt.swp".to_vec().into())
+        .await
+        .is_err());
+
+    Ok(())
+}
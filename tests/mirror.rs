@@ -0,0 +1,233 @@
+mod common;
+
+use std::{ops::Range, sync::Arc};
+
+use async_trait::async_trait;
+use common::TestFixture;
+use opendal::{services::Fs, ErrorKind, Metadata, Operator, OperatorInfo};
+use opendal_mount::{
+    schema::MirrorPolicy,
+    storage::{Entry, MirrorStorage, Storage, WriteMetadata},
+};
+use tokio::sync::Semaphore;
+
+fn secondary(fixture: &TestFixture, name: &str) -> anyhow::Result<Operator> {
+    let root = fixture.root.path().join(name);
+
+    Ok(Operator::new(Fs::default().root(root.to_str().unwrap()))?.finish())
+}
+
+/// Storage whose deletions wait for a permit.
+struct Gated {
+    inner: Operator,
+    deletes: Arc<Semaphore>,
+}
+
+#[async_trait]
+impl Storage for Gated {
+    fn info(&self) -> OperatorInfo {
+        self.inner.info()
+    }
+
+    async fn stat(&self, path: &str) -> opendal::Result<Metadata> {
+        Storage::stat(&self.inner, path).await
+    }
+
+    async fn read(&self, path: &str, range: Range<u64>) -> opendal::Result<Vec<u8>> {
+        Storage::read(&self.inner, path, range).await
+    }
+
+    async fn write_with_metadata(
+        &self,
+        path: &str,
+        data: Vec<u8>,
+        metadata: WriteMetadata,
+    ) -> opendal::Result<()> {
+        self.inner.write_with_metadata(path, data, metadata).await
+    }
+
+    async fn append(&self, path: &str, data: Vec<u8>) -> opendal::Result<()> {
+        Storage::append(&self.inner, path, data).await
+    }
+
+    async fn list(&self, path: &str) -> opendal::Result<Vec<Entry>> {
+        Storage::list(&self.inner, path).await
+    }
+
+    async fn create_dir(&self, path: &str) -> opendal::Result<()> {
+        Storage::create_dir(&self.inner, path).await
+    }
+
+    async fn delete(&self, path: &str) -> opendal::Result<()> {
+        self.deletes.acquire().await.unwrap().forget();
+
+        Storage::delete(&self.inner, path).await
+    }
+}
+
+#[tokio::test]
+async fn sync_writes_both() -> anyhow::Result<()> {
+    let fixture = TestFixture::new()?;
+    let secondary = secondary(&fixture, "secondary")?;
+
+    let mirror = MirrorStorage::new(
+        Arc::new(fixture.base.clone()),
+        Arc::new(secondary.clone()),
+        MirrorPolicy::Sync,
+    );
+
+    mirror.write("a.txt", b"hello".to_vec()).await?;
+    mirror.append("a.txt", b" world".to_vec()).await?;
+
+    assert_eq!(fixture.base.read("a.txt").await?.to_vec(), b"hello world");
+    assert_eq!(secondary.read("a.txt").await?.to_vec(), b"hello world");
+    assert!(mirror.divergences().await.is_empty());
+
+    mirror.delete("a.txt").await?;
+    assert!(!secondary.exists("a.txt").await?);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn failed_writes_are_repaired() -> anyhow::Result<()> {
+    let fixture = TestFixture::new()?;
+
+    let secondary = secondary(&fixture, "secondary")?;
+
+    // Writes to the secondary fail while a file is in place of its root.
+    let blocker = fixture.root.path().join("secondary");
+    std::fs::remove_dir(&blocker)?;
+    std::fs::write(&blocker, "")?;
+
+    let mirror = MirrorStorage::new(
+        Arc::new(fixture.base.clone()),
+        Arc::new(secondary.clone()),
+        MirrorPolicy::Sync,
+    );
+
+    mirror.write("a.txt", b"hello".to_vec()).await?;
+    assert_eq!(mirror.read("a.txt", 0..5).await?, b"hello");
+
+    let divergences = mirror.divergences().await;
+    assert_eq!(divergences.len(), 1);
+    assert_eq!(divergences[0].path, "a.txt");
+    assert_eq!(divergences[0].operation, "write");
+
+    std::fs::remove_file(&blocker)?;
+
+    assert_eq!(mirror.repair().await, 1);
+    assert!(mirror.divergences().await.is_empty());
+    assert_eq!(secondary.read("a.txt").await?.to_vec(), b"hello");
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn async_flush_waits_for_secondary() -> anyhow::Result<()> {
+    let fixture = TestFixture::new()?;
+    let secondary = secondary(&fixture, "secondary")?;
+
+    let mirror = MirrorStorage::new(
+        Arc::new(fixture.base.clone()),
+        Arc::new(secondary.clone()),
+        MirrorPolicy::Async,
+    );
+
+    for i in 0..10 {
+        mirror.write(&format!("{i}.txt"), vec![i; 4]).await?;
+    }
+    mirror.flush().await;
+
+    for i in 0..10 {
+        assert_eq!(
+            secondary.read(&format!("{i}.txt")).await?.to_vec(),
+            vec![i; 4]
+        );
+    }
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn missing_files_are_read_from_secondary() -> anyhow::Result<()> {
+    let fixture = TestFixture::new()?;
+    let secondary = secondary(&fixture, "secondary")?;
+
+    let mirror = MirrorStorage::new(
+        Arc::new(fixture.base.clone()),
+        Arc::new(secondary.clone()),
+        MirrorPolicy::Sync,
+    );
+
+    mirror.write("a.txt", b"hello".to_vec()).await?;
+
+    // Lost on the primary behind the back of the mirror.
+    fixture.base.delete("a.txt").await?;
+
+    assert_eq!(mirror.stat("a.txt").await?.content_length(), 5);
+    assert_eq!(mirror.read("a.txt", 0..5).await?, b"hello");
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn listing_merges_secondary() -> anyhow::Result<()> {
+    let fixture = TestFixture::new()?;
+    let secondary = secondary(&fixture, "secondary")?;
+
+    let mirror = MirrorStorage::new(
+        Arc::new(fixture.base.clone()),
+        Arc::new(secondary.clone()),
+        MirrorPolicy::Sync,
+    );
+
+    mirror.write("a.txt", b"hello".to_vec()).await?;
+    mirror.write("b.txt", b"bye".to_vec()).await?;
+
+    // Lost on the primary behind the back of the mirror.
+    fixture.base.delete("b.txt").await?;
+
+    let mut names: Vec<String> = mirror
+        .list("/")
+        .await?
+        .iter()
+        .map(|e| e.name().to_owned())
+        .collect();
+    names.sort();
+    assert_eq!(names, vec!["a.txt", "b.txt"]);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn queued_deletes_hide_secondary() -> anyhow::Result<()> {
+    let fixture = TestFixture::new()?;
+    let secondary = secondary(&fixture, "secondary")?;
+    let deletes = Arc::new(Semaphore::new(0));
+
+    let mirror = MirrorStorage::new(
+        Arc::new(fixture.base.clone()),
+        Arc::new(Gated {
+            inner: secondary.clone(),
+            deletes: deletes.clone(),
+        }),
+        MirrorPolicy::Async,
+    );
+
+    mirror.write("a.txt", b"hello".to_vec()).await?;
+    mirror.flush().await;
+
+    mirror.delete("a.txt").await?;
+    assert!(secondary.exists("a.txt").await?);
+
+    let err = mirror.stat("a.txt").await.unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::NotFound);
+    assert!(mirror.list("/").await?.is_empty());
+
+    deletes.add_permits(1);
+    mirror.flush().await;
+    assert!(!secondary.exists("a.txt").await?);
+
+    Ok(())
+}
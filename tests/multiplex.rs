@@ -0,0 +1,330 @@
+mod common;
+
+use async_graphql::{EmptySubscription, Schema};
+use nfsserve::{
+    nfs::{nfsstat3, nfstime3, sattr3},
+    vfs::{NFSFileSystem, VFSCapabilities},
+};
+use opendal::{services::Memory, Operator};
+use opendal_mount::{
+    errors::OpendalMountError,
+    schema::{MountOptions, Mutation, Query},
+    MultiplexedFs,
+};
+
+fn memory() -> anyhow::Result<Operator> {
+    Ok(Operator::new(Memory::default())?.finish())
+}
+
+#[tokio::test]
+async fn mounts_are_subdirectories_of_the_root() -> anyhow::Result<()> {
+    let fs = MultiplexedFs::new("127.0.0.1", 0).with_root_mount("/tmp/cloud");
+    let a = memory()?;
+    let b = memory()?;
+
+    fs.mount_operator("a", a.clone(), MountOptions::default())
+        .await?;
+    fs.mount_operator("b", b.clone(), MountOptions::default())
+        .await?;
+
+    let root = fs.readdir(fs.root_dir(), 0, 10).await.unwrap();
+    let names: Vec<&[u8]> = root.entries.iter().map(|e| e.name.0.as_slice()).collect();
+    assert_eq!(names, [b"a".as_slice(), b"b".as_slice()]);
+
+    // The same path in both mounts has distinct file ids.
+    let name = b"file.txt".to_vec().into();
+    let mut ids = vec![];
+    for entry in root.entries.iter() {
+        let (id, _) = fs
+            .create(entry.fileid, &name, sattr3::default())
+            .await
+            .unwrap();
+        fs.write(id, 0, &entry.name.0).await.unwrap();
+        ids.push(id);
+    }
+    assert_ne!(ids[0], ids[1]);
+
+    assert_eq!(a.read("file.txt").await?.to_vec(), b"a");
+    assert_eq!(b.read("file.txt").await?.to_vec(), b"b");
+
+    let id = fs.path_to_id(b"/b/file.txt").await.unwrap();
+    assert_eq!(id, ids[1]);
+    assert_eq!(fs.read(id, 0, 10).await.unwrap().0, b"b");
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn umount_forgets_the_mount() -> anyhow::Result<()> {
+    let fs = MultiplexedFs::new("127.0.0.1", 0).with_root_mount("/tmp/cloud");
+
+    let op = memory()?;
+    op.write("file.txt", "data").await?;
+    fs.mount_operator("a", op.clone(), MountOptions::default())
+        .await?;
+
+    assert!(matches!(
+        fs.mount_operator("a", op, MountOptions::default()).await,
+        Err(OpendalMountError::AlreadyMounted(_))
+    ));
+
+    let id = fs.path_to_id(b"/a/file.txt").await.unwrap();
+    let fh = fs.id_to_fh(id);
+
+    fs.umount("a").await?;
+
+    assert!(matches!(fs.fh_to_id(&fh), Err(nfsstat3::NFS3ERR_STALE)));
+    assert!(matches!(
+        fs.umount("a").await,
+        Err(OpendalMountError::NotMounted(_))
+    ));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn invalid_mount_names_are_rejected() -> anyhow::Result<()> {
+    let fs = MultiplexedFs::new("127.0.0.1", 0).with_root_mount("/tmp/cloud");
+
+    assert!(matches!(
+        fs.mount_operator("a/b", memory()?, MountOptions::default())
+            .await,
+        Err(OpendalMountError::InvalidMountName(_))
+    ));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn server_is_read_only_when_every_mount_is() -> anyhow::Result<()> {
+    let fs = MultiplexedFs::new("127.0.0.1", 0).with_root_mount("/tmp/cloud");
+    let read_only = MountOptions {
+        read_only: true,
+        ..Default::default()
+    };
+
+    fs.mount_operator("a", memory()?, read_only).await?;
+    assert!(matches!(fs.capabilities(), VFSCapabilities::ReadOnly));
+
+    fs.mount_operator("b", memory()?, MountOptions::default())
+        .await?;
+    assert!(matches!(fs.capabilities(), VFSCapabilities::ReadWrite));
+
+    fs.umount("b").await?;
+    assert!(matches!(fs.capabilities(), VFSCapabilities::ReadOnly));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn failed_mounts_are_rolled_back() -> anyhow::Result<()> {
+    let fs = MultiplexedFs::new("127.0.0.1", 0);
+
+    assert!(fs
+        .mount_operator(
+            "/nonexistent/opendal-mount",
+            memory()?,
+            MountOptions::default()
+        )
+        .await
+        .is_err());
+
+    assert!(fs.mounted_operators().await.is_empty());
+    assert!(matches!(
+        fs.umount("/nonexistent/opendal-mount").await,
+        Err(OpendalMountError::NotMounted(_))
+    ));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn umount_keeps_unrenamed_local_only_files() -> anyhow::Result<()> {
+    let fs = MultiplexedFs::new("127.0.0.1", 0).with_root_mount("/tmp/cloud");
+    let op = memory()?;
+    let options = MountOptions {
+        local_only: vec!["*.swp".to_owned()],
+        ..Default::default()
+    };
+
+    fs.mount_operator("a", op.clone(), options).await?;
+
+    let dir = fs.readdir(fs.root_dir(), 0, 10).await.unwrap().entries[0].fileid;
+    let (id, _) = fs
+        .create(dir, &b"a.swp".to_vec().into(), sattr3::default())
+        .await
+        .unwrap();
+    fs.write(id, 0, b"hello").await.unwrap();
+
+    assert!(matches!(
+        fs.umount("a").await,
+        Err(OpendalMountError::Busy(_))
+    ));
+
+    fs.rename(
+        dir,
+        &b"a.swp".to_vec().into(),
+        dir,
+        &b"a.txt".to_vec().into(),
+    )
+    .await
+    .unwrap();
+
+    fs.umount("a").await?;
+    assert_eq!(op.read("a.txt").await?.to_vec(), b"hello");
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn umount_mutation() -> anyhow::Result<()> {
+    let fs = MultiplexedFs::new("127.0.0.1", 0).with_root_mount("/tmp/cloud");
+    fs.mount_operator("a", memory()?, MountOptions::default())
+        .await?;
+
+    let schema = Schema::build(Query, Mutation, EmptySubscription)
+        .data(fs)
+        .finish();
+
+    let response = schema
+        .execute(r#"mutation { umount(mountPoint: "a") }"#)
+        .await;
+    assert!(response.errors.is_empty());
+    assert_eq!(
+        response.data.into_json()?,
+        serde_json::json!({ "umount": "a" })
+    );
+
+    let response = schema.execute("{ fs { id } }").await;
+    assert_eq!(response.data.into_json()?, serde_json::json!({ "fs": [] }));
+
+    let response = schema
+        .execute(r#"mutation { umount(mountPoint: "a") }"#)
+        .await;
+    assert!(!response.errors.is_empty());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn renames_across_mounts_are_refused() -> anyhow::Result<()> {
+    let fs = MultiplexedFs::new("127.0.0.1", 0).with_root_mount("/tmp/cloud");
+    let a = memory()?;
+    a.write("file.txt", "data").await?;
+
+    fs.mount_operator("a", a.clone(), MountOptions::default())
+        .await?;
+    fs.mount_operator("b", memory()?, MountOptions::default())
+        .await?;
+
+    let dir_a = fs.path_to_id(b"/a").await.unwrap();
+    let dir_b = fs.path_to_id(b"/b").await.unwrap();
+    let name = b"file.txt".to_vec().into();
+
+    assert!(matches!(
+        fs.rename(dir_a, &name, dir_b, &name).await,
+        Err(nfsstat3::NFS3ERR_XDEV)
+    ));
+
+    fs.rename(dir_a, &name, dir_a, &b"moved.txt".to_vec().into())
+        .await
+        .unwrap();
+    assert_eq!(a.read("moved.txt").await?.to_vec(), b"data");
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn handles_stay_stale_after_umount() -> anyhow::Result<()> {
+    let fs = MultiplexedFs::new("127.0.0.1", 0).with_root_mount("/tmp/cloud");
+
+    let a = memory()?;
+    a.write("file.txt", "a").await?;
+    fs.mount_operator("a", a, MountOptions::default()).await?;
+
+    let old = fs.path_to_id(b"/a/file.txt").await.unwrap();
+    let fh = fs.id_to_fh(old);
+
+    fs.umount("a").await?;
+
+    // The next mount gets another index, the same path resolving to
+    // another file id.
+    let b = memory()?;
+    b.write("file.txt", "b").await?;
+    fs.mount_operator("b", b, MountOptions::default()).await?;
+
+    assert!(matches!(fs.fh_to_id(&fh), Err(nfsstat3::NFS3ERR_STALE)));
+    assert!(fs.getattr(old).await.is_err());
+    assert_ne!(fs.path_to_id(b"/b/file.txt").await.unwrap(), old);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn root_listing_is_paged() -> anyhow::Result<()> {
+    let fs = MultiplexedFs::new("127.0.0.1", 0).with_root_mount("/tmp/cloud");
+    for name in ["a", "b", "c"] {
+        fs.mount_operator(name, memory()?, MountOptions::default())
+            .await?;
+    }
+
+    let first = fs.readdir(fs.root_dir(), 0, 2).await.unwrap();
+    assert_eq!(first.entries.len(), 2);
+    assert!(!first.end);
+
+    let last = first.entries[1].fileid;
+    let rest = fs.readdir(fs.root_dir(), last, 2).await.unwrap();
+    assert_eq!(rest.entries.len(), 1);
+    assert!(rest.end);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn only_mounts_are_exported() -> anyhow::Result<()> {
+    let fs = MultiplexedFs::new("127.0.0.1", 0).with_root_mount("/tmp/cloud");
+    fs.mount_operator("a", memory()?, MountOptions::default())
+        .await?;
+
+    let id = fs.path_to_id(b"/a").await.unwrap();
+    assert_eq!(
+        fs.lookup(fs.root_dir(), &b"a".to_vec().into())
+            .await
+            .unwrap(),
+        id
+    );
+    assert!(matches!(fs.path_to_id(b"/").await, Ok(id) if id == fs.root_dir()));
+    assert!(fs.fh_to_id(&fs.id_to_fh(fs.root_dir())).is_ok());
+
+    // Without a root mount, each mount is exported on its own.
+    let fs = MultiplexedFs::new("127.0.0.1", 0);
+
+    assert!(matches!(
+        fs.path_to_id(b"/").await,
+        Err(nfsstat3::NFS3ERR_NOENT)
+    ));
+    assert!(matches!(
+        fs.fh_to_id(&fs.id_to_fh(fs.root_dir())),
+        Err(nfsstat3::NFS3ERR_STALE)
+    ));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn root_changes_with_mounts() -> anyhow::Result<()> {
+    let fs = MultiplexedFs::new("127.0.0.1", 0).with_root_mount("/tmp/cloud");
+
+    let before = fs.getattr(fs.root_dir()).await.unwrap().mtime;
+    fs.mount_operator("a", memory()?, MountOptions::default())
+        .await?;
+    let mounted = fs.getattr(fs.root_dir()).await.unwrap().mtime;
+    fs.umount("a").await?;
+    let unmounted = fs.getattr(fs.root_dir()).await.unwrap().mtime;
+
+    let time = |t: nfstime3| (t.seconds, t.nseconds);
+    assert!(time(mounted) > time(before));
+    assert!(time(unmounted) > time(mounted));
+
+    Ok(())
+}
@@ -0,0 +1,189 @@
+mod common;
+
+use std::sync::Arc;
+
+use common::TestFixture;
+use nfsserve::{
+    nfs::{fileid3, nfsstat3, sattr3},
+    vfs::NFSFileSystem,
+};
+use opendal::{services::Fs, Operator};
+use opendal_mount::{storage::OverlayStorage, OpendalFs};
+
+struct OverlayFixture {
+    fixture: TestFixture,
+    upper: Operator,
+    fs: OpendalFs,
+}
+
+impl OverlayFixture {
+    async fn new() -> anyhow::Result<Self> {
+        let fixture = TestFixture::new()?;
+        fixture.base.write("a.txt", "hello").await?;
+        fixture.base.write("b.txt", "bye").await?;
+        fixture.base.write("dir/c.txt", "c").await?;
+
+        let upper_root = fixture.root.path().join("upper");
+        let upper = Operator::new(Fs::default().root(upper_root.to_str().unwrap()))?.finish();
+
+        let storage = OverlayStorage::new(Arc::new(fixture.base.clone()), Arc::new(upper.clone()));
+        let fs = OpendalFs::with_storage(Arc::new(storage));
+
+        Ok(Self { fixture, upper, fs })
+    }
+
+    async fn names(&self) -> Vec<String> {
+        let listing = self.fs.readdir(self.fs.root_dir(), 0, 100).await.unwrap();
+
+        let mut names: Vec<String> = listing
+            .entries
+            .iter()
+            .map(|e| String::from_utf8_lossy(&e.name).into_owned())
+            .collect();
+        names.sort();
+
+        names
+    }
+
+    async fn id(&self, name: &str) -> fileid3 {
+        self.fs.readdir(self.fs.root_dir(), 0, 100).await.unwrap();
+
+        self.fs
+            .lookup(self.fs.root_dir(), &name.as_bytes().to_vec().into())
+            .await
+            .unwrap()
+    }
+}
+
+#[tokio::test]
+async fn listing_merges_layers() -> anyhow::Result<()> {
+    let overlay = OverlayFixture::new().await?;
+    overlay.upper.write("d.txt", "d").await?;
+
+    assert_eq!(
+        overlay.names().await,
+        vec!["a.txt", "b.txt", "d.txt", "dir"]
+    );
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn write_copies_up() -> anyhow::Result<()> {
+    let overlay = OverlayFixture::new().await?;
+
+    let id = overlay.id("a.txt").await;
+    overlay.fs.write(id, 5, b" world").await.unwrap();
+
+    let (data, _) = overlay.fs.read(id, 0, 100).await.unwrap();
+    assert_eq!(data, b"hello world");
+
+    assert_eq!(overlay.upper.read("a.txt").await?.to_vec(), b"hello world");
+    assert_eq!(overlay.fixture.base.read("a.txt").await?.to_vec(), b"hello");
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn remove_leaves_whiteout() -> anyhow::Result<()> {
+    let overlay = OverlayFixture::new().await?;
+
+    overlay
+        .fs
+        .remove(overlay.fs.root_dir(), &b"b.txt".to_vec().into())
+        .await
+        .unwrap();
+
+    assert_eq!(overlay.names().await, vec!["a.txt", "dir"]);
+    assert!(overlay.upper.exists(".wh.b.txt").await?);
+    assert!(overlay.fixture.base.exists("b.txt").await?);
+
+    let (id, _) = overlay
+        .fs
+        .create(
+            overlay.fs.root_dir(),
+            &b"b.txt".to_vec().into(),
+            sattr3::default(),
+        )
+        .await
+        .unwrap();
+    overlay.fs.write(id, 0, b"new").await.unwrap();
+
+    assert_eq!(overlay.names().await, vec!["a.txt", "b.txt", "dir"]);
+    assert!(!overlay.upper.exists(".wh.b.txt").await?);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn removed_directory_hides_its_children() -> anyhow::Result<()> {
+    let overlay = OverlayFixture::new().await?;
+
+    let dir = overlay.id("dir").await;
+    let child = overlay
+        .fs
+        .lookup(dir, &b"c.txt".to_vec().into())
+        .await
+        .unwrap();
+    let fh = overlay.fs.id_to_fh(child);
+
+    let err = overlay
+        .fs
+        .remove(overlay.fs.root_dir(), &b"dir".to_vec().into())
+        .await
+        .unwrap_err();
+    assert!(matches!(err, nfsstat3::NFS3ERR_NOTEMPTY));
+
+    // As `rm -r` does, children first.
+    overlay
+        .fs
+        .remove(dir, &b"c.txt".to_vec().into())
+        .await
+        .unwrap();
+    overlay
+        .fs
+        .remove(overlay.fs.root_dir(), &b"dir".to_vec().into())
+        .await
+        .unwrap();
+
+    assert_eq!(overlay.names().await, vec!["a.txt", "b.txt"]);
+    assert!(overlay.fs.fh_to_id(&fh).is_err());
+    assert!(overlay.fs.path_to_id(b"dir/c.txt").await.is_err());
+    assert!(overlay.upper.exists(".wh.dir").await?);
+    assert!(!overlay.upper.exists("dir/").await?);
+    assert!(overlay.fixture.base.exists("dir/c.txt").await?);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn remade_directory_is_empty() -> anyhow::Result<()> {
+    let overlay = OverlayFixture::new().await?;
+
+    let dir = overlay.id("dir").await;
+    overlay
+        .fs
+        .remove(dir, &b"c.txt".to_vec().into())
+        .await
+        .unwrap();
+    overlay
+        .fs
+        .remove(overlay.fs.root_dir(), &b"dir".to_vec().into())
+        .await
+        .unwrap();
+
+    let (dir, _) = overlay
+        .fs
+        .mkdir(overlay.fs.root_dir(), &b"dir".to_vec().into())
+        .await
+        .unwrap();
+
+    assert_eq!(overlay.names().await, vec!["a.txt", "b.txt", "dir"]);
+
+    let listing = overlay.fs.readdir(dir, 0, 100).await.unwrap();
+    assert!(listing.entries.is_empty());
+    assert!(overlay.fs.path_to_id(b"dir/c.txt").await.is_err());
+    assert!(overlay.upper.exists("dir/.wh..opq").await?);
+
+    Ok(())
+}
@@ -0,0 +1,83 @@
+mod common;
+
+use common::TestFixture;
+use nfsserve::{
+    nfs::{nfsstat3, sattr3},
+    vfs::NFSFileSystem,
+};
+use opendal_mount::{OpendalFs, Quota};
+
+#[tokio::test]
+async fn byte_quota_rejects_writes() -> anyhow::Result<()> {
+    let fixture = TestFixture::new()?;
+    let fs = OpendalFs::new(fixture.base.clone()).with_quota(Quota::new(Some(4), None));
+
+    let (id, _) = fs
+        .create(fs.root_dir(), &b"a.txt".to_vec().into(), sattr3::default())
+        .await
+        .unwrap();
+
+    fs.write(id, 0, b"abcd").await.unwrap();
+    let err = fs.write(id, 4, b"e").await.unwrap_err();
+    assert_eq!(err, nfsstat3::NFS3ERR_DQUOT);
+
+    let usage = fs.quota().usage();
+    assert_eq!(usage.bytes_used, 4);
+    assert_eq!(usage.objects, 1);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn object_quota_rejects_creates() -> anyhow::Result<()> {
+    let fixture = TestFixture::new()?;
+    let fs = OpendalFs::new(fixture.base.clone()).with_quota(Quota::new(None, Some(1)));
+
+    fs.create(fs.root_dir(), &b"a.txt".to_vec().into(), sattr3::default())
+        .await
+        .unwrap();
+
+    // Creating an existing file again is not one more object.
+    fs.create(fs.root_dir(), &b"a.txt".to_vec().into(), sattr3::default())
+        .await
+        .unwrap();
+    assert_eq!(fs.quota().usage().objects, 1);
+
+    let err = fs
+        .create(fs.root_dir(), &b"b.txt".to_vec().into(), sattr3::default())
+        .await
+        .unwrap_err();
+    assert_eq!(err, nfsstat3::NFS3ERR_DQUOT);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn usage_follows_file_sizes() -> anyhow::Result<()> {
+    let fixture = TestFixture::new()?;
+    fixture.base.write("existing.txt", "abc").await?;
+
+    let fs = OpendalFs::new(fixture.base.clone()).with_quota(Quota::new(Some(8), None));
+    fs.measure_usage().await?;
+    assert_eq!(fs.quota().usage().bytes_used, 3);
+    assert_eq!(fs.quota().usage().objects, 1);
+
+    let (id, _) = fs
+        .create(fs.root_dir(), &b"a.txt".to_vec().into(), sattr3::default())
+        .await
+        .unwrap();
+
+    // Overwrites only count the difference in size.
+    fs.write(id, 0, b"abcd").await.unwrap();
+    fs.write(id, 0, b"ab").await.unwrap();
+    assert_eq!(fs.quota().usage().bytes_used, 5);
+
+    fs.remove(fs.root_dir(), &b"a.txt".to_vec().into())
+        .await
+        .unwrap();
+    let usage = fs.quota().usage();
+    assert_eq!(usage.bytes_used, 3);
+    assert_eq!(usage.objects, 1);
+
+    Ok(())
+}
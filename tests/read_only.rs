@@ -0,0 +1,43 @@
+mod common;
+
+use common::TestFixture;
+use nfsserve::{
+    nfs::{nfsstat3, sattr3},
+    vfs::{NFSFileSystem, VFSCapabilities},
+};
+use opendal_mount::OpendalFs;
+
+#[tokio::test]
+async fn read_only_mount_rejects_changes() -> anyhow::Result<()> {
+    let fixture = TestFixture::new()?;
+    fixture.base.write("a.txt", "hello").await?;
+
+    let fs = OpendalFs::new(fixture.base.clone()).with_read_only(true);
+    assert!(matches!(fs.capabilities(), VFSCapabilities::ReadOnly));
+
+    let err = fs
+        .create(fs.root_dir(), &b"b.txt".to_vec().into(), sattr3::default())
+        .await
+        .unwrap_err();
+    assert_eq!(err, nfsstat3::NFS3ERR_ROFS);
+
+    let err = fs
+        .mkdir(fs.root_dir(), &b"dir".to_vec().into())
+        .await
+        .unwrap_err();
+    assert_eq!(err, nfsstat3::NFS3ERR_ROFS);
+
+    let listing = fs.readdir(fs.root_dir(), 0, 10).await.unwrap();
+    assert_eq!(listing.entries.len(), 1);
+
+    let id = listing.entries[0].fileid;
+    assert_eq!(
+        fs.write(id, 0, b"bye").await.unwrap_err(),
+        nfsstat3::NFS3ERR_ROFS
+    );
+
+    let (data, _) = fs.read(id, 0, 5).await.unwrap();
+    assert_eq!(data, b"hello");
+
+    Ok(())
+}
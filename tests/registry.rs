@@ -0,0 +1,115 @@
+mod common;
+
+use std::collections::HashMap;
+
+use nfsserve::vfs::NFSFileSystem;
+use opendal_mount::{
+    schema::{MountOptions, OperatorInput},
+    MountEntry, MountSource, MultiplexedFs,
+};
+
+fn entry(mount_point: &str, service: &str) -> MountEntry {
+    MountEntry {
+        mount_point: mount_point.to_owned(),
+        source: MountSource::Operator(OperatorInput {
+            service: service.to_owned(),
+            parameters: HashMap::new(),
+        }),
+        options: MountOptions {
+            read_only: true,
+            ..Default::default()
+        },
+        index: None,
+    }
+}
+
+fn server(state_file: &std::path::Path) -> MultiplexedFs {
+    MultiplexedFs::new("127.0.0.1", 0)
+        .with_root_mount("/tmp/cloud")
+        .with_state_file(state_file)
+}
+
+#[tokio::test]
+async fn mounts_are_restored() -> anyhow::Result<()> {
+    let dir = tempfile::tempdir()?;
+    let state_file = dir.path().join("state.json");
+
+    let fs = server(&state_file);
+    fs.restore().await?;
+    fs.mount(entry("a", "memory")).await?;
+    fs.mount(entry("b", "memory")).await?;
+    fs.umount("b").await?;
+
+    let fs = server(&state_file);
+    fs.restore().await?;
+
+    let mounted = fs.mounted_operators().await;
+    assert_eq!(mounted.len(), 1);
+    assert_eq!(mounted[0].id, "a");
+    assert!(mounted[0].read_only);
+    assert!(fs.restore_failures().await.is_empty());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn failed_restores_are_reported_and_kept() -> anyhow::Result<()> {
+    let dir = tempfile::tempdir()?;
+    let state_file = dir.path().join("state.json");
+    std::fs::write(
+        &state_file,
+        serde_json::to_vec(&[entry("a", "memory"), entry("broken", "no-such-service")])?,
+    )?;
+
+    let fs = server(&state_file);
+    fs.restore().await?;
+
+    let failures = fs.restore_failures().await;
+    assert_eq!(failures.len(), 1);
+    assert_eq!(failures[0].mount_point, "broken");
+
+    // Still saved, to be retried on the next start.
+    let fs = server(&state_file);
+    fs.restore().await?;
+    assert_eq!(fs.restore_failures().await.len(), 1);
+
+    fs.umount("broken").await?;
+    let fs = server(&state_file);
+    fs.restore().await?;
+    assert!(fs.restore_failures().await.is_empty());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn restored_mounts_keep_their_handles() -> anyhow::Result<()> {
+    let dir = tempfile::tempdir()?;
+    let state_file = dir.path().join("state.json");
+    let data = dir.path().join("data");
+    std::fs::create_dir_all(&data)?;
+    std::fs::write(data.join("file.txt"), "data")?;
+
+    let mut b = entry("b", "fs");
+    if let MountSource::Operator(input) = &mut b.source {
+        input
+            .parameters
+            .insert("root".to_owned(), data.display().to_string());
+    }
+
+    let fs = server(&state_file);
+    fs.restore().await?;
+    fs.mount(entry("a", "memory")).await?;
+    fs.mount(b).await?;
+    fs.umount("a").await?;
+
+    let id = fs.path_to_id(b"/b/file.txt").await.unwrap();
+    let fh = fs.id_to_fh(id);
+
+    let fs = server(&state_file);
+    fs.restore().await?;
+
+    assert_eq!(fs.path_to_id(b"/b/file.txt").await.unwrap(), id);
+    assert!(matches!(fs.fh_to_id(&fh), Ok(restored) if restored == id));
+
+    Ok(())
+}
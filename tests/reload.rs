@@ -0,0 +1,245 @@
+mod common;
+
+use async_graphql::{EmptySubscription, Schema};
+use nfsserve::vfs::NFSFileSystem;
+use opendal_mount::{
+    config::{Config, ReloadPlan},
+    errors::OpendalMountError,
+    schema::{Mutation, Query},
+    MultiplexedFs,
+};
+
+const BEFORE: &str = r#"
+    [[mounts]]
+    mount_point = "kept"
+    service = "memory"
+
+    [[mounts]]
+    mount_point = "changed"
+    service = "memory"
+
+    [[mounts]]
+    mount_point = "removed"
+    service = "memory"
+"#;
+
+const AFTER: &str = r#"
+    [[mounts]]
+    mount_point = "kept"
+    service = "memory"
+
+    [[mounts]]
+    mount_point = "changed"
+    service = "memory"
+    options = { read_only = true }
+
+    [[mounts]]
+    mount_point = "added"
+    service = "memory"
+"#;
+
+fn mount_points(mut mount_points: Vec<String>) -> Vec<String> {
+    mount_points.sort();
+    mount_points
+}
+
+#[test]
+fn plan_diffs_declared_mounts() -> anyhow::Result<()> {
+    let plan = ReloadPlan::diff(
+        &Config::parse(BEFORE)?.entries(),
+        &Config::parse(AFTER)?.entries(),
+    );
+
+    assert_eq!(plan.add[0].mount_point, "added");
+    assert_eq!(plan.remove, ["removed"]);
+    assert_eq!(plan.remount[0].mount_point, "changed");
+    assert_eq!(plan.unchanged, ["kept"]);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn reload_leaves_unchanged_mounts_alone() -> anyhow::Result<()> {
+    let fs = MultiplexedFs::new("127.0.0.1", 0).with_root_mount("/tmp/cloud");
+    for entry in Config::parse(BEFORE)?.entries() {
+        fs.mount_declared(entry).await?;
+    }
+
+    let kept = fs.path_to_id(b"/kept").await.unwrap();
+    let changed = fs.path_to_id(b"/changed").await.unwrap();
+
+    let after = Config::parse(AFTER)?.entries();
+
+    fs.reload(&after, true).await;
+    let declared = fs.declared().await;
+    assert_eq!(
+        mount_points(declared.into_iter().map(|e| e.mount_point).collect()),
+        ["changed", "kept", "removed"]
+    );
+
+    let plan = fs.reload(&after, false).await;
+    assert!(!plan.is_empty());
+    assert_eq!(fs.declared().await, {
+        let mut after = after.clone();
+        after.sort_by(|a, b| a.mount_point.cmp(&b.mount_point));
+        after
+    });
+
+    // The unchanged mount kept its file ids, the remounted one did not.
+    assert_eq!(fs.path_to_id(b"/kept").await.unwrap(), kept);
+    assert!(fs.fh_to_id(&fs.id_to_fh(kept)).is_ok());
+    assert!(fs.path_to_id(b"/changed").await.unwrap() != changed);
+
+    assert!(fs.reload(&after, false).await.is_empty());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn reload_mutation_previews_then_applies() -> anyhow::Result<()> {
+    let dir = tempfile::tempdir()?;
+    let config_file = dir.path().join("config.toml");
+    std::fs::write(&config_file, BEFORE)?;
+
+    let fs = MultiplexedFs::new("127.0.0.1", 0)
+        .with_root_mount("/tmp/cloud")
+        .with_config_file(&config_file);
+    for entry in Config::load(&config_file)?.entries() {
+        fs.mount_declared(entry).await?;
+    }
+
+    std::fs::write(&config_file, AFTER)?;
+
+    let schema = Schema::build(Query, Mutation, EmptySubscription)
+        .data(fs.clone())
+        .finish();
+    let reload = |dry_run: bool| {
+        format!(
+            "mutation {{ reload(dryRun: {}) {{ added removed remounted dryRun }} }}",
+            dry_run
+        )
+    };
+
+    let response = schema.execute(reload(true)).await;
+    assert!(response.errors.is_empty());
+    assert_eq!(
+        response.data.into_json()?,
+        serde_json::json!({ "reload": {
+            "added": ["added"],
+            "removed": ["removed"],
+            "remounted": ["changed"],
+            "dryRun": true,
+        } })
+    );
+    assert_eq!(
+        mount_points(
+            fs.declared()
+                .await
+                .into_iter()
+                .map(|e| e.mount_point)
+                .collect()
+        ),
+        ["changed", "kept", "removed"]
+    );
+
+    let response = schema.execute(reload(false)).await;
+    assert!(response.errors.is_empty());
+    assert_eq!(
+        mount_points(
+            fs.declared()
+                .await
+                .into_iter()
+                .map(|e| e.mount_point)
+                .collect()
+        ),
+        ["added", "changed", "kept"]
+    );
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn reload_needs_a_config_file() {
+    let fs = MultiplexedFs::new("127.0.0.1", 0).with_root_mount("/tmp/cloud");
+
+    assert!(matches!(
+        fs.reload_config(true).await,
+        Err(OpendalMountError::NoConfigFile())
+    ));
+}
+
+#[tokio::test]
+async fn failed_remount_keeps_the_current_mount() -> anyhow::Result<()> {
+    let fs = MultiplexedFs::new("127.0.0.1", 0).with_root_mount("/tmp/cloud");
+    for entry in Config::parse(BEFORE)?.entries() {
+        fs.mount_declared(entry).await?;
+    }
+
+    let changed = fs.path_to_id(b"/changed").await.unwrap();
+
+    let broken = Config::parse(
+        r#"
+        [[mounts]]
+        mount_point = "changed"
+        service = "no-such-service"
+    "#,
+    )?
+    .entries();
+
+    let plan = fs.reload(&broken, false).await;
+    assert_eq!(plan.remount[0].mount_point, "changed");
+
+    // Never released, the mount kept its file ids.
+    assert_eq!(fs.path_to_id(b"/changed").await.unwrap(), changed);
+    assert!(fs.fh_to_id(&fs.id_to_fh(changed)).is_ok());
+    assert_eq!(
+        fs.declared().await,
+        vec![Config::parse(BEFORE)?.entries().remove(1)]
+    );
+
+    let failures = fs.restore_failures().await;
+    assert_eq!(failures.len(), 1);
+    assert_eq!(failures[0].mount_point, "changed");
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn declared_failures_are_reported() -> anyhow::Result<()> {
+    let fs = MultiplexedFs::new("127.0.0.1", 0).with_root_mount("/tmp/cloud");
+
+    let broken = Config::parse(
+        r#"
+        [[mounts]]
+        mount_point = "broken"
+        service = "no-such-service"
+    "#,
+    )?
+    .entries();
+    assert!(fs.mount_declared(broken[0].clone()).await.is_err());
+
+    let failures = fs.restore_failures().await;
+    assert_eq!(failures.len(), 1);
+    assert_eq!(failures[0].mount_point, "broken");
+
+    // Retried on reload, and forgotten once mounted.
+    let fixed = Config::parse(
+        r#"
+        [[mounts]]
+        mount_point = "broken"
+        service = "memory"
+    "#,
+    )?
+    .entries();
+    let plan = fs.reload(&fixed, false).await;
+    assert_eq!(plan.add[0].mount_point, "broken");
+    assert!(fs.restore_failures().await.is_empty());
+
+    // Or once no longer declared.
+    fs.reload(&[], false).await;
+    assert!(fs.mount_declared(broken[0].clone()).await.is_err());
+    fs.reload(&[], false).await;
+    assert!(fs.restore_failures().await.is_empty());
+
+    Ok(())
+}
@@ -0,0 +1,138 @@
+mod common;
+
+use common::TestFixture;
+use nfsserve::{
+    nfs::{nfsstat3, sattr3, set_size3},
+    vfs::NFSFileSystem,
+};
+use opendal::{services::Memory, Operator};
+use opendal_mount::OpendalFs;
+
+#[tokio::test]
+async fn sidecar_shows_metadata() -> anyhow::Result<()> {
+    let fixture = TestFixture::new()?;
+    let fs = OpendalFs::new(fixture.base.clone()).with_sidecars(true);
+
+    let (id, _) = fs
+        .create(fs.root_dir(), &b"a.txt".to_vec().into(), sattr3::default())
+        .await
+        .unwrap();
+    fs.write(id, 0, b"hello").await.unwrap();
+
+    let listing = fs.readdir(fs.root_dir(), 0, 10).await.unwrap();
+    let mut names: Vec<String> = listing
+        .entries
+        .iter()
+        .map(|e| String::from_utf8_lossy(&e.name).into_owned())
+        .collect();
+    names.sort();
+    assert_eq!(names, vec![".a.txt.meta.json", "a.txt"]);
+
+    let sidecar = fs
+        .lookup(fs.root_dir(), &b".a.txt.meta.json".to_vec().into())
+        .await
+        .unwrap();
+    let attr = fs.getattr(sidecar).await.unwrap();
+    let (data, _) = fs.read(sidecar, 0, attr.size as u32).await.unwrap();
+
+    let json: serde_json::Value = serde_json::from_slice(&data)?;
+    assert_eq!(json["content_length"], 5);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn sidecar_write_updates_metadata() -> anyhow::Result<()> {
+    let op = Operator::new(Memory::default())?.finish();
+    op.write("a.txt", "hello").await?;
+
+    let fs = OpendalFs::new(op.clone()).with_sidecars(true);
+
+    let sidecar = fs
+        .lookup(fs.root_dir(), &b".a.txt.meta.json".to_vec().into())
+        .await
+        .unwrap();
+
+    // Written in two parts, the metadata being updated once complete.
+    let json = br#"{"content_type": "text/plain; charset=utf-8"}"#;
+    fs.write(sidecar, 0, &json[..10]).await.unwrap();
+    assert_eq!(op.stat("a.txt").await?.content_type(), None);
+
+    fs.write(sidecar, 10, &json[10..]).await.unwrap();
+
+    let meta = op.stat("a.txt").await?;
+    assert_eq!(meta.content_type(), Some("text/plain; charset=utf-8"));
+    assert_eq!(op.read("a.txt").await?.to_vec(), b"hello");
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn sidecar_size_is_capped() -> anyhow::Result<()> {
+    let fixture = TestFixture::new()?;
+    fixture.base.write("a.txt", "hello").await?;
+
+    let fs = OpendalFs::new(fixture.base.clone()).with_sidecars(true);
+
+    let sidecar = fs
+        .lookup(fs.root_dir(), &b".a.txt.meta.json".to_vec().into())
+        .await
+        .unwrap();
+
+    let err = fs.write(sidecar, 1 << 40, b"{}").await.unwrap_err();
+    assert!(matches!(err, nfsstat3::NFS3ERR_FBIG));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn large_files_keep_their_metadata() -> anyhow::Result<()> {
+    let op = Operator::new(Memory::default())?.finish();
+    op.write("a.bin", vec![0; 64 * 1024 * 1024 + 1]).await?;
+
+    let fs = OpendalFs::new(op.clone()).with_sidecars(true);
+
+    let sidecar = fs
+        .lookup(fs.root_dir(), &b".a.bin.meta.json".to_vec().into())
+        .await
+        .unwrap();
+
+    let json = br#"{"content_type": "application/octet-stream"}"#;
+    let err = fs.write(sidecar, 0, json).await.unwrap_err();
+    assert!(matches!(err, nfsstat3::NFS3ERR_FBIG));
+    assert_eq!(op.stat("a.bin").await?.content_type(), None);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn files_are_extended_in_place() -> anyhow::Result<()> {
+    let fixture = TestFixture::new()?;
+    let fs = OpendalFs::new(fixture.base.clone());
+
+    let (id, _) = fs
+        .create(fs.root_dir(), &b"a.txt".to_vec().into(), sattr3::default())
+        .await
+        .unwrap();
+    fs.write(id, 0, b"hello").await.unwrap();
+
+    let grown = sattr3 {
+        size: set_size3::size(3 * 1024 * 1024),
+        ..Default::default()
+    };
+    fs.setattr(id, grown).await.unwrap();
+
+    let data = fixture.base.read("a.txt").await?.to_vec();
+    assert_eq!(data.len(), 3 * 1024 * 1024);
+    assert_eq!(&data[..5], b"hello");
+    assert!(data[5..].iter().all(|b| *b == 0));
+
+    let cut = sattr3 {
+        size: set_size3::size(2),
+        ..Default::default()
+    };
+    fs.setattr(id, cut).await.unwrap();
+    assert_eq!(fixture.base.read("a.txt").await?.to_vec(), b"he");
+
+    Ok(())
+}
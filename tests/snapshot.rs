@@ -0,0 +1,107 @@
+mod common;
+
+use std::{sync::Arc, time::Duration};
+
+use common::TestFixture;
+use nfsserve::{nfs::sattr3, vfs::NFSFileSystem};
+use opendal::{services::Fs, Operator};
+use opendal_mount::{
+    errors::OpendalMountError,
+    schema::{Compression, MountOptions},
+    snapshot, MultiplexedFs,
+};
+
+#[tokio::test]
+async fn snapshot_copies_and_resumes() -> anyhow::Result<()> {
+    let fixture = TestFixture::new()?;
+    fixture.base.write("a.txt", "hello").await?;
+    fixture.base.write("dir/b.txt", "bye").await?;
+
+    let target_root = fixture.root.path().join("target");
+    let target = Operator::new(Fs::default().root(target_root.to_str().unwrap()))?.finish();
+
+    let source = Arc::new(fixture.base.clone());
+
+    let first = snapshot(source.clone(), target.clone(), "snap").await?;
+    assert_eq!(first.files, 2);
+    assert_eq!(first.bytes, 8);
+    assert!(first.failed.is_empty());
+
+    assert_eq!(target.read("snap/a.txt").await?.to_vec(), b"hello");
+    assert_eq!(target.read("snap/dir/b.txt").await?.to_vec(), b"bye");
+
+    fixture.base.write("c.txt", "new").await?;
+
+    let resumed = snapshot(source.clone(), target.clone(), "snap").await?;
+    assert_eq!(resumed.files, 1);
+    assert_eq!(resumed.skipped, 2);
+    assert_eq!(target.read("snap/c.txt").await?.to_vec(), b"new");
+
+    // Changed since copied, with the same size.
+    tokio::time::sleep(Duration::from_millis(10)).await;
+    fixture.base.write("a.txt", "world").await?;
+
+    let resumed = snapshot(source, target.clone(), "snap").await?;
+    assert_eq!(resumed.files, 1);
+    assert_eq!(target.read("snap/a.txt").await?.to_vec(), b"world");
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn snapshot_keeps_stored_content() -> anyhow::Result<()> {
+    let fixture = TestFixture::new()?;
+
+    let target_root = fixture.root.path().join("target");
+    let target = Operator::new(Fs::default().root(target_root.to_str().unwrap()))?.finish();
+
+    let options = MountOptions {
+        compression: Some(Compression::Zstd),
+        ..Default::default()
+    };
+
+    let fs = MultiplexedFs::new("127.0.0.1", 0).with_root_mount("/tmp/cloud");
+    fs.mount_operator("m", fixture.base.clone(), options.clone())
+        .await?;
+
+    let dir = fs.path_to_id(b"/m").await.unwrap();
+    let (id, _) = fs
+        .create(dir, &b"a.txt".to_vec().into(), sattr3::default())
+        .await
+        .unwrap();
+    fs.write(id, 0, b"hello").await.unwrap();
+
+    fs.snapshot("m", target.clone(), Some("snap".to_owned()))
+        .await?;
+
+    // Copied compressed, as stored by the mount.
+    let copied = target.read("snap/a.txt").await?.to_vec();
+    assert_ne!(copied, b"hello");
+    assert_eq!(copied, fixture.base.read("a.txt").await?.to_vec());
+
+    fs.mount_snapshot("s", target, "snap", options).await?;
+
+    let id = fs.path_to_id(b"/s/a.txt").await.unwrap();
+    assert_eq!(fs.read(id, 0, 10).await.unwrap().0, b"hello");
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn snapshots_are_mounted_at_their_prefix() -> anyhow::Result<()> {
+    let fixture = TestFixture::new()?;
+
+    let fs = MultiplexedFs::new("127.0.0.1", 0).with_root_mount("/tmp/cloud");
+    let options = MountOptions {
+        sub_path: Some("data".to_owned()),
+        ..Default::default()
+    };
+
+    let err = fs
+        .mount_snapshot("s", fixture.base.clone(), "snap", options)
+        .await
+        .unwrap_err();
+    assert!(matches!(err, OpendalMountError::InvalidSubPath(_)));
+
+    Ok(())
+}
@@ -0,0 +1,60 @@
+mod common;
+
+use std::sync::Arc;
+
+use common::TestFixture;
+use nfsserve::vfs::NFSFileSystem;
+use opendal_mount::{storage::SubPathStorage, OpendalFs};
+
+#[tokio::test]
+async fn mount_is_confined_to_sub_path() -> anyhow::Result<()> {
+    let fixture = TestFixture::new()?;
+    fixture.base.write("secret.txt", "secret").await?;
+    fixture.base.write("team-a/data/x.txt", "x").await?;
+
+    let storage = SubPathStorage::new(Arc::new(fixture.base.clone()), "team-a/data")?;
+    let fs = OpendalFs::with_storage(Arc::new(storage));
+
+    let listing = fs.readdir(fs.root_dir(), 0, 10).await.unwrap();
+    let names: Vec<String> = listing
+        .entries
+        .iter()
+        .map(|e| String::from_utf8_lossy(&e.name).into_owned())
+        .collect();
+    assert_eq!(names, vec!["x.txt"]);
+
+    let (data, _) = fs.read(listing.entries[0].fileid, 0, 10).await.unwrap();
+    assert_eq!(data, b"x");
+
+    // `..` of the root is the root itself, never the parent prefix.
+    let parent = fs
+        .lookup(fs.root_dir(), &b"..".to_vec().into())
+        .await
+        .unwrap();
+    assert_eq!(parent, fs.root_dir());
+
+    assert!(fs
+        .lookup(parent, &b"secret.txt".to_vec().into())
+        .await
+        .is_err());
+    assert!(fs
+        .lookup(fs.root_dir(), &b"../secret.txt".to_vec().into())
+        .await
+        .is_err());
+
+    let listing = fs.readdir(parent, 0, 10).await.unwrap();
+    assert_eq!(listing.entries.len(), 1);
+    assert_eq!(listing.entries[0].name.0.as_slice(), b"x.txt");
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn sub_path_cannot_escape_root() -> anyhow::Result<()> {
+    let fixture = TestFixture::new()?;
+
+    assert!(SubPathStorage::new(Arc::new(fixture.base.clone()), "team-a/../../etc").is_err());
+    assert!(SubPathStorage::new(Arc::new(fixture.base.clone()), "team-a/../team-b").is_ok());
+
+    Ok(())
+}
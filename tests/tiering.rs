@@ -0,0 +1,114 @@
+mod common;
+
+use std::{collections::HashMap, sync::Arc, time::Duration};
+
+use common::TestFixture;
+use opendal::{services::Fs, Operator};
+use opendal_mount::{
+    schema::{MountOptions, OperatorInput, Tier, TieringOptions},
+    storage::{Storage, TierPolicy, TieredStorage},
+    MultiplexedFs,
+};
+
+fn tiered(fixture: &TestFixture, policy: TierPolicy) -> anyhow::Result<(Operator, TieredStorage)> {
+    let cold_root = fixture.root.path().join("cold");
+    let cold = Operator::new(Fs::default().root(cold_root.to_str().unwrap()))?.finish();
+
+    let storage = TieredStorage::new(
+        Arc::new(fixture.base.clone()),
+        Arc::new(cold.clone()),
+        policy,
+    );
+
+    Ok((cold, storage))
+}
+
+#[tokio::test]
+async fn demotes_over_capacity() -> anyhow::Result<()> {
+    let fixture = TestFixture::new()?;
+    let (cold, storage) = tiered(
+        &fixture,
+        TierPolicy {
+            max_hot_bytes: Some(8),
+            ..Default::default()
+        },
+    )?;
+
+    storage.write("old.txt", b"hello".to_vec()).await?;
+    tokio::time::sleep(Duration::from_millis(10)).await;
+    storage.write("dir/new.txt", b"world".to_vec()).await?;
+
+    assert_eq!(storage.demote().await, 1);
+
+    assert_eq!(storage.tier("old.txt").await?, Tier::Cold);
+    assert_eq!(storage.tier("dir/new.txt").await?, Tier::Hot);
+    assert!(cold.exists("old.txt").await?);
+
+    assert_eq!(storage.read("old.txt", 0..5).await?, b"hello");
+    assert_eq!(storage.tier("old.txt").await?, Tier::Cold);
+
+    let names: Vec<String> = storage
+        .list("/")
+        .await?
+        .iter()
+        .map(|e| e.name().to_owned())
+        .collect();
+    assert!(names.contains(&"old.txt".to_owned()));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn promotes_on_read() -> anyhow::Result<()> {
+    let fixture = TestFixture::new()?;
+    let (cold, storage) = tiered(
+        &fixture,
+        TierPolicy {
+            max_age: Some(Duration::ZERO),
+            promote: true,
+            ..Default::default()
+        },
+    )?;
+
+    storage.write("a.txt", b"hello".to_vec()).await?;
+    tokio::time::sleep(Duration::from_millis(10)).await;
+
+    assert_eq!(storage.demote().await, 1);
+    assert_eq!(storage.tier("a.txt").await?, Tier::Cold);
+
+    assert_eq!(storage.read("a.txt", 0..5).await?, b"hello");
+    assert_eq!(storage.tier("a.txt").await?, Tier::Hot);
+    assert!(!cold.exists("a.txt").await?);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn tier_query_is_relative_to_sub_path() -> anyhow::Result<()> {
+    let fixture = TestFixture::new()?;
+    fixture.base.write("team/a.txt", "hello").await?;
+
+    let cold_root = fixture.root.path().join("cold");
+    let options = MountOptions {
+        sub_path: Some("team".to_owned()),
+        tiering: Some(TieringOptions {
+            cold: OperatorInput {
+                service: "fs".to_owned(),
+                parameters: HashMap::from([("root".to_owned(), cold_root.display().to_string())]),
+            },
+            demote_after_secs: None,
+            max_hot_bytes: None,
+            promote: false,
+        }),
+        ..Default::default()
+    };
+
+    let fs = MultiplexedFs::new("127.0.0.1", 0).with_root_mount("/tmp/cloud");
+    fs.mount_operator("t", fixture.base.clone(), options)
+        .await?;
+
+    assert_eq!(fs.tier("t", "a.txt").await?, Tier::Hot);
+    assert!(fs.tier("t", "../secret.txt").await.is_err());
+
+    Ok(())
+}
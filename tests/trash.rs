@@ -0,0 +1,120 @@
+mod common;
+
+use std::{sync::Arc, time::Duration};
+
+use common::TestFixture;
+use opendal_mount::storage::{Storage, TrashStorage};
+
+#[tokio::test]
+async fn remove_and_restore() -> anyhow::Result<()> {
+    let fixture = TestFixture::new()?;
+    let trash = TrashStorage::new(Arc::new(fixture.base.clone()), Duration::from_secs(3600));
+
+    trash.write("/dir/a.txt", b"hello".to_vec()).await?;
+    trash.delete("/dir/a.txt").await?;
+
+    assert!(!fixture.base.exists("dir/a.txt").await?);
+
+    let items = trash.items().await?;
+    assert_eq!(items.len(), 1);
+    assert_eq!(items[0].path, "dir/a.txt");
+    assert_eq!(items[0].size, 5);
+    assert!(items[0].trash_path.starts_with(".trash/"));
+
+    assert_eq!(trash.restore(&items[0].trash_path).await?, "dir/a.txt");
+    assert_eq!(fixture.base.read("dir/a.txt").await?.to_vec(), b"hello");
+    assert!(trash.items().await?.is_empty());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn overwrite_keeps_previous_content() -> anyhow::Result<()> {
+    let fixture = TestFixture::new()?;
+    let trash = TrashStorage::new(Arc::new(fixture.base.clone()), Duration::from_secs(3600));
+
+    trash.write("a.txt", b"first".to_vec()).await?;
+    trash.write("a.txt", b"second".to_vec()).await?;
+
+    let items = trash.items().await?;
+    assert_eq!(items.len(), 1);
+    assert_eq!(
+        fixture.base.read(&items[0].trash_path).await?.to_vec(),
+        b"first"
+    );
+    assert_eq!(fixture.base.read("a.txt").await?.to_vec(), b"second");
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn purge_expired() -> anyhow::Result<()> {
+    let fixture = TestFixture::new()?;
+    let trash = TrashStorage::new(Arc::new(fixture.base.clone()), Duration::ZERO);
+
+    trash.write("a.txt", b"hello".to_vec()).await?;
+    trash.delete("a.txt").await?;
+    tokio::time::sleep(Duration::from_millis(10)).await;
+
+    assert_eq!(trash.purge().await, 1);
+    assert!(trash.items().await?.is_empty());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn trash_is_hidden_and_confined() -> anyhow::Result<()> {
+    let fixture = TestFixture::new()?;
+    let trash = TrashStorage::new(Arc::new(fixture.base.clone()), Duration::from_secs(3600));
+
+    fixture.base.write("secret.txt", "secret").await?;
+    trash.write("a.txt", b"hello".to_vec()).await?;
+    trash.delete("a.txt").await?;
+
+    let names: Vec<String> = trash
+        .list("/")
+        .await?
+        .iter()
+        .map(|e| e.name().to_owned())
+        .collect();
+    assert!(!names.iter().any(|n| n.starts_with(".trash")));
+    assert!(trash.stat(".trash").await.is_err());
+    assert!(trash.write(".trash/1/b.txt", b"b".to_vec()).await.is_err());
+
+    for trash_path in [".trash/1/../../secret.txt", "../secret.txt", ".trash/1/"] {
+        assert!(trash.restore(trash_path).await.is_err());
+    }
+    assert_eq!(fixture.base.read("secret.txt").await?.to_vec(), b"secret");
+
+    let items = trash.items().await?;
+    assert_eq!(trash.restore(&items[0].trash_path).await?, "a.txt");
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn removals_in_the_same_millisecond_are_kept() -> anyhow::Result<()> {
+    let fixture = TestFixture::new()?;
+    let trash = TrashStorage::new(Arc::new(fixture.base.clone()), Duration::from_secs(3600));
+
+    for content in ["first", "second", "third"] {
+        trash.write("a.txt", content.as_bytes().to_vec()).await?;
+        trash.delete("a.txt").await?;
+    }
+
+    let items = trash.items().await?;
+    assert_eq!(items.len(), 3);
+
+    let mut contents = Vec::new();
+    for item in &items {
+        assert_eq!(item.path, "a.txt");
+        contents.push(fixture.base.read(&item.trash_path).await?.to_vec());
+    }
+    contents.sort();
+    assert_eq!(
+        contents,
+        vec![b"first".to_vec(), b"second".to_vec(), b"third".to_vec()]
+    );
+
+    Ok(())
+}
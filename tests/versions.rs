@@ -0,0 +1,246 @@
+mod common;
+
+use std::{collections::HashMap, ops::Range, sync::Arc};
+
+use async_trait::async_trait;
+use nfsserve::{
+    nfs::{nfsstat3, sattr3},
+    vfs::NFSFileSystem,
+};
+use opendal::{services::Memory, EntryMode, ErrorKind, Metadata, Operator, OperatorInfo};
+use opendal_mount::{
+    storage::{Entry, Storage, WriteMetadata},
+    OpendalFs,
+};
+
+/// Versions of each object, oldest first, as `(id, content)`.
+type Versions = HashMap<String, Vec<(String, Vec<u8>)>>;
+
+/// Memory operator along with the past versions of its objects, the
+/// memory service not keeping any.
+struct VersionedMemory {
+    op: Operator,
+    versions: Arc<Versions>,
+}
+
+/// Objects of a [`VersionedMemory`] at one version.
+struct AtVersion {
+    info: OperatorInfo,
+    version: String,
+    versions: Arc<Versions>,
+}
+
+fn key(path: &str) -> String {
+    path.trim_start_matches('/').to_owned()
+}
+
+fn read_only() -> opendal::Error {
+    opendal::Error::new(ErrorKind::PermissionDenied, "read only")
+}
+
+impl AtVersion {
+    fn content(&self, path: &str) -> opendal::Result<&[u8]> {
+        self.versions
+            .get(&key(path))
+            .and_then(|versions| versions.iter().find(|(id, _)| *id == self.version))
+            .map(|(_, content)| content.as_slice())
+            .ok_or_else(|| opendal::Error::new(ErrorKind::NotFound, "no such version"))
+    }
+}
+
+#[async_trait]
+impl Storage for VersionedMemory {
+    fn info(&self) -> OperatorInfo {
+        self.op.info()
+    }
+
+    async fn stat(&self, path: &str) -> opendal::Result<Metadata> {
+        Storage::stat(&self.op, path).await
+    }
+
+    async fn read(&self, path: &str, range: Range<u64>) -> opendal::Result<Vec<u8>> {
+        Storage::read(&self.op, path, range).await
+    }
+
+    async fn write_with_metadata(
+        &self,
+        path: &str,
+        data: Vec<u8>,
+        metadata: WriteMetadata,
+    ) -> opendal::Result<()> {
+        Storage::write_with_metadata(&self.op, path, data, metadata).await
+    }
+
+    async fn append(&self, path: &str, data: Vec<u8>) -> opendal::Result<()> {
+        Storage::append(&self.op, path, data).await
+    }
+
+    async fn list(&self, path: &str) -> opendal::Result<Vec<Entry>> {
+        Storage::list(&self.op, path).await
+    }
+
+    async fn create_dir(&self, path: &str) -> opendal::Result<()> {
+        Storage::create_dir(&self.op, path).await
+    }
+
+    async fn delete(&self, path: &str) -> opendal::Result<()> {
+        Storage::delete(&self.op, path).await
+    }
+
+    async fn list_versions(&self, path: &str) -> opendal::Result<Vec<Entry>> {
+        let versions = self.versions.get(&key(path)).cloned().unwrap_or_default();
+
+        Ok(versions
+            .iter()
+            .map(|(id, content)| {
+                let mut meta = Metadata::new(EntryMode::FILE);
+                meta.set_version(id);
+                meta.set_content_length(content.len() as u64);
+
+                Entry::new(&key(path), meta)
+            })
+            .collect())
+    }
+
+    fn at_version(&self, version: &str) -> opendal::Result<Arc<dyn Storage>> {
+        Ok(Arc::new(AtVersion {
+            info: self.op.info(),
+            version: version.to_owned(),
+            versions: self.versions.clone(),
+        }))
+    }
+}
+
+#[async_trait]
+impl Storage for AtVersion {
+    fn info(&self) -> OperatorInfo {
+        self.info.clone()
+    }
+
+    async fn stat(&self, path: &str) -> opendal::Result<Metadata> {
+        let mut meta = Metadata::new(EntryMode::FILE);
+        meta.set_content_length(self.content(path)?.len() as u64);
+
+        Ok(meta)
+    }
+
+    async fn read(&self, path: &str, range: Range<u64>) -> opendal::Result<Vec<u8>> {
+        let content = self.content(path)?;
+        let start = (range.start as usize).min(content.len());
+        let end = (range.end as usize).min(content.len());
+
+        Ok(content[start..end].to_vec())
+    }
+
+    async fn write_with_metadata(
+        &self,
+        _path: &str,
+        _data: Vec<u8>,
+        _metadata: WriteMetadata,
+    ) -> opendal::Result<()> {
+        Err(read_only())
+    }
+
+    async fn append(&self, _path: &str, _data: Vec<u8>) -> opendal::Result<()> {
+        Err(read_only())
+    }
+
+    async fn list(&self, _path: &str) -> opendal::Result<Vec<Entry>> {
+        Ok(vec![])
+    }
+
+    async fn create_dir(&self, _path: &str) -> opendal::Result<()> {
+        Err(read_only())
+    }
+
+    async fn delete(&self, _path: &str) -> opendal::Result<()> {
+        Err(read_only())
+    }
+}
+
+async fn versioned_fs() -> anyhow::Result<OpendalFs> {
+    let op = Operator::new(Memory::default())?.finish();
+    op.write("notes.txt", "second").await?;
+
+    let versions = HashMap::from([(
+        "notes.txt".to_owned(),
+        vec![
+            ("v1".to_owned(), b"first".to_vec()),
+            ("v2".to_owned(), b"second".to_vec()),
+        ],
+    )]);
+
+    let storage = VersionedMemory {
+        op,
+        versions: Arc::new(versions),
+    };
+
+    Ok(OpendalFs::with_storage(Arc::new(storage)).with_versions(true))
+}
+
+#[tokio::test]
+async fn versions_are_listed_and_read() -> anyhow::Result<()> {
+    let fs = versioned_fs().await?;
+
+    let versions_dir = fs
+        .lookup(fs.root_dir(), &b".versions".to_vec().into())
+        .await
+        .unwrap();
+    let file = fs
+        .lookup(versions_dir, &b"notes.txt".to_vec().into())
+        .await
+        .unwrap();
+
+    let listing = fs.readdir(file, 0, 10).await.unwrap();
+    let mut names: Vec<String> = listing
+        .entries
+        .iter()
+        .map(|e| String::from_utf8_lossy(e.name.0.as_slice()).into_owned())
+        .collect();
+    names.sort();
+    assert_eq!(names, ["v1", "v2"]);
+
+    let version = fs.lookup(file, &b"v1".to_vec().into()).await.unwrap();
+    let (data, eof) = fs.read(version, 0, 100).await.unwrap();
+    assert_eq!(data, b"first");
+    assert!(eof);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn versions_are_read_only() -> anyhow::Result<()> {
+    let fs = versioned_fs().await?;
+
+    let versions_dir = fs
+        .lookup(fs.root_dir(), &b".versions".to_vec().into())
+        .await
+        .unwrap();
+    let file = fs
+        .lookup(versions_dir, &b"notes.txt".to_vec().into())
+        .await
+        .unwrap();
+    let version = fs.lookup(file, &b"v1".to_vec().into()).await.unwrap();
+
+    let err = fs.write(version, 0, b"changed").await.unwrap_err();
+    assert!(matches!(err, nfsstat3::NFS3ERR_ROFS));
+
+    let err = fs
+        .create(file, &b"v3".to_vec().into(), sattr3::default())
+        .await
+        .unwrap_err();
+    assert!(matches!(err, nfsstat3::NFS3ERR_ROFS));
+
+    let err = fs.remove(file, &b"v1".to_vec().into()).await.unwrap_err();
+    assert!(matches!(err, nfsstat3::NFS3ERR_ROFS));
+
+    // The current content is left alone.
+    let current = fs
+        .lookup(fs.root_dir(), &b"notes.txt".to_vec().into())
+        .await
+        .unwrap();
+    let (data, _) = fs.read(current, 0, 100).await.unwrap();
+    assert_eq!(data, b"second");
+
+    Ok(())
+}